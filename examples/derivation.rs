@@ -0,0 +1,42 @@
+//! Times stepping the iterations up one at a time like the app's slider
+//! does, with the expansions kept between steps and without, run with
+//! `--release`.
+
+use std::time::Instant;
+
+use lily::system::{Derivation, Rules};
+
+const RULES: &str = "\
+A -> F[-A]F[-A]+FA
+F -> FF";
+
+/// The limit the app expands within by default.
+const LIMIT: usize = 10_000_000;
+
+fn main() {
+    let rules = Rules::parse(RULES);
+    let steps = 1..=12;
+
+    // a new derivation every step, expanding from the axiom again
+    let start = Instant::now();
+    for iterations in steps.clone() {
+        let mut derivation = Derivation::new(rules.clone(), "A", 0);
+        derivation.expand(iterations, LIMIT).unwrap();
+    }
+    let fresh = start.elapsed();
+
+    // one derivation kept across the steps, each expands one iteration
+    let start = Instant::now();
+    let mut derivation = Derivation::new(rules.clone(), "A", 0);
+    for iterations in steps.clone() {
+        derivation.expand(iterations, LIMIT).unwrap();
+    }
+    let kept = start.elapsed();
+
+    println!("expanding from the axiom every step: {:?}", fresh);
+    println!("continuing the kept expansions:      {:?}", kept);
+    println!(
+        "{:.1} times faster",
+        fresh.as_secs_f64() / kept.as_secs_f64()
+    );
+}
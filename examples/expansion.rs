@@ -0,0 +1,112 @@
+//! Times expanding the default system to 12 iterations, and a plant with a
+//! dozen rules to 16, run with `--release`.
+//!
+//! Each is also expanded by trying every rule at every symbol, the way rules
+//! were applied before they were bucketed by their first symbol, to show
+//! what the buckets save.
+
+use std::time::{Duration, Instant};
+
+use lily::system::{Pcg32, Rules};
+
+const DEFAULT_RULES: &str = "\
+A -> F[-A]F[-A]+FA
+F -> FF";
+
+const PLANT_RULES: &str = "\
+A -> B[+A]C[-A]D
+B -> BE
+C -> CG
+D -> DH
+E -> F
+G -> F
+H -> F
+I -> J
+J -> K
+K -> L
+L -> M
+M -> I";
+
+/// Applies plain `a -> b` rules by trying every one of them at every symbol,
+/// preferring the longest match.
+fn apply_linear(rules: &[(&str, &str)], input: &str) -> String {
+    let mut output = String::new();
+    let mut index = 0;
+
+    while let Some(c) = input[index..].chars().next() {
+        let matched = (rules.iter())
+            .filter(|(from, _)| input[index..].starts_with(from))
+            .max_by_key(|(from, _)| from.len());
+
+        match matched {
+            Some((from, to)) => {
+                output.push_str(to);
+                index += from.len();
+            }
+            None => {
+                output.push(c);
+                index += c.len_utf8();
+            }
+        }
+    }
+
+    output
+}
+
+/// Expands `axiom` with every rule tried at every symbol, returning the
+/// expansion and how long it took.
+fn time_linear(rules: &str, axiom: &str, iterations: usize) -> (String, Duration) {
+    let rules: Vec<_> = (rules.lines())
+        .filter_map(|line| line.split_once(" -> "))
+        .collect();
+
+    let start = Instant::now();
+    let mut tree = String::from(axiom);
+
+    for _ in 0..iterations {
+        tree = apply_linear(&rules, &tree);
+    }
+
+    (tree, start.elapsed())
+}
+
+/// Expands `axiom` with `rules`, printing how long each iteration took.
+fn time(name: &str, rules: &str, axiom: &str, iterations: usize) {
+    println!("{}:", name);
+
+    let rules_text = rules;
+    let rules = Rules::parse(rules);
+
+    let mut rng = Pcg32::new(0);
+    let mut tree = String::from(axiom);
+    let mut total = Duration::ZERO;
+
+    for iteration in 1..=iterations {
+        let start = Instant::now();
+        tree = rules.apply(&tree, &mut rng);
+        total += start.elapsed();
+
+        println!(
+            "  iteration {}: {} symbols in {:?}",
+            iteration,
+            tree.len(),
+            start.elapsed()
+        );
+    }
+
+    println!("  {:?} in total", total);
+
+    let (linear, elapsed) = time_linear(rules_text, axiom, iterations);
+    assert_eq!(linear, tree, "both ways expand the same");
+
+    println!(
+        "  {:?} trying every rule, {:.1}x the time",
+        elapsed,
+        elapsed.as_secs_f64() / total.as_secs_f64()
+    );
+}
+
+fn main() {
+    time("default system", DEFAULT_RULES, "A", 12);
+    time("plant", PLANT_RULES, "A", 16);
+}
@@ -0,0 +1,55 @@
+//! Extends the turtle with a custom instruction drawing a spiral.
+
+use lily::system::{
+    self, Instruction, Instructions, Orientation, Pcg32, Rules, Segment, SystemOptions,
+    TurtleBackend, TurtleState,
+};
+
+/// Draws a shrinking spiral, leaving the turtle at its end.
+fn spiral(state: &mut TurtleState, backend: &mut dyn TurtleBackend) {
+    let mut length = 4.0;
+
+    for _ in 0..24 {
+        let from = state.position;
+        state.position += state.heading() * length * state.scale;
+
+        backend.segment(&Segment {
+            from: from.to_point(),
+            to: state.position.to_point(),
+            depth: [from.depth(), state.position.depth()],
+            left: state.left().to_vector(),
+            width: state.width,
+            end_width: state.width,
+            color: state.color,
+        });
+
+        state.orientation = state.orientation * Orientation::yaw(30.0);
+        length *= 0.9;
+    }
+}
+
+fn main() {
+    let rules = Rules::parse("A -> F[+A][-A]S");
+
+    let mut instructions = Instructions::parse("F = forward 10\n+ = turn 30\n- = turn -30");
+    instructions.insert('[', Instruction::Push);
+    instructions.insert(']', Instruction::Pop);
+    instructions.insert_custom('S', spiral);
+
+    let mut rng = Pcg32::new(0);
+    let mut tree = String::from("A");
+
+    for _ in 0..4 {
+        tree = rules.apply(&tree, &mut rng);
+    }
+
+    let options = SystemOptions::default();
+    let stream = instructions.apply(&tree);
+    let mesh = system::generate_mesh_with(&options, &stream, instructions.customs());
+
+    println!(
+        "{} vertices, {} triangles",
+        mesh.vertices.len(),
+        mesh.indices.len() / 3
+    );
+}
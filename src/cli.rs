@@ -0,0 +1,1857 @@
+//! The command line interface, used when lily is started with arguments.
+
+use std::{
+    collections::BTreeMap,
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, Subcommand};
+use lily::{
+    diagnostics::{self, Diagnostic, DiagnosticOptions, Section, Severity},
+    error::Result,
+    export::{
+        self, ContactSheetOptions, GcodeUnits, PdfBackend, PdfOptions, PenControl, Plot,
+        PlotterOptions, PngOptions, PolylineBackend, SvgAnimation, SvgBackend,
+    },
+    project::{Definition, Project, ProjectFormat, SourceLines},
+    system::{self, GenerationStats, Growth},
+    LilyError,
+};
+use notify::{RecursiveMode, Watcher};
+use ori::prelude::{Mesh, Size};
+use serde::{Deserialize, Serialize};
+
+use crate::file_watch;
+
+#[derive(Parser)]
+#[command(name = "lily", about = "A playground for L-systems")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Open a project file or share code in the app.
+    Open {
+        /// A path to a project, a share code, or `-` to read a project from stdin.
+        input: String,
+    },
+
+    /// Render a file to an image.
+    Render {
+        /// A project, plain text definition or Fractint `.l` file, `-` reads stdin.
+        input: PathBuf,
+
+        /// The output path, `-` writes to stdout.
+        #[arg(short, long, default_value = "-")]
+        out: PathBuf,
+
+        /// Defaults to the extension of the output path, or SVG on stdout.
+        #[arg(short, long)]
+        format: Option<Format>,
+
+        /// The longest side of the image.
+        #[arg(short, long, default_value_t = 1024)]
+        size: u32,
+
+        /// With `--format animated-svg`, the seconds it takes to show every iteration.
+        #[arg(long, default_value_t = SvgAnimation::default().duration)]
+        duration: f32,
+
+        /// With `--format animated-svg`, stop on the last iteration instead of repeating.
+        #[arg(long)]
+        once: bool,
+
+        /// Stop with an error when an iteration grows past this many symbols.
+        #[arg(long, default_value_t = system::DEFAULT_SYMBOL_LIMIT)]
+        max_symbols: usize,
+
+        /// For PDF and plotters, `a4`, `a5`, `a3`, `letter` or `<WIDTH>x<HEIGHT>` in millimeters.
+        #[arg(long, default_value = "a4", value_parser = parse_page_size)]
+        page_size: Size,
+
+        /// For PDF and plotters, the space left free on every side, in millimeters.
+        #[arg(long, default_value_t = PdfOptions::default().margin)]
+        margin: f32,
+
+        /// For plotters, the pen speed while drawing, in millimeters per minute.
+        #[arg(long, default_value_t = PlotterOptions::default().feed_rate)]
+        feed_rate: f32,
+
+        /// For plotters, millimeters per turtle unit, instead of fitting the page.
+        #[arg(long)]
+        scale: Option<f32>,
+
+        /// With `--format gcode`, the units the coordinates are written in.
+        #[arg(long, value_enum, default_value_t = Units::Mm)]
+        units: Units,
+
+        /// With `--format gcode`, the height the pen is lifted to, in millimeters.
+        #[arg(long, default_value_t = 5.0, allow_negative_numbers = true)]
+        pen_up: f32,
+
+        /// With `--format gcode`, the height the pen draws at, in millimeters.
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        pen_down: f32,
+    },
+
+    /// Render every entry of a manifest file.
+    Batch {
+        manifest: PathBuf,
+
+        /// Number of entries rendered at once, defaults to the number of cores.
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Lay every entry out in one labeled grid instead, an SVG or PNG.
+        #[arg(long)]
+        contact_sheet: Option<PathBuf>,
+
+        /// The number of cells in each row of the contact sheet.
+        #[arg(long, default_value_t = ContactSheetOptions::default().columns, requires = "contact_sheet")]
+        columns: usize,
+
+        /// The width and height of each cell of the contact sheet, in pixels.
+        #[arg(long, default_value_t = ContactSheetOptions::default().cell_size, requires = "contact_sheet")]
+        cell_size: f32,
+
+        /// The height of the labels on the contact sheet, in pixels.
+        #[arg(long, default_value_t = ContactSheetOptions::default().label_size, requires = "contact_sheet")]
+        label_size: f32,
+
+        /// An entry fails when an iteration grows past this many symbols.
+        #[arg(long, default_value_t = system::DEFAULT_SYMBOL_LIMIT)]
+        max_symbols: usize,
+    },
+
+    /// Check definition files for mistakes, exiting with an error if any are found.
+    Validate {
+        /// Projects, plain text definitions or Fractint `.l` files, `-` reads stdin.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// The number of symbols a system may expand to.
+        #[arg(long, default_value_t = DiagnosticOptions::default().max_symbols)]
+        max_symbols: usize,
+
+        /// Print the findings as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the string a system expands to.
+    Expand {
+        /// A project, plain text definition or Fractint `.l` file, `-` reads stdin.
+        input: PathBuf,
+
+        /// Overrides the iterations of the definition.
+        #[arg(short = 'n', long)]
+        iterations: Option<usize>,
+
+        /// Print every iteration up to `-n` on its own line, starting with the axiom.
+        #[arg(long)]
+        all: bool,
+
+        /// With `--all`, write each iteration to `<DIR>/<iteration>.txt` instead.
+        #[arg(long, requires = "all")]
+        out_dir: Option<PathBuf>,
+
+        /// Print only the number of symbols.
+        #[arg(long)]
+        count_only: bool,
+
+        /// Stop with an error when an iteration grows past this many symbols.
+        #[arg(long, default_value_t = system::DEFAULT_SYMBOL_LIMIT)]
+        max_symbols: usize,
+    },
+
+    /// Print measurements of a system.
+    Stats {
+        /// A project, plain text definition or Fractint `.l` file, `-` reads stdin.
+        input: PathBuf,
+
+        /// Overrides the iterations of the definition.
+        #[arg(short = 'n', long)]
+        iterations: Option<usize>,
+
+        /// Print the measurements as JSON.
+        #[arg(long)]
+        json: bool,
+
+        /// Don't generate the mesh, which is faster but leaves out its size.
+        #[arg(long)]
+        skip_mesh: bool,
+
+        /// Stop with an error when an iteration grows past this many symbols.
+        #[arg(long, default_value_t = system::DEFAULT_SYMBOL_LIMIT)]
+        max_symbols: usize,
+    },
+
+    /// Render a file, and render it again every time it changes.
+    Watch {
+        input: PathBuf,
+
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// Defaults to the extension of the output path.
+        #[arg(short, long)]
+        format: Option<Format>,
+
+        /// The longest side of the image.
+        #[arg(short, long, default_value_t = 1024)]
+        size: u32,
+    },
+
+    /// Convert definitions between formats.
+    Convert {
+        /// Projects, plain text definitions or Fractint `.l` files, `-` reads stdin.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Defaults to the extension of the output path.
+        #[arg(short, long)]
+        to: Option<ConvertFormat>,
+
+        /// The output file for a single system, defaults to stdout.
+        #[arg(short, long, conflicts_with = "out_dir")]
+        output: Option<PathBuf>,
+
+        /// Write every system to `<DIR>/<name>.<format>`.
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+
+        /// Convert even when the format can't hold everything, dropping the rest.
+        #[arg(long)]
+        lossy: bool,
+    },
+}
+
+/// What `main` should do after the command line has been handled.
+pub enum Action {
+    Exit(ExitCode),
+    /// Launch the app with a project loaded, and the file it came from.
+    Launch(Box<Project>, Option<PathBuf>),
+}
+
+pub fn run() -> Action {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Open { input } => {
+            open(&input).map(|(project, path)| Action::Launch(Box::new(project), path))
+        }
+        Command::Render {
+            input,
+            out,
+            format,
+            size,
+            duration,
+            once,
+            max_symbols,
+            page_size,
+            margin,
+            feed_rate,
+            scale,
+            units,
+            pen_up,
+            pen_down,
+        } => {
+            let options = RenderOptions {
+                size,
+                animation: SvgAnimation {
+                    duration,
+                    repeat: !once,
+                },
+                max_symbols,
+                pdf: PdfOptions {
+                    page: page_size,
+                    margin,
+                    ..Default::default()
+                },
+                plotter: PlotterOptions {
+                    paper: page_size,
+                    margin,
+                    scale,
+                    pen: PenControl::Z {
+                        up: pen_up,
+                        down: pen_down,
+                    },
+                    feed_rate,
+                    units: units.into(),
+                    ..Default::default()
+                },
+            };
+
+            render_file(&input, &out, format, &options).map(|()| Action::Exit(ExitCode::SUCCESS))
+        }
+        Command::Batch {
+            manifest,
+            jobs,
+            contact_sheet,
+            columns,
+            cell_size,
+            label_size,
+            max_symbols,
+        } => {
+            let sheet = contact_sheet.map(|path| ContactSheet {
+                path,
+                options: ContactSheetOptions {
+                    columns,
+                    cell_size,
+                    label_size,
+                    ..Default::default()
+                },
+            });
+
+            batch(&manifest, jobs, sheet.as_ref(), max_symbols).map(Action::Exit)
+        }
+        Command::Validate {
+            paths,
+            max_symbols,
+            json,
+        } => {
+            let options = DiagnosticOptions {
+                max_symbols,
+                ..Default::default()
+            };
+
+            Ok(Action::Exit(validate(&paths, &options, json)))
+        }
+        Command::Expand {
+            input,
+            iterations,
+            all,
+            out_dir,
+            count_only,
+            max_symbols,
+        } => {
+            let options = ExpandOptions {
+                all,
+                out_dir,
+                count_only,
+                max_symbols,
+            };
+
+            expand(&input, iterations, &options).map(|()| Action::Exit(ExitCode::SUCCESS))
+        }
+        Command::Stats {
+            input,
+            iterations,
+            json,
+            skip_mesh,
+            max_symbols,
+        } => {
+            let options = StatsOptions {
+                json,
+                skip_mesh,
+                max_symbols,
+            };
+
+            stats(&input, iterations, &options).map(|()| Action::Exit(ExitCode::SUCCESS))
+        }
+        Command::Watch {
+            input,
+            out,
+            format,
+            size,
+        } => {
+            let options = RenderOptions {
+                size,
+                ..Default::default()
+            };
+
+            watch(&input, &out, format, &options).map(Action::Exit)
+        }
+        Command::Convert {
+            inputs,
+            to,
+            output,
+            out_dir,
+            lossy,
+        } => {
+            let options = ConvertOptions {
+                to: to.map(ProjectFormat::from),
+                output,
+                out_dir,
+                lossy,
+            };
+
+            convert(&inputs, &options).map(Action::Exit)
+        }
+    };
+
+    match result {
+        Ok(action) => action,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Action::Exit(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Whether `path` is `-`, standing in for stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// The name of `path` in messages.
+fn display(path: &Path) -> String {
+    match is_stdio(path) {
+        true => String::from("<stdin>"),
+        false => path.display().to_string(),
+    }
+}
+
+/// Reads a file, or stdin for `-`.
+fn read_input(path: &Path) -> Result<String> {
+    if !is_stdio(path) {
+        return std::fs::read_to_string(path).map_err(|err| LilyError::io(path, err));
+    }
+
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|err| LilyError::io("<stdin>", err))?;
+
+    Ok(text)
+}
+
+/// Writes a file, or stdout for `-`.
+fn write_output(path: &Path, binary: bool, bytes: &[u8]) -> Result<()> {
+    if !is_stdio(path) {
+        return std::fs::write(path, bytes).map_err(|err| LilyError::io(path, err));
+    }
+
+    let mut stdout = std::io::stdout().lock();
+
+    if binary && stdout.is_terminal() {
+        return Err(LilyError::Project(String::from(
+            "refusing to write a binary format to a terminal, use --out or a pipe",
+        )));
+    }
+
+    match stdout.write_all(bytes).and_then(|()| stdout.flush()) {
+        // the reader having enough, like `head`, isn't an error
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => {
+            Err(LilyError::io("<stdout>", err))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Opens a file, stdin or a share code, along with the file to watch.
+fn open(input: &str) -> Result<(Project, Option<PathBuf>)> {
+    let path = Path::new(input);
+
+    if is_stdio(path) {
+        let text = read_input(path)?;
+        return Ok((Project::parse(&text, ProjectFormat::detect(&text))?, None));
+    }
+
+    match path.exists() {
+        true => Ok((Project::load(path)?, Some(path.to_path_buf()))),
+        false => Ok((system::decode_share(input)?, None)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Svg,
+    /// A PNG of the mesh, rendered in strips so any size fits in memory.
+    Png,
+    /// G-code for pen plotters, laid out on A4 paper unless `--page-size` says otherwise.
+    Gcode,
+    /// HPGL for pen plotters, laid out like G-code.
+    Hpgl,
+    /// A vector PDF, on an A4 page unless `--page-size` says otherwise.
+    Pdf,
+    /// An SVG showing every iteration in turn.
+    #[serde(rename = "animated-svg")]
+    AnimatedSvg,
+    /// The mesh as Wavefront OBJ, for 3D tools.
+    Obj,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "svg" => Some(Self::Svg),
+            "png" => Some(Self::Png),
+            "gcode" | "nc" | "ngc" => Some(Self::Gcode),
+            "hpgl" | "plt" => Some(Self::Hpgl),
+            "pdf" => Some(Self::Pdf),
+            "obj" => Some(Self::Obj),
+            _ => None,
+        }
+    }
+
+    /// Picks the format for `output`, from `format` or the extension of the
+    /// path, text formats are written to stdout by default.
+    fn pick(format: Option<Self>, output: &Path) -> Result<Self> {
+        if is_stdio(output) {
+            return Ok(format.unwrap_or_default());
+        }
+
+        let format = format.or_else(|| Self::from_path(output));
+        format.ok_or_else(|| LilyError::Project(String::from("unknown output format")))
+    }
+
+    /// Binary formats aren't written to a terminal.
+    fn is_binary(self) -> bool {
+        match self {
+            Self::Svg | Self::AnimatedSvg | Self::Gcode | Self::Hpgl | Self::Obj => false,
+            Self::Png | Self::Pdf => true,
+        }
+    }
+}
+
+struct RenderOptions {
+    /// The longest side of the image.
+    size: u32,
+    animation: SvgAnimation,
+    /// Expansions growing past this many symbols fail.
+    max_symbols: usize,
+    pdf: PdfOptions,
+    plotter: PlotterOptions,
+}
+
+/// The units of `--units`, see [`GcodeUnits`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Units {
+    Mm,
+    Inches,
+}
+
+impl From<Units> for GcodeUnits {
+    fn from(units: Units) -> Self {
+        match units {
+            Units::Mm => Self::Millimeters,
+            Units::Inches => Self::Inches,
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            animation: SvgAnimation::default(),
+            max_symbols: system::DEFAULT_SYMBOL_LIMIT,
+            pdf: PdfOptions::default(),
+            plotter: PlotterOptions::default(),
+        }
+    }
+}
+
+/// Reads a paper size for `--page-size`, by name or as `<WIDTH>x<HEIGHT>` in
+/// millimeters.
+fn parse_page_size(text: &str) -> Result<Size, String> {
+    match text.to_lowercase().as_str() {
+        "a3" => return Ok(PlotterOptions::A3),
+        "a4" => return Ok(PdfOptions::A4),
+        "a5" => return Ok(PdfOptions::A5),
+        "letter" => return Ok(PlotterOptions::LETTER),
+        _ => {}
+    }
+
+    let invalid = || format!("`{}` is not a4, a5, a3, letter or <WIDTH>x<HEIGHT>", text);
+
+    let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+    let width: f32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: f32 = height.trim().parse().map_err(|_| invalid())?;
+
+    match width > 0.0 && height > 0.0 {
+        true => Ok(Size::new(width, height)),
+        false => Err(format!("`{}` has to be wider and taller than 0", text)),
+    }
+}
+
+/// Renders a definition headlessly.
+///
+/// Plotter formats and PDF are laid out on paper instead, ignoring the size.
+fn render(definition: &Definition, format: Format, options: &RenderOptions) -> Result<Vec<u8>> {
+    let stream = definition.try_interpret(options.max_symbols)?;
+
+    let bytes = match format {
+        Format::Svg => {
+            let mut backend = SvgBackend::new();
+            system::interpret(
+                &definition.options,
+                &stream,
+                &Default::default(),
+                &mut backend,
+            );
+
+            backend.finish(options.size as f32).into_bytes()
+        }
+        Format::AnimatedSvg => {
+            let rules = definition.rules();
+            let instructions = definition.instructions();
+
+            let expansions = (rules.expansions(&definition.axiom))
+                .with_limit(options.max_symbols)
+                .with_seed(definition.seed);
+            let frames = (expansions.take(definition.iterations + 1))
+                .map(|tree| {
+                    let mut backend = SvgBackend::new();
+                    system::interpret(
+                        &definition.options,
+                        &instructions.apply(&tree?),
+                        instructions.customs(),
+                        &mut backend,
+                    );
+
+                    Ok(backend)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            export::animated_svg(&frames, options.size as f32, &options.animation).into_bytes()
+        }
+        Format::Png => {
+            let mesh = system::generate_mesh(&definition.options, &stream);
+
+            let options = PngOptions {
+                size: options.size,
+                ..Default::default()
+            };
+
+            let mut png = Vec::new();
+            // writing into memory can't fail, and every mesh can be encoded
+            export::export_png(&mesh, &options, &mut png).unwrap();
+            png
+        }
+        Format::Obj => {
+            let mesh = system::generate_mesh(&definition.options, &stream);
+            export::mesh_to_obj(&mesh, &definition.axiom).into_bytes()
+        }
+        Format::Pdf => {
+            let mut backend = PdfBackend::new();
+            system::interpret(
+                &definition.options,
+                &stream,
+                &Default::default(),
+                &mut backend,
+            );
+
+            backend.finish(&options.pdf)
+        }
+        Format::Gcode | Format::Hpgl => {
+            let mut backend = PolylineBackend::new();
+            system::interpret(
+                &definition.options,
+                &stream,
+                &Default::default(),
+                &mut backend,
+            );
+
+            let plot = Plot::new(&backend.finish(), &options.plotter);
+            eprintln!(
+                "pen-up travel: {:.0} mm, {:.0} mm in drawing order",
+                plot.travel, plot.unoptimized_travel
+            );
+
+            match format {
+                Format::Gcode => plot.to_gcode().into_bytes(),
+                _ => plot.to_hpgl().into_bytes(),
+            }
+        }
+    };
+
+    Ok(bytes)
+}
+
+/// Renders the first system in `input` for `lily render`.
+fn render_file(
+    input: &Path,
+    output: &Path,
+    format: Option<Format>,
+    options: &RenderOptions,
+) -> Result<()> {
+    let format = Format::pick(format, output)?;
+
+    let source = sources(input)?.into_iter().next();
+    let source = source.ok_or_else(|| LilyError::Project(String::from("no systems found")))?;
+
+    write_output(
+        output,
+        format.is_binary(),
+        &render(&source.definition, format, options)?,
+    )
+}
+
+/// A manifest lists the images rendered by `lily batch`.
+///
+/// ```toml
+/// [[entry]]
+/// project = "tree.json"
+/// seeds = [1, 2, 3]
+/// output = "out/tree-{seed}.svg"
+///
+/// [[entry]]
+/// output = "koch.svg"
+/// size = 2048
+///
+/// [entry.definition]
+/// axiom = "F"
+/// rules = "F -> F+F-F-F+F"
+/// instructions = "F = forward 1\n+ = turn 90\n- = turn -90"
+/// iterations = 4
+/// ```
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default, rename = "entry")]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    /// A project file, relative to the manifest.
+    project: Option<PathBuf>,
+    /// An inline definition, used when there's no project.
+    definition: Option<Definition>,
+    iterations: Option<usize>,
+    /// Renders one output per seed, replacing `{seed}` in the output path.
+    #[serde(default)]
+    seeds: Vec<u64>,
+    /// The output path, relative to the manifest.
+    output: String,
+    /// Defaults to the extension of the output path.
+    format: Option<Format>,
+    #[serde(default = "Entry::default_size")]
+    size: u32,
+}
+
+impl Entry {
+    fn default_size() -> u32 {
+        1024
+    }
+
+    fn definition(&self, base: &Path) -> Result<Definition> {
+        let mut definition = match (&self.project, &self.definition) {
+            (Some(project), _) => Project::load(base.join(project))?.definition,
+            (None, Some(definition)) => definition.clone(),
+            (None, None) => {
+                return Err(LilyError::Project(String::from(
+                    "entry has neither a project nor a definition",
+                )))
+            }
+        };
+
+        if let Some(iterations) = self.iterations {
+            definition.iterations = iterations;
+        }
+
+        Ok(definition)
+    }
+}
+
+/// A single image to render.
+struct Job {
+    definition: Definition,
+    output: PathBuf,
+    format: Format,
+    options: RenderOptions,
+}
+
+fn jobs(
+    manifest: &Manifest,
+    base: &Path,
+    max_symbols: usize,
+) -> Vec<Result<Job, (String, LilyError)>> {
+    let mut jobs = Vec::new();
+
+    for entry in manifest.entries.iter() {
+        let definition = match entry.definition(base) {
+            Ok(definition) => definition,
+            Err(err) => {
+                jobs.push(Err((entry.output.clone(), err)));
+                continue;
+            }
+        };
+
+        let seeds = match entry.seeds.is_empty() {
+            true => vec![definition.seed],
+            false => entry.seeds.clone(),
+        };
+
+        for seed in seeds {
+            let output = base.join(entry.output.replace("{seed}", &seed.to_string()));
+            let format = entry.format.or_else(|| Format::from_path(&output));
+
+            let Some(format) = format else {
+                let err = LilyError::Project(String::from("unknown output format"));
+                jobs.push(Err((output.display().to_string(), err)));
+                continue;
+            };
+
+            jobs.push(Ok(Job {
+                definition: Definition {
+                    seed,
+                    ..definition.clone()
+                },
+                output,
+                format,
+                options: RenderOptions {
+                    size: entry.size,
+                    max_symbols,
+                    ..Default::default()
+                },
+            }));
+        }
+    }
+
+    jobs
+}
+
+fn run_job(job: &Job) -> Result<(), String> {
+    let bytes = render(&job.definition, job.format, &job.options).map_err(|err| err.to_string())?;
+
+    if let Some(parent) = job.output.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| LilyError::io(parent, err).to_string())?;
+    }
+
+    std::fs::write(&job.output, bytes).map_err(|err| LilyError::io(&job.output, err).to_string())
+}
+
+/// Where `lily batch --contact-sheet` writes its sheet, and how.
+struct ContactSheet {
+    path: PathBuf,
+    options: ContactSheetOptions,
+}
+
+/// The drawing of an entry on a contact sheet.
+enum Cell {
+    Svg(SvgBackend),
+    Mesh(Mesh, Growth),
+}
+
+impl Cell {
+    fn new(definition: &Definition, format: Format, max_symbols: usize) -> Result<Self> {
+        let stream = definition.try_interpret(max_symbols)?;
+
+        let cell = match format {
+            Format::Png => Cell::Mesh(
+                system::generate_mesh(&definition.options, &stream),
+                definition.options.growth,
+            ),
+            _ => {
+                let mut backend = SvgBackend::new();
+                system::interpret(
+                    &definition.options,
+                    &stream,
+                    &Default::default(),
+                    &mut backend,
+                );
+
+                Cell::Svg(backend)
+            }
+        };
+
+        Ok(cell)
+    }
+}
+
+/// Writes the rendered entries into one sheet, labeled by their output names.
+fn write_contact_sheet(
+    sheet: &ContactSheet,
+    format: Format,
+    cells: Vec<(String, Cell)>,
+) -> Result<()> {
+    let bytes = match format {
+        Format::Png => {
+            let meshes: Vec<_> = (cells.into_iter())
+                .filter_map(|(name, cell)| match cell {
+                    Cell::Mesh(mesh, growth) => Some((name, mesh, growth)),
+                    Cell::Svg(_) => None,
+                })
+                .collect();
+
+            let mut png = Vec::new();
+            export::contact_sheet_png(&meshes, &sheet.options, &mut png)?;
+            png
+        }
+        _ => {
+            let backends: Vec<_> = (cells.into_iter())
+                .filter_map(|(name, cell)| match cell {
+                    Cell::Svg(backend) => Some((name, backend)),
+                    Cell::Mesh(..) => None,
+                })
+                .collect();
+
+            export::contact_sheet_svg(&backends, &sheet.options).into_bytes()
+        }
+    };
+
+    std::fs::write(&sheet.path, bytes).map_err(|err| LilyError::io(&sheet.path, err))
+}
+
+fn batch(
+    path: &Path,
+    jobs: Option<usize>,
+    sheet: Option<&ContactSheet>,
+    max_symbols: usize,
+) -> Result<ExitCode> {
+    let text = std::fs::read_to_string(path).map_err(|err| LilyError::io(path, err))?;
+    let manifest: Manifest =
+        toml::from_str(&text).map_err(|err| LilyError::Project(err.to_string()))?;
+
+    let sheet_format = match sheet {
+        Some(sheet) => match Format::pick(None, &sheet.path)? {
+            format @ (Format::Svg | Format::Png) => Some(format),
+            _ => {
+                return Err(LilyError::Project(String::from(
+                    "contact sheets are written as SVG or PNG",
+                )))
+            }
+        },
+        None => None,
+    };
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let queue = self::jobs(&manifest, base, max_symbols);
+    let cells = Mutex::new(Vec::new());
+
+    let threads = jobs
+        .or_else(|| thread::available_parallelism().ok().map(Into::into))
+        .unwrap_or(1)
+        .max(1);
+
+    let next = AtomicUsize::new(0);
+    let done = Mutex::new(0);
+    let failed = AtomicBool::new(false);
+
+    // a failing entry is reported and the rest still run
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+
+                let Some(job) = queue.get(index) else {
+                    break;
+                };
+
+                let start = Instant::now();
+                let (name, result) = match (job, sheet_format) {
+                    (Ok(job), None) => (job.output.display().to_string(), run_job(job)),
+                    (Ok(job), Some(format)) => {
+                        let name = job.output.display().to_string();
+
+                        match Cell::new(&job.definition, format, max_symbols) {
+                            Ok(cell) => {
+                                let label = label(&job.output);
+                                cells.lock().unwrap().push((index, label, cell));
+                                (name, Ok(()))
+                            }
+                            Err(err) => (name, Err(err.to_string())),
+                        }
+                    }
+                    (Err((name, err)), _) => (name.clone(), Err(err.to_string())),
+                };
+
+                let mut done = done.lock().unwrap();
+                *done += 1;
+
+                match result {
+                    Ok(()) => println!(
+                        "[{}/{}] {} ({} ms)",
+                        done,
+                        queue.len(),
+                        name,
+                        start.elapsed().as_millis()
+                    ),
+                    Err(err) => {
+                        failed.store(true, Ordering::Relaxed);
+                        eprintln!("[{}/{}] {} failed: {}", done, queue.len(), name, err);
+                    }
+                }
+            });
+        }
+    });
+
+    if let (Some(sheet), Some(format)) = (sheet, sheet_format) {
+        // the cells finish in any order, the sheet follows the manifest
+        let mut cells = cells.into_inner().unwrap();
+        cells.sort_by_key(|(index, _, _)| *index);
+
+        let cells = cells
+            .into_iter()
+            .map(|(_, name, cell)| (name, cell))
+            .collect();
+        write_contact_sheet(sheet, format, cells)?;
+        println!("wrote {}", sheet.path.display());
+    }
+
+    match failed.into_inner() {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// The name of an entry on a contact sheet, the name of its output.
+fn label(output: &Path) -> String {
+    match output.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => output.display().to_string(),
+    }
+}
+
+/// A definition read from a file by `lily validate`.
+struct Source {
+    /// The name of the system, for files holding more than one.
+    name: Option<String>,
+    definition: Definition,
+    /// Where the lines came from, for plain text files.
+    lines: Option<SourceLines>,
+}
+
+fn sources(path: &Path) -> Result<Vec<Source>> {
+    let text = read_input(path)?;
+
+    if path.extension().is_some_and(|extension| extension == "l") {
+        let systems = system::import_fractint(&text)?;
+
+        return Ok(systems
+            .into_iter()
+            .map(|system| Source {
+                name: Some(system.name),
+                definition: system.definition,
+                lines: None,
+            })
+            .collect());
+    }
+
+    let format = ProjectFormat::from_path(path).unwrap_or_else(|| ProjectFormat::detect(&text));
+
+    let source = match format {
+        ProjectFormat::Text => {
+            let (definition, lines) = Definition::parse_text_with_lines(&text);
+
+            Source {
+                name: None,
+                definition,
+                lines: Some(lines),
+            }
+        }
+        format => Source {
+            name: None,
+            definition: Project::parse(&text, format)?.definition,
+            lines: None,
+        },
+    };
+
+    Ok(vec![source])
+}
+
+/// A finding of `lily validate`, as printed by `--json`.
+#[derive(Serialize)]
+struct Finding {
+    file: String,
+    system: Option<String>,
+    /// The line in the file, when it's known.
+    line: Option<usize>,
+    severity: Severity,
+    section: Option<Section>,
+    /// The line within the section.
+    section_line: Option<usize>,
+    message: String,
+}
+
+impl Finding {
+    fn new(path: &Path, source: &Source, diagnostic: Diagnostic) -> Self {
+        let line = source
+            .lines
+            .as_ref()
+            .zip(diagnostic.line)
+            .and_then(|(lines, line)| match diagnostic.section {
+                Section::Rules => lines.rule(line),
+                Section::Instructions => lines.instruction(line),
+                _ => None,
+            });
+
+        Self {
+            file: display(path),
+            system: source.name.clone(),
+            line,
+            severity: diagnostic.severity,
+            section: Some(diagnostic.section),
+            section_line: diagnostic.line,
+            message: diagnostic.message,
+        }
+    }
+
+    /// A file that couldn't be read is an error of its own.
+    fn load_error(path: &Path, err: LilyError) -> Self {
+        let message = match err {
+            // the path is already part of the finding
+            LilyError::Io { ref source, .. } => source.to_string(),
+            ref err => err.to_string(),
+        };
+
+        Self {
+            file: display(path),
+            system: None,
+            line: err.line(),
+            severity: Severity::Error,
+            section: None,
+            section_line: None,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.file)?;
+
+        if let Some(ref system) = self.system {
+            write!(f, " ({})", system)?;
+        }
+
+        if let Some(line) = self.line {
+            return write!(f, ":{}: {}: {}", line, self.severity, self.message);
+        }
+
+        write!(f, ": {}: ", self.severity)?;
+
+        match (self.section, self.section_line) {
+            (Some(section), Some(line)) => write!(f, "{} line {}: ", section, line)?,
+            (Some(section), None) => write!(f, "{}: ", section)?,
+            _ => {}
+        }
+
+        f.write_str(&self.message)
+    }
+}
+
+fn validate(paths: &[PathBuf], options: &DiagnosticOptions, json: bool) -> ExitCode {
+    let mut findings = Vec::new();
+
+    for path in paths {
+        match sources(path) {
+            Ok(sources) => {
+                for source in sources.iter() {
+                    let diagnostics = diagnostics::diagnose(&source.definition, options);
+
+                    for diagnostic in diagnostics {
+                        findings.push(Finding::new(path, source, diagnostic));
+                    }
+                }
+            }
+            Err(err) => findings.push(Finding::load_error(path, err)),
+        }
+    }
+
+    if json {
+        // serializing plain strings and numbers can't fail
+        println!("{}", serde_json::to_string_pretty(&findings).unwrap());
+    } else {
+        for finding in findings.iter() {
+            println!("{}", finding);
+        }
+    }
+
+    let failed = findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error);
+
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+/// How long a file has to stay untouched before it's rendered again, editors
+/// often write a file in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+fn watch(
+    input: &Path,
+    output: &Path,
+    format: Option<Format>,
+    options: &RenderOptions,
+) -> Result<ExitCode> {
+    if is_stdio(input) || is_stdio(output) {
+        return Err(LilyError::Project(String::from(
+            "watch needs files, not stdin or stdout",
+        )));
+    }
+
+    let format = Format::pick(format, output)?;
+
+    let notify_error = |err: notify::Error| LilyError::Project(err.to_string());
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(notify_error)?;
+
+    watcher
+        .watch(file_watch::directory(input), RecursiveMode::NonRecursive)
+        .map_err(notify_error)?;
+
+    println!("watching {}, press Ctrl+C to stop", input.display());
+    rebuild(input, output, format, options);
+
+    while let Ok(event) = receiver.recv() {
+        let changed = event.is_ok_and(|event| file_watch::touches(&event, input));
+
+        if !changed {
+            continue;
+        }
+
+        while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+
+        rebuild(input, output, format, options);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Renders `input` for `lily watch`, keeping the last output when it fails.
+fn rebuild(input: &Path, output: &Path, format: Format, options: &RenderOptions) {
+    let start = Instant::now();
+
+    let source = match sources(input) {
+        Ok(sources) => sources.into_iter().next(),
+        Err(err) => {
+            eprintln!("{}", Finding::load_error(input, err));
+            None
+        }
+    };
+
+    let Some(source) = source else {
+        eprintln!("keeping the last output");
+        return;
+    };
+
+    let diagnostics = diagnostics::diagnose(&source.definition, &DiagnosticOptions::default());
+    let failed = diagnostics::has_errors(&diagnostics);
+
+    for diagnostic in diagnostics {
+        eprintln!("{}", Finding::new(input, &source, diagnostic));
+    }
+
+    if failed {
+        eprintln!("keeping the last output");
+        return;
+    }
+
+    let bytes = match render(&source.definition, format, options) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("{}\nkeeping the last output", err);
+            return;
+        }
+    };
+
+    match std::fs::write(output, bytes) {
+        Ok(()) => println!(
+            "rendered {} ({} ms)",
+            output.display(),
+            start.elapsed().as_millis()
+        ),
+        Err(err) => eprintln!("{}", LilyError::io(output, err)),
+    }
+}
+
+struct ExpandOptions {
+    all: bool,
+    out_dir: Option<PathBuf>,
+    count_only: bool,
+    max_symbols: usize,
+}
+
+fn expand(input: &Path, iterations: Option<usize>, options: &ExpandOptions) -> Result<()> {
+    let source = sources(input)?.into_iter().next();
+    let definition = source
+        .ok_or_else(|| LilyError::Project(String::from("no systems found")))?
+        .definition;
+
+    let iterations = iterations.unwrap_or(definition.iterations);
+    let rules = definition.rules();
+
+    let expansions = rules
+        .expansions(&definition.axiom)
+        .with_limit(options.max_symbols)
+        .with_seed(definition.seed)
+        .take(iterations + 1)
+        .enumerate()
+        // without --all only the last iteration is printed
+        .filter(|(i, _)| options.all || *i == iterations);
+
+    if let Some(ref directory) = options.out_dir {
+        std::fs::create_dir_all(directory).map_err(|err| LilyError::io(directory, err))?;
+    }
+
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    let stdout_error = |err| LilyError::io("<stdout>", err);
+
+    // each iteration is written as soon as it's expanded, and dropped after
+    for (i, tree) in expansions {
+        let tree = tree?;
+
+        let line = match options.count_only {
+            true => tree.chars().count().to_string(),
+            false => tree,
+        };
+
+        match options.out_dir {
+            Some(ref directory) => {
+                let path = directory.join(format!("{}.txt", i));
+                std::fs::write(&path, line + "\n").map_err(|err| LilyError::io(path, err))?;
+            }
+            None => writeln!(stdout, "{}", line).map_err(stdout_error)?,
+        }
+    }
+
+    stdout.flush().map_err(stdout_error)
+}
+
+/// The output of `lily stats`.
+#[derive(Serialize)]
+struct StatsReport {
+    iterations: usize,
+    #[serde(flatten)]
+    stats: GenerationStats,
+    frequencies: BTreeMap<char, usize>,
+    /// The estimated length after each iteration, see [`system::Rules::estimate_lengths`].
+    growth: Vec<f64>,
+}
+
+impl std::fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stats = &self.stats;
+        let optional = |count: Option<usize>| match count {
+            Some(count) => count.to_string(),
+            None => String::from("skipped"),
+        };
+
+        writeln!(f, "iterations    {}", self.iterations)?;
+        writeln!(f, "symbols       {}", stats.symbols)?;
+        writeln!(f, "instructions  {}", stats.instructions)?;
+        writeln!(f, "segments      {}", stats.segments)?;
+        writeln!(f, "branches      {}", stats.branches)?;
+        writeln!(f, "max depth     {}", stats.max_depth)?;
+        writeln!(f, "length        {}", stats.length)?;
+        writeln!(f, "size          {} x {}", stats.width, stats.height)?;
+        writeln!(f, "vertices      {}", optional(stats.vertices))?;
+        writeln!(f, "triangles     {}", optional(stats.triangles))?;
+
+        let growth: Vec<_> = self
+            .growth
+            .iter()
+            .map(|length| format!("{:.0}", length))
+            .collect();
+        writeln!(f, "growth        {}", growth.join(" "))?;
+
+        let mut frequencies: Vec<_> = self.frequencies.iter().collect();
+        frequencies.sort_by(|a, b| b.1.cmp(a.1));
+
+        for (i, (symbol, count)) in frequencies.into_iter().enumerate() {
+            let label = if i == 0 { "frequencies" } else { "" };
+            writeln!(f, "{:<13} {} {}", label, symbol, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct StatsOptions {
+    json: bool,
+    skip_mesh: bool,
+    max_symbols: usize,
+}
+
+/// Measures `definition` for `lily stats`.
+fn stats_report(definition: &Definition, options: &StatsOptions) -> Result<StatsReport> {
+    let tree = definition.try_expand(options.max_symbols)?;
+    let instructions = definition.instructions();
+    let stream = instructions.apply(&tree);
+
+    let mut stats =
+        system::generation_stats(&definition.options, &tree, &stream, instructions.customs());
+
+    if !options.skip_mesh {
+        let mesh = system::generate_mesh_with(&definition.options, &stream, instructions.customs());
+        stats = stats.with_mesh(&mesh);
+    }
+
+    Ok(StatsReport {
+        iterations: definition.iterations,
+        stats,
+        frequencies: system::symbol_frequencies(&tree),
+        growth: (definition.rules()).estimate_lengths(&definition.axiom, definition.iterations),
+    })
+}
+
+fn stats(input: &Path, iterations: Option<usize>, options: &StatsOptions) -> Result<()> {
+    let source = sources(input)?.into_iter().next();
+    let mut definition = source
+        .ok_or_else(|| LilyError::Project(String::from("no systems found")))?
+        .definition;
+
+    if let Some(iterations) = iterations {
+        definition.iterations = iterations;
+    }
+
+    let report = stats_report(&definition, options)?;
+
+    match options.json {
+        // serializing plain strings and numbers can't fail
+        true => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+        false => print!("{}", report),
+    }
+
+    Ok(())
+}
+
+/// The formats `lily convert` writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum ConvertFormat {
+    /// A JSON project, the native `.lily` format.
+    #[value(alias = "lily")]
+    Json,
+    /// A TOML project.
+    Toml,
+    /// The plain text format, without options, view or snapshots.
+    Text,
+}
+
+impl From<ConvertFormat> for ProjectFormat {
+    fn from(format: ConvertFormat) -> Self {
+        match format {
+            ConvertFormat::Json => Self::Json,
+            ConvertFormat::Toml => Self::Toml,
+            ConvertFormat::Text => Self::Text,
+        }
+    }
+}
+
+fn extension(format: ProjectFormat) -> &'static str {
+    match format {
+        ProjectFormat::Json => "lily",
+        ProjectFormat::Toml => "toml",
+        ProjectFormat::Text => "txt",
+    }
+}
+
+struct ConvertOptions {
+    to: Option<ProjectFormat>,
+    output: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    lossy: bool,
+}
+
+/// Reads every project in a file, Fractint `.l` files may hold many.
+fn projects(path: &Path) -> Result<Vec<(Option<String>, Project)>> {
+    let text = read_input(path)?;
+
+    if path.extension().is_some_and(|extension| extension == "l") {
+        let systems = system::import_fractint(&text)?;
+
+        return Ok(systems
+            .into_iter()
+            .map(|system| (Some(system.name), Project::new(system.definition)))
+            .collect());
+    }
+
+    let format = ProjectFormat::from_path(path).unwrap_or_else(|| ProjectFormat::detect(&text));
+    Ok(vec![(None, Project::parse(&text, format)?)])
+}
+
+/// Where a converted system goes, named after the system or the input file.
+fn convert_output(
+    input: &Path,
+    name: Option<&str>,
+    format: ProjectFormat,
+    options: &ConvertOptions,
+) -> Result<PathBuf> {
+    if let Some(ref output) = options.output {
+        return Ok(output.clone());
+    }
+
+    let Some(ref directory) = options.out_dir else {
+        return Ok(PathBuf::from("-"));
+    };
+
+    let stem = match name {
+        Some(name) => name.to_string(),
+        None if is_stdio(input) => String::from("stdin"),
+        None => input.file_stem().map_or_else(
+            || String::from("system"),
+            |stem| stem.to_string_lossy().into_owned(),
+        ),
+    };
+
+    Ok(directory.join(format!("{}.{}", stem, extension(format))))
+}
+
+/// Converts one system, refusing to drop anything unless `--lossy` is given.
+fn convert_project(
+    project: &Project,
+    output: &Path,
+    format: ProjectFormat,
+    options: &ConvertOptions,
+) -> Result<()> {
+    let lost = project.lost_in(format);
+
+    if !lost.is_empty() && !options.lossy {
+        return Err(LilyError::Export(format!(
+            "the {} can't be written as {}, pass `--lossy` to drop them",
+            lost.join(", "),
+            extension(format)
+        )));
+    }
+
+    let text = project.to_string(format)?;
+    write_output(output, false, text.as_bytes())
+}
+
+fn convert(inputs: &[PathBuf], options: &ConvertOptions) -> Result<ExitCode> {
+    let format = match (options.to, &options.output) {
+        (Some(format), _) => format,
+        (None, Some(output)) if !is_stdio(output) => ProjectFormat::from_path(output)
+            .ok_or_else(|| LilyError::Project(String::from("unknown output format")))?,
+        (None, _) => ProjectFormat::Json,
+    };
+
+    if let Some(ref directory) = options.out_dir {
+        std::fs::create_dir_all(directory).map_err(|err| LilyError::io(directory, err))?;
+    }
+
+    let mut failed = false;
+    let mut converted = 0;
+
+    for input in inputs {
+        let projects = match projects(input) {
+            Ok(projects) => projects,
+            Err(err) => {
+                eprintln!("{}", Finding::load_error(input, err));
+                failed = true;
+                continue;
+            }
+        };
+
+        for (name, project) in projects {
+            let source = match name {
+                Some(ref name) => format!("{} ({})", display(input), name),
+                None => display(input),
+            };
+
+            let result =
+                convert_output(input, name.as_deref(), format, options).and_then(|output| {
+                    // a single output can't take several systems
+                    if options.out_dir.is_none() && converted > 0 {
+                        return Err(LilyError::Project(String::from(
+                            "several systems need `--out-dir`",
+                        )));
+                    }
+
+                    convert_project(&project, &output, format, options).map(|()| output)
+                });
+
+            match result {
+                Ok(output) if is_stdio(&output) => converted += 1,
+                Ok(output) => {
+                    converted += 1;
+                    eprintln!("converted {} to {}", source, output.display());
+                }
+                Err(err) => {
+                    eprintln!("{}: error: {}", source, err);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    match failed {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Doubles in length with every iteration, 64 symbols at 6.
+    fn doubling() -> Definition {
+        Definition {
+            axiom: String::from("F"),
+            rules: String::from("F -> FF"),
+            instructions: String::from("F = forward 1"),
+            iterations: 6,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_stops_at_max_symbols() {
+        let options = RenderOptions {
+            size: 64,
+            max_symbols: 32,
+            ..Default::default()
+        };
+
+        let formats = [
+            Format::Svg,
+            Format::AnimatedSvg,
+            Format::Png,
+            Format::Obj,
+            Format::Pdf,
+            Format::Gcode,
+            Format::Hpgl,
+        ];
+
+        for format in formats {
+            let result = render(&doubling(), format, &options);
+
+            match result {
+                Err(LilyError::ExpansionBudget {
+                    limit, iteration, ..
+                }) => assert_eq!((limit, iteration), (32, 6)),
+                other => panic!("{:?} rendered past the limit: {:?}", format, other.err()),
+            }
+        }
+
+        let options = RenderOptions {
+            max_symbols: 64,
+            ..options
+        };
+        assert!(render(&doubling(), Format::Svg, &options).is_ok());
+    }
+
+    #[test]
+    fn contact_sheet_cells_stop_at_max_symbols() {
+        let cell = Cell::new(&doubling(), Format::Svg, 32);
+        assert!(matches!(cell, Err(LilyError::ExpansionBudget { .. })));
+
+        let cell = Cell::new(&doubling(), Format::Png, 64);
+        assert!(matches!(cell, Ok(Cell::Mesh(..))));
+    }
+
+    #[test]
+    fn stats_stop_at_max_symbols() {
+        let options = StatsOptions {
+            json: true,
+            skip_mesh: true,
+            max_symbols: 32,
+        };
+
+        let result = stats_report(&doubling(), &options);
+        assert!(matches!(result, Err(LilyError::ExpansionBudget { .. })));
+
+        let options = StatsOptions {
+            max_symbols: 64,
+            ..options
+        };
+
+        let report = stats_report(&doubling(), &options).unwrap();
+        assert_eq!(report.stats.symbols, 64);
+        assert_eq!(report.frequencies[&'F'], 64);
+    }
+
+    #[test]
+    fn page_sizes() {
+        assert_eq!(parse_page_size("A5"), Ok(PdfOptions::A5));
+        assert_eq!(parse_page_size("letter"), Ok(PlotterOptions::LETTER));
+        assert_eq!(parse_page_size("100x50.5"), Ok(Size::new(100.0, 50.5)));
+
+        assert!(parse_page_size("b4").is_err());
+        assert!(parse_page_size("100x").is_err());
+        assert!(parse_page_size("0x100").is_err());
+    }
+
+    #[test]
+    fn pdf_page_size_and_margin() {
+        let options = RenderOptions {
+            pdf: PdfOptions {
+                page: PdfOptions::A5,
+                margin: 20.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let pdf = render(&doubling(), Format::Pdf, &options).unwrap();
+        assert!(String::from_utf8_lossy(&pdf).contains("/MediaBox [0 0 419.528 595.276]"));
+
+        let mut backend = PdfBackend::new();
+        let definition = doubling();
+        let stream = definition.interpret();
+        system::interpret(
+            &definition.options,
+            &stream,
+            &Default::default(),
+            &mut backend,
+        );
+
+        assert_eq!(pdf, backend.finish(&options.pdf));
+        assert_ne!(pdf, backend.finish(&PdfOptions::default()));
+    }
+
+    #[test]
+    fn plotter_flags() {
+        let args = "lily render in.txt --format gcode --page-size a3 --feed-rate 1200 \
+                    --scale 2 --units inches --pen-up 3 --pen-down -1";
+        let cli = Cli::try_parse_from(args.split_whitespace()).unwrap();
+
+        let Command::Render {
+            page_size,
+            feed_rate,
+            scale,
+            units,
+            pen_up,
+            pen_down,
+            ..
+        } = cli.command
+        else {
+            panic!("expected a render command");
+        };
+
+        assert_eq!(page_size, PlotterOptions::A3);
+        assert_eq!((feed_rate, scale), (1200.0, Some(2.0)));
+        assert_eq!(units, Units::Inches);
+        assert_eq!((pen_up, pen_down), (3.0, -1.0));
+    }
+
+    #[test]
+    fn plotter_options_reach_the_gcode() {
+        let options = RenderOptions {
+            plotter: PlotterOptions {
+                feed_rate: 1270.0,
+                units: GcodeUnits::Inches,
+                pen: PenControl::Z {
+                    up: 2.54,
+                    down: 0.0,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let gcode = render(&doubling(), Format::Gcode, &options).unwrap();
+        let gcode = String::from_utf8(gcode).unwrap();
+
+        assert!(gcode.starts_with("G20 ; inches\n"));
+        assert!(gcode.contains("G0 Z0.100\n"));
+        assert!(gcode.contains(" F50\n"));
+    }
+
+    /// An empty directory of its own for a test.
+    fn scratch(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("lily-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn convert_to(input: &Path, format: ProjectFormat, output: &Path, lossy: bool) -> bool {
+        let options = ConvertOptions {
+            to: Some(format),
+            output: Some(output.to_path_buf()),
+            out_dir: None,
+            lossy,
+        };
+
+        convert(&[input.to_path_buf()], &options).unwrap() == ExitCode::SUCCESS
+    }
+
+    #[test]
+    fn converts_between_every_format() {
+        let directory = scratch("convert");
+
+        // stochastic rules and a seed, which every format can hold
+        let project = Project::new(Definition {
+            axiom: String::from("X"),
+            rules: String::from("X -> F[+X]F[-X]+X (0.6)\nX -> F[-X]+X (0.4)\nF -> FF"),
+            instructions: String::from("F = forward 2\n+ = turn 25\n- = turn -25"),
+            iterations: 5,
+            seed: 42,
+            ..Default::default()
+        });
+
+        let formats = [
+            ProjectFormat::Json,
+            ProjectFormat::Toml,
+            ProjectFormat::Text,
+        ];
+
+        for from in formats {
+            let input = directory.join(format!("input.{}", extension(from)));
+            std::fs::write(&input, project.to_string(from).unwrap()).unwrap();
+
+            for to in formats {
+                let output = directory.join(format!("output.{}", extension(to)));
+                assert!(convert_to(&input, to, &output, false));
+
+                let text = std::fs::read_to_string(&output).unwrap();
+                let converted = Project::parse(&text, to).unwrap();
+                assert_eq!(converted, project, "{:?} to {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_lossy_conversions() {
+        let directory = scratch("lossy");
+
+        let mut project = Project::new(doubling());
+        project.definition.options.branch_width = 7.5;
+        project.view.zoom = 2.0;
+
+        let input = directory.join("input.lily");
+        std::fs::write(&input, project.to_string(ProjectFormat::Json).unwrap()).unwrap();
+        assert_eq!(project.lost_in(ProjectFormat::Text), ["options", "view"]);
+
+        let output = directory.join("output.txt");
+        assert!(!convert_to(&input, ProjectFormat::Text, &output, false));
+        assert!(!output.exists());
+
+        // TOML holds everything
+        assert!(convert_to(&input, ProjectFormat::Toml, &output, false));
+
+        assert!(convert_to(&input, ProjectFormat::Text, &output, true));
+        let text = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(Definition::parse_text(&text), doubling());
+    }
+
+    #[test]
+    fn converts_fractint_files_into_a_directory() {
+        let directory = scratch("fractint");
+
+        let input = directory.join("archive.l");
+        let fractint = "Koch1 {\n  Angle 6\n  Axiom F--F--F\n  F=F+F--F+F\n}\n\
+                        Dragon {\n  Angle 8\n  Axiom FX\n  F=\n  y=+FX--FY+\n  x=-FX++FY-\n}\n";
+        std::fs::write(&input, fractint).unwrap();
+
+        // a missing input is reported without stopping the others
+        let out_dir = directory.join("converted");
+        let options = ConvertOptions {
+            to: Some(ProjectFormat::Toml),
+            output: None,
+            out_dir: Some(out_dir.clone()),
+            lossy: false,
+        };
+        let inputs = [directory.join("missing.l"), input];
+        assert_eq!(convert(&inputs, &options).unwrap(), ExitCode::FAILURE);
+
+        for system in system::import_fractint(fractint).unwrap() {
+            let path = out_dir.join(format!("{}.toml", system.name));
+            let text = std::fs::read_to_string(path).unwrap();
+            let project = Project::parse(&text, ProjectFormat::Toml).unwrap();
+            assert_eq!(project.definition, system.definition);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Access to the system clipboard, which isn't available on the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::cell::RefCell;
+
+    use copypasta::{ClipboardContext, ClipboardProvider};
+
+    thread_local! {
+        // on X11 the copied text is only served while the context lives
+        static CLIPBOARD: RefCell<Option<ClipboardContext>> = const { RefCell::new(None) };
+    }
+
+    fn with_clipboard<T>(
+        f: impl FnOnce(&mut ClipboardContext) -> Result<T, Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Result<T, String> {
+        CLIPBOARD.with(|clipboard| {
+            let mut clipboard = clipboard.borrow_mut();
+
+            if clipboard.is_none() {
+                *clipboard = Some(ClipboardContext::new().map_err(|err| err.to_string())?);
+            }
+
+            f(clipboard.as_mut().unwrap()).map_err(|err| err.to_string())
+        })
+    }
+
+    pub fn set(text: String) -> Result<(), String> {
+        with_clipboard(|clipboard| clipboard.set_contents(text))
+    }
+
+    pub fn get() -> Result<String, String> {
+        with_clipboard(|clipboard| clipboard.get_contents())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod native {
+    pub fn set(_text: String) -> Result<(), String> {
+        Err(String::from("the clipboard isn't available on the web"))
+    }
+
+    pub fn get() -> Result<String, String> {
+        Err(String::from("the clipboard isn't available on the web"))
+    }
+}
+
+pub use native::{get, set};
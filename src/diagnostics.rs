@@ -0,0 +1,517 @@
+//! Checks a definition for mistakes without generating it.
+
+use std::{collections::HashSet, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    project::Definition,
+    system::{self, Instruction, Instructions, Pcg32, Rule, Rules},
+    LilyError,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth knowing, but not necessarily a mistake.
+    Note,
+    /// Probably a mistake, but the system still generates.
+    Warning,
+    /// The system won't generate the way it's written.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Note => f.write_str("note"),
+            Self::Warning => f.write_str("warning"),
+            Self::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// The part of a [`Definition`] a diagnostic refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    Axiom,
+    Rules,
+    Instructions,
+    Iterations,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Axiom => f.write_str("axiom"),
+            Self::Rules => f.write_str("rules"),
+            Self::Instructions => f.write_str("instructions"),
+            Self::Iterations => f.write_str("iterations"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub section: Section,
+    /// The 1-based line within the section.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, section: Section, message: String) -> Self {
+        Self {
+            severity,
+            section,
+            line: None,
+            message,
+        }
+    }
+
+    fn at(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}: {} line {}: ", self.severity, self.section, line)?,
+            None => write!(f, "{}: {}: ", self.severity, self.section)?,
+        }
+
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiagnosticOptions {
+    /// Brackets are checked on the expansion after at most this many
+    /// iterations, imbalances usually show up within the first few.
+    pub bracket_iterations: usize,
+    /// The estimated length the expansion may grow to.
+    pub max_symbols: usize,
+}
+
+impl Default for DiagnosticOptions {
+    fn default() -> Self {
+        Self {
+            bracket_iterations: 3,
+            max_symbols: system::DEFAULT_SYMBOL_LIMIT,
+        }
+    }
+}
+
+/// Runs every check on `definition`, the findings are in the order of the
+/// checks rather than by line.
+pub fn diagnose(definition: &Definition, options: &DiagnosticOptions) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let (rules, errors) = Rules::parse_checked(&definition.rules);
+    diagnostics.extend(errors.iter().map(parse_error));
+
+    let (bound, errors, bindings) = Instructions::parse_bindings(&definition.instructions);
+    diagnostics.extend(errors.iter().map(parse_error));
+
+    let mut instructions = bound.clone();
+    instructions.insert_defaults();
+
+    shadowed_rules(&definition.rules, &mut diagnostics);
+    probabilities(&definition.rules, &rules, &mut diagnostics);
+    duplicate_instructions(&definition.instructions, &bindings, &mut diagnostics);
+    unknown_symbols(definition, &rules, &instructions, &mut diagnostics);
+    brackets(definition, &rules, &instructions, options, &mut diagnostics);
+    polygons(definition, &rules, &instructions, options, &mut diagnostics);
+    growth(definition, &rules, options, &mut diagnostics);
+    unused_instructions(
+        definition,
+        &rules,
+        &bound,
+        &bindings,
+        options,
+        &mut diagnostics,
+    );
+
+    diagnostics
+}
+
+/// Checks the instructions on their own, for lines that can't be parsed and
+/// symbols that are bound twice, which is quick enough to do on every edit.
+///
+/// `errors` and `bindings` are what [`Instructions::parse_bindings`] found in
+/// `text`, so the text isn't parsed again.
+pub fn diagnose_instructions(
+    text: &str,
+    errors: &[LilyError],
+    bindings: &[(usize, char)],
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<_> = errors.iter().map(parse_error).collect();
+    duplicate_instructions(text, bindings, &mut diagnostics);
+
+    diagnostics
+}
+
+/// Whether any of `diagnostics` is an error.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+fn parse_error(err: &LilyError) -> Diagnostic {
+    match err {
+        LilyError::Rule { line, text, reason } => Diagnostic::new(
+            Severity::Error,
+            Section::Rules,
+            format!("invalid rule `{}`: {}", text, reason),
+        )
+        .at(*line),
+        LilyError::Instruction { line, text, reason } => Diagnostic::new(
+            Severity::Error,
+            Section::Instructions,
+            format!("invalid instruction `{}`: {}", text, reason),
+        )
+        .at(*line),
+        err => Diagnostic::new(Severity::Error, Section::Rules, err.to_string()),
+    }
+}
+
+/// The longest rule matching wins, ties go to the earlier rule, so a
+/// context-sensitive rule never matches after a rule for the same symbols
+/// without a context.
+fn shadowed_rules(text: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let rules: Vec<_> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| Some((i + 1, Rule::parse(line)?)))
+        .collect();
+
+    for (i, (line, rule)) in rules.iter().enumerate() {
+        let earlier = rules[..i].iter().find(|(_, earlier)| {
+            rule.rule == earlier.rule
+                && rule.parameters.len() == earlier.parameters.len()
+                && rule.is_context_sensitive()
+                && !earlier.is_context_sensitive()
+        });
+
+        let Some((_, earlier)) = earlier else {
+            continue;
+        };
+
+        // lines are counted within the rules, refer to the rule itself instead
+        let message = format!(
+            "`{}` is shadowed by `{}`, this rule is never used",
+            rule.rule, earlier
+        );
+
+        diagnostics.push(Diagnostic::new(Severity::Warning, Section::Rules, message).at(*line));
+    }
+}
+
+/// The probabilities of the alternatives for the same symbols are scaled to
+/// add up to 1, which is rarely what was meant when they don't.
+fn probabilities(text: &str, rules: &Rules, diagnostics: &mut Vec<Diagnostic>) {
+    let mut reported = Vec::<Rule>::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let Some(rule) = Rule::parse(line) else {
+            continue;
+        };
+
+        if rules.alternatives(&rule).nth(1).is_none()
+            || reported.iter().any(|other| other.same_predecessor(&rule))
+        {
+            continue;
+        }
+
+        let total = rules.total_probability(&rule);
+
+        if (total - 1.0).abs() > 1e-3 {
+            let message = format!(
+                "the probabilities of the rules for `{}` add up to {:.2}, they're scaled to add up to 1",
+                rule.rule, total
+            );
+
+            diagnostics.push(Diagnostic::new(Severity::Warning, Section::Rules, message).at(i + 1));
+        }
+
+        reported.push(rule);
+    }
+}
+
+/// The last instruction for a symbol wins, earlier ones are ignored.
+fn duplicate_instructions(
+    text: &str,
+    bindings: &[(usize, char)],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let lines: Vec<_> = text.lines().collect();
+
+    for (i, (line, key)) in bindings.iter().enumerate() {
+        let later = bindings[i + 1..].iter().find(|(_, later)| later == key);
+
+        if let Some((later, _)) = later {
+            let message = format!(
+                "`{}` is bound again by `{}`, this instruction is never used",
+                key,
+                lines[later - 1].trim()
+            );
+
+            diagnostics
+                .push(Diagnostic::new(Severity::Warning, Section::Instructions, message).at(*line));
+        }
+    }
+}
+
+/// Symbols without a rule or an instruction do nothing at all.
+fn unknown_symbols(
+    definition: &Definition,
+    rules: &Rules,
+    instructions: &Instructions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let replaced: HashSet<char> = rules.iter().flat_map(|rule| rule.rule.chars()).collect();
+    let mut reported = HashSet::new();
+
+    let mut check = |text: &str, section: Section, line: Option<usize>| {
+        for c in system::symbols(text) {
+            // digits without an instruction are repeat counts
+            if c.is_whitespace()
+                || c.is_ascii_digit()
+                || replaced.contains(&c)
+                || instructions.get(c).is_some()
+                || !reported.insert(c)
+            {
+                continue;
+            }
+
+            let message = format!("`{}` has no rule or instruction", c);
+            let mut diagnostic = Diagnostic::new(Severity::Warning, section, message);
+            diagnostic.line = line;
+            diagnostics.push(diagnostic);
+        }
+    };
+
+    check(&definition.axiom, Section::Axiom, None);
+
+    for (i, line) in definition.rules.lines().enumerate() {
+        if let Some(rule) = Rule::parse(line) {
+            check(&rule.replace, Section::Rules, Some(i + 1));
+        }
+    }
+}
+
+/// The first few iterations of the tree, enough to find mistakes in how the
+/// rules nest, and how many that is.
+fn early_tree(
+    definition: &Definition,
+    rules: &Rules,
+    options: &DiagnosticOptions,
+) -> (usize, String) {
+    let iterations = usize::min(definition.iterations, options.bracket_iterations);
+
+    let mut rng = Pcg32::new(definition.seed);
+    let mut tree = definition.axiom.clone();
+    for _ in 0..iterations {
+        tree = rules.apply(&tree, &mut rng);
+    }
+
+    (iterations, tree)
+}
+
+/// An extra pop is ignored, which usually isn't what was meant.
+fn brackets(
+    definition: &Definition,
+    rules: &Rules,
+    instructions: &Instructions,
+    options: &DiagnosticOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let (iterations, tree) = early_tree(definition, rules, options);
+
+    let mut depth = 0usize;
+
+    for (i, c) in tree.chars().enumerate() {
+        match instructions.get(c) {
+            Some(Instruction::Push) => depth += 1,
+            Some(Instruction::Pop) if depth == 0 => {
+                let message = format!(
+                    "after {} iterations, symbol {} pops more branches than were pushed, \
+                     it's ignored",
+                    iterations,
+                    i + 1
+                );
+
+                diagnostics.push(Diagnostic::new(Severity::Error, Section::Rules, message));
+                return;
+            }
+            Some(Instruction::Pop) => depth -= 1,
+            _ => {}
+        }
+    }
+
+    if depth > 0 {
+        let message = format!(
+            "after {} iterations, {} branches are never popped",
+            iterations, depth
+        );
+
+        diagnostics.push(Diagnostic::new(Severity::Warning, Section::Rules, message));
+    }
+}
+
+/// Polygons that are begun twice, ended without being begun or never ended
+/// are left out of the drawing, only the first is reported.
+fn polygons(
+    definition: &Definition,
+    rules: &Rules,
+    instructions: &Instructions,
+    options: &DiagnosticOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let (iterations, tree) = early_tree(definition, rules, options);
+
+    // whether each branch has a polygon open, a branch starts with the one
+    // of its parent
+    let mut open = vec![false];
+
+    for (i, c) in tree.chars().enumerate() {
+        let problem = match instructions.get(c) {
+            Some(Instruction::Push) => {
+                open.push(open[open.len() - 1]);
+                continue;
+            }
+            Some(Instruction::Pop) if open.len() > 1 => {
+                open.pop();
+                continue;
+            }
+            Some(Instruction::BeginPolygon) if open[open.len() - 1] => {
+                "begins a polygon while another is open, the open one is discarded"
+            }
+            Some(Instruction::EndPolygon) if !open[open.len() - 1] => {
+                "ends a polygon that was never begun, it's ignored"
+            }
+            Some(Instruction::BeginPolygon) | Some(Instruction::EndPolygon) => {
+                let last = open.len() - 1;
+                open[last] = !open[last];
+                continue;
+            }
+            _ => continue,
+        };
+
+        let message = format!(
+            "after {} iterations, symbol {} {}",
+            iterations,
+            i + 1,
+            problem
+        );
+
+        diagnostics.push(Diagnostic::new(Severity::Warning, Section::Rules, message));
+        return;
+    }
+
+    if open.contains(&true) {
+        let message = format!(
+            "after {} iterations, a polygon is never ended and isn't drawn",
+            iterations
+        );
+
+        diagnostics.push(Diagnostic::new(Severity::Warning, Section::Rules, message));
+    }
+}
+
+fn growth(
+    definition: &Definition,
+    rules: &Rules,
+    options: &DiagnosticOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let lengths = rules.estimate_lengths(&definition.axiom, definition.iterations);
+    let limit = options.max_symbols as f64;
+
+    if let Some(iteration) = lengths.iter().position(|&length| length > limit) {
+        let message = format!(
+            "expands to about {:.0} symbols after {} iterations, more than the limit of {}",
+            lengths[definition.iterations], definition.iterations, options.max_symbols
+        );
+
+        let mut diagnostic = Diagnostic::new(Severity::Error, Section::Iterations, message);
+
+        if iteration < definition.iterations {
+            diagnostic.message += &format!(", the limit is passed at iteration {}", iteration);
+        }
+
+        diagnostics.push(diagnostic);
+    }
+}
+
+/// Instructions for symbols the final expansion doesn't contain, often left
+/// over from an earlier version of the rules.
+fn unused_instructions(
+    definition: &Definition,
+    rules: &Rules,
+    bound: &Instructions,
+    bindings: &[(usize, char)],
+    options: &DiagnosticOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let expansion = (rules.expansions(&definition.axiom))
+        .with_limit(options.max_symbols)
+        .with_seed(definition.seed)
+        .nth(definition.iterations);
+
+    // expansions over the limit are already reported by `growth`
+    let Some(Ok(tree)) = expansion else {
+        return;
+    };
+
+    for key in bound.unused_keys(&tree) {
+        // the last binding is the one that counts
+        let line = (bindings.iter().rev())
+            .find(|(_, bound)| *bound == key)
+            .map(|(line, _)| *line);
+
+        let message = format!(
+            "`{}` never appears after {} iterations, this instruction is unused",
+            key, definition.iterations
+        );
+
+        let mut diagnostic = Diagnostic::new(Severity::Note, Section::Instructions, message);
+        diagnostic.line = line;
+        diagnostics.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shadowed(rules: &str) -> Vec<(Option<usize>, String)> {
+        let mut diagnostics = Vec::new();
+        shadowed_rules(rules, &mut diagnostics);
+
+        (diagnostics.into_iter())
+            .map(|diagnostic| (diagnostic.line, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn longer_rules_are_not_shadowed() {
+        assert!(shadowed("F -> FF\nFA -> X").is_empty());
+        assert!(shadowed("FA -> X\nF -> FF").is_empty());
+        assert!(shadowed("A > C -> Y\nB < A -> X").is_empty());
+
+        // a context can't win against a rule for the same symbols before it
+        assert_eq!(
+            shadowed("A -> X\nB < A -> Y"),
+            [(
+                Some(2),
+                String::from("`A` is shadowed by `A -> X`, this rule is never used")
+            )]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! The error type shared by parsing, expansion, generation and file handling.
+
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// A [`Result`](std::result::Result) defaulting to [`LilyError`].
+pub type Result<T, E = LilyError> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum LilyError {
+    /// A line of rules text could not be parsed.
+    #[error("line {line}: invalid rule `{text}`: {reason}")]
+    Rule {
+        line: usize,
+        text: String,
+        reason: String,
+    },
+
+    /// A line of instructions text could not be parsed.
+    #[error("line {line}: invalid instruction `{text}`: {reason}")]
+    Instruction {
+        line: usize,
+        text: String,
+        reason: String,
+    },
+
+    /// A Fractint `.l` file could not be imported.
+    #[error("line {line}: {reason}")]
+    Fractint { line: usize, reason: String },
+
+    /// The expanded string grew past the symbol limit.
+    ///
+    /// `partial` holds the last expansion that fit within the limit.
+    #[error("expansion stopped at iteration {iteration}: more than {limit} symbols")]
+    ExpansionBudget {
+        limit: usize,
+        iteration: usize,
+        partial: String,
+    },
+
+    /// The generated mesh grew past the vertex limit.
+    #[error("generation stopped: more than {limit} vertices")]
+    GenerationBudget { limit: usize, vertices: usize },
+
+    /// The operation was cancelled before it finished.
+    #[error("cancelled")]
+    Cancelled,
+
+    /// The expansion has a bracket without a partner.
+    #[error("{0}")]
+    Brackets(#[from] BracketError),
+
+    /// A project file is malformed.
+    #[error("malformed project: {0}")]
+    Project(String),
+
+    /// A project file was written by a newer version of lily.
+    #[error("project version {found} is newer than the supported version {supported}")]
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    /// A share code could not be decoded.
+    #[error("invalid share code: {0}")]
+    ShareCode(String),
+
+    /// An image or document could not be exported.
+    #[error("export failed: {0}")]
+    Export(String),
+
+    /// Reading or writing a file failed.
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// The first bracket in an expansion without a partner, see
+/// [`Instructions::validate_brackets`](crate::system::Instructions::validate_brackets).
+///
+/// `index` is the byte index of the bracket in the expansion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum BracketError {
+    /// A pop without a push before it.
+    #[error("the `{symbol}` at byte {index} closes a branch that was never opened")]
+    UnmatchedPop { index: usize, symbol: char },
+
+    /// A push that's never popped.
+    #[error("the `{symbol}` at byte {index} opens a branch that's never closed")]
+    UnclosedPush { index: usize, symbol: char },
+}
+
+/// Applying the rules stopped once the expansion grew past
+/// [`Rules::max_length`](crate::system::Rules::max_length).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("stopped at {length} characters")]
+pub struct Truncated {
+    /// The length the expansion had grown to when it was stopped.
+    pub length: usize,
+}
+
+impl LilyError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// The 1-based line the error refers to, if any.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::Rule { line, .. }
+            | Self::Instruction { line, .. }
+            | Self::Fractint { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the command line prints these, so their wording is pinned
+
+    #[test]
+    fn display() {
+        let cases = [
+            (
+                LilyError::Rule {
+                    line: 2,
+                    text: String::from("F ->"),
+                    reason: String::from("expected a replacement"),
+                },
+                "line 2: invalid rule `F ->`: expected a replacement",
+            ),
+            (
+                LilyError::Instruction {
+                    line: 3,
+                    text: String::from("F = fly"),
+                    reason: String::from("unknown instruction `fly`"),
+                },
+                "line 3: invalid instruction `F = fly`: unknown instruction `fly`",
+            ),
+            (
+                LilyError::ExpansionBudget {
+                    limit: 100,
+                    iteration: 4,
+                    partial: String::from("FF"),
+                },
+                "expansion stopped at iteration 4: more than 100 symbols",
+            ),
+            (
+                LilyError::GenerationBudget {
+                    limit: 10,
+                    vertices: 12,
+                },
+                "generation stopped: more than 10 vertices",
+            ),
+            (LilyError::Cancelled, "cancelled"),
+            (
+                LilyError::UnsupportedVersion {
+                    found: 3,
+                    supported: 1,
+                },
+                "project version 3 is newer than the supported version 1",
+            ),
+            (
+                LilyError::io("koch.lily", io::Error::other("denied")),
+                "koch.lily: denied",
+            ),
+        ];
+
+        for (err, message) in cases {
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn from_bracket_error() {
+        let validate = || -> Result<()> {
+            Err(BracketError::UnmatchedPop {
+                index: 3,
+                symbol: ']',
+            })?
+        };
+
+        let err = validate().unwrap_err();
+        assert!(matches!(err, LilyError::Brackets(_)));
+        assert_eq!(
+            err.to_string(),
+            "the `]` at byte 3 closes a branch that was never opened"
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let err = LilyError::Fractint {
+            line: 7,
+            reason: String::from("unknown command `Foo`"),
+        };
+
+        assert_eq!(err.line(), Some(7));
+        assert_eq!(LilyError::Cancelled.line(), None);
+    }
+}
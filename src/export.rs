@@ -0,0 +1,20 @@
+//! Writing systems to files.
+
+mod contact_sheet;
+mod font;
+mod obj;
+mod pdf;
+mod plotter;
+mod png;
+mod polyline;
+mod svg;
+
+pub use contact_sheet::{
+    contact_sheet_mesh, contact_sheet_png, contact_sheet_svg, ContactSheetOptions,
+};
+pub use obj::mesh_to_obj;
+pub use pdf::{PdfBackend, PdfOptions};
+pub use plotter::{export_gcode, export_hpgl, GcodeUnits, PenControl, Plot, PlotterOptions};
+pub use png::{export_png, PngOptions};
+pub use polyline::{Polyline, PolylineBackend};
+pub use svg::{animated_svg, mesh_to_svg, SvgAnimation, SvgBackend};
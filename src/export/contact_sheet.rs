@@ -0,0 +1,270 @@
+use std::{fmt::Write as _, io::Write};
+
+use ori::prelude::*;
+
+use super::{export_png, font, PngOptions, SvgBackend};
+use crate::{
+    error::Result,
+    system::{self, FitMode, Growth},
+};
+
+/// How the cells of a contact sheet are laid out, in pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContactSheetOptions {
+    /// The number of cells in each row.
+    pub columns: usize,
+    /// The width and height of each cell.
+    pub cell_size: f32,
+    /// The height of the labels under the cells.
+    pub label_size: f32,
+    /// The space around and between the cells.
+    pub gap: f32,
+    /// Fills the sheet before drawing, the sheet is transparent without one.
+    pub background: Option<Color>,
+    pub label_color: Color,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        Self {
+            columns: 4,
+            cell_size: 256.0,
+            label_size: 16.0,
+            gap: 16.0,
+            background: Some(Color::WHITE),
+            label_color: Color::BLACK,
+        }
+    }
+}
+
+impl ContactSheetOptions {
+    fn columns(&self, count: usize) -> usize {
+        self.columns.clamp(1, count.max(1))
+    }
+
+    /// The height of a row, including its labels.
+    fn row_height(&self) -> f32 {
+        self.cell_size + self.label_size * 1.5
+    }
+
+    /// The size of a sheet holding `count` cells.
+    pub fn size(&self, count: usize) -> Size {
+        let columns = self.columns(count);
+        let rows = count.div_ceil(columns).max(1);
+
+        Size::new(
+            self.gap + columns as f32 * (self.cell_size + self.gap),
+            self.gap + rows as f32 * (self.row_height() + self.gap),
+        )
+    }
+
+    /// Where the drawing of cell `index` of `count` goes, its label is right
+    /// below it.
+    pub fn cell(&self, index: usize, count: usize) -> Rect {
+        let columns = self.columns(count);
+        let (column, row) = (index % columns, index / columns);
+
+        let min = Point::new(
+            self.gap + column as f32 * (self.cell_size + self.gap),
+            self.gap + row as f32 * (self.row_height() + self.gap),
+        );
+
+        Rect::min_size(min, Size::all(self.cell_size))
+    }
+
+    /// The top center of the label of `cell`.
+    fn label(&self, cell: Rect) -> Point {
+        Point::new(cell.center().x, cell.max.y + self.label_size * 0.25)
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Writes `entries` into one SVG, each drawing fit in a cell with its name
+/// below it.
+pub fn contact_sheet_svg(
+    entries: &[(String, SvgBackend)],
+    options: &ContactSheetOptions,
+) -> String {
+    let count = entries.len();
+    let size = options.size(count);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" width="{}" height="{}">"#,
+        size.width, size.height, size.width, size.height,
+    );
+
+    if let Some(color) = options.background {
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}"/>"#,
+            system::format_color(color)
+        );
+    }
+
+    for (i, (name, backend)) in entries.iter().enumerate() {
+        let cell = options.cell(i, count);
+
+        // nested viewports fit the drawing to the cell, keeping its aspect ratio
+        if let Some(bounds) = backend.bounds() {
+            let _ = writeln!(
+                svg,
+                r#"<svg x="{}" y="{}" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+                cell.min.x,
+                cell.min.y,
+                cell.width(),
+                cell.height(),
+                bounds.min.x,
+                bounds.min.y,
+                bounds.width().max(f32::EPSILON),
+                bounds.height().max(f32::EPSILON),
+            );
+
+            svg.push_str("<g fill=\"none\" stroke-linecap=\"round\">\n");
+            backend.write_lines(&mut svg);
+            svg.push_str("</g>\n</svg>\n");
+        }
+
+        let label = options.label(cell);
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" font-size="{}" font-family="sans-serif" text-anchor="middle" dominant-baseline="hanging" fill="{}">{}</text>"#,
+            label.x,
+            label.y,
+            options.label_size,
+            system::format_color(options.label_color),
+            escape(name),
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Adds `text` to `mesh` in the bitmap font, centered below `top`.
+///
+/// Text wider than `max_width` is cut short.
+fn push_label(
+    mesh: &mut Mesh,
+    text: &str,
+    top: Point,
+    max_width: f32,
+    options: &ContactSheetOptions,
+) {
+    let pixel = options.label_size / font::HEIGHT as f32;
+    let advance = pixel * font::ADVANCE as f32;
+
+    let fits = (max_width / advance).floor().max(0.0) as usize;
+    let chars: Vec<_> = text.chars().take(fits).collect();
+
+    // the space after the last glyph isn't part of the text
+    let width = chars.len() as f32 * advance - pixel;
+    let left = top.x - width.max(0.0) / 2.0;
+
+    let vertex = |position| Vertex {
+        position,
+        tex_coords: Point::ZERO,
+        color: options.label_color,
+    };
+
+    for (i, &c) in chars.iter().enumerate() {
+        for (column, bits) in font::glyph(c).into_iter().enumerate() {
+            for row in 0..font::HEIGHT {
+                if bits & (1 << row) == 0 {
+                    continue;
+                }
+
+                let min = Point::new(
+                    left + i as f32 * advance + column as f32 * pixel,
+                    top.y + row as f32 * pixel,
+                );
+
+                let index = mesh.vertices.len() as u32;
+                mesh.vertices.push(vertex(min));
+                mesh.vertices.push(vertex(min + Vector::new(pixel, 0.0)));
+                mesh.vertices.push(vertex(min + Vector::new(pixel, pixel)));
+                mesh.vertices.push(vertex(min + Vector::new(0.0, pixel)));
+
+                mesh.indices.extend([index, index + 1, index + 2]);
+                mesh.indices.extend([index, index + 2, index + 3]);
+            }
+        }
+    }
+}
+
+/// Lays `entries` out in one mesh, each fit in a cell with its name below it.
+///
+/// Each mesh is anchored the way it grows. The mesh spans the whole sheet, so
+/// it's rasterized the same size as [`ContactSheetOptions::size`].
+pub fn contact_sheet_mesh(
+    entries: &[(String, Mesh, Growth)],
+    options: &ContactSheetOptions,
+) -> Mesh {
+    let count = entries.len();
+    let size = options.size(count);
+
+    let mut sheet = Mesh::new();
+
+    // two unused corners make the bounds of the mesh the bounds of the sheet
+    for position in [Point::ZERO, Point::new(size.width, size.height)] {
+        sheet.vertices.push(Vertex {
+            position,
+            tex_coords: Point::ZERO,
+            color: Color::TRANSPARENT,
+        });
+    }
+
+    for (i, (name, mesh, growth)) in entries.iter().enumerate() {
+        let cell = options.cell(i, count);
+
+        if !mesh.vertices.is_empty() {
+            let mut mesh = mesh.clone();
+            system::fit_mesh(&mut mesh, cell, *growth, FitMode::default());
+
+            let offset = sheet.vertices.len() as u32;
+            sheet.vertices.extend(mesh.vertices);
+            sheet
+                .indices
+                .extend(mesh.indices.iter().map(|index| index + offset));
+        }
+
+        let max_width = options.cell_size + options.gap;
+        push_label(&mut sheet, name, options.label(cell), max_width, options);
+    }
+
+    sheet
+}
+
+/// Rasterizes [`contact_sheet_mesh`] into a PNG written to `writer`.
+pub fn contact_sheet_png(
+    entries: &[(String, Mesh, Growth)],
+    options: &ContactSheetOptions,
+    writer: impl Write,
+) -> Result<()> {
+    let size = options.size(entries.len());
+    let mesh = contact_sheet_mesh(entries, options);
+
+    let png = PngOptions {
+        size: f32::max(size.width, size.height).ceil() as u32,
+        background: options.background,
+        ..Default::default()
+    };
+
+    export_png(&mesh, &png, writer)
+}
@@ -0,0 +1,143 @@
+//! Wavefront OBJ, for taking meshes into 3D tools.
+//!
+//! Vertex colors are written with the common `v x y z r g b` extension,
+//! which Blender and MeshLab read and other tools skip, rather than as a
+//! sidecar MTL file. Meshes have `y` pointing down the screen, it's flipped
+//! so the drawing is upright in tools where `y` points up, and every face is
+//! wound counter-clockwise seen from `+z`, so they all face that way and
+//! share one normal.
+
+use std::fmt::Write;
+
+use ori::prelude::*;
+
+/// The name of an object, OBJ names end at whitespace.
+fn object_name(name: &str) -> String {
+    let name: Vec<_> = name.split_whitespace().collect();
+
+    match name.is_empty() {
+        true => String::from("lily"),
+        false => name.join("_"),
+    }
+}
+
+/// Writes the vertices and triangles of `mesh` as one object called `name`,
+/// usually the axiom, `z` is 0 for every vertex.
+pub fn mesh_to_obj(mesh: &Mesh, name: &str) -> String {
+    let mut obj = String::new();
+    let _ = writeln!(obj, "o {}", object_name(name));
+
+    for vertex in mesh.vertices.iter() {
+        let Color { r, g, b, .. } = vertex.color;
+        let position = vertex.position;
+
+        let _ = writeln!(obj, "v {} {} 0 {} {} {}", position.x, -position.y, r, g, b);
+    }
+
+    obj.push_str("vn 0 0 1\n");
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, mut b, mut c] = [triangle[0], triangle[1], triangle[2]];
+
+        // the turtle's strips alternate between windings, a positive area
+        // with `y` down is clockwise once `y` is flipped
+        let point = |index: u32| mesh.vertices[index as usize].position;
+        let (pa, pb, pc) = (point(a), point(b), point(c));
+
+        if (pb.x - pa.x) * (pc.y - pa.y) - (pb.y - pa.y) * (pc.x - pa.x) > 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+
+        // OBJ counts vertices from 1
+        let _ = writeln!(obj, "f {}//1 {}//1 {}//1", a + 1, b + 1, c + 1);
+    }
+
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::PRESETS;
+
+    struct Obj {
+        name: String,
+        vertices: Vec<[f32; 6]>,
+        faces: Vec<[usize; 3]>,
+    }
+
+    fn parse(obj: &str) -> Obj {
+        let mut parsed = Obj {
+            name: String::new(),
+            vertices: Vec::new(),
+            faces: Vec::new(),
+        };
+
+        for line in obj.lines() {
+            let mut parts = line.split_whitespace();
+
+            match parts.next() {
+                Some("o") => parsed.name = parts.collect(),
+                Some("v") => {
+                    let numbers: Vec<f32> = parts.map(|part| part.parse().unwrap()).collect();
+                    parsed.vertices.push(numbers.try_into().unwrap());
+                }
+                Some("f") => {
+                    // `index//normal`, both counted from 1
+                    let indices: Vec<usize> = parts
+                        .map(|part| {
+                            let (index, normal) = part.split_once("//").unwrap();
+                            assert_eq!(normal, "1");
+                            index.parse().unwrap()
+                        })
+                        .collect();
+                    parsed.faces.push(indices.try_into().unwrap());
+                }
+                Some("vn") => assert_eq!(line, "vn 0 0 1"),
+                other => panic!("unexpected line {:?}", other),
+            }
+        }
+
+        parsed
+    }
+
+    #[test]
+    fn writes_the_whole_mesh() {
+        let plant = PRESETS
+            .iter()
+            .find(|preset| preset.name == "Plant")
+            .unwrap();
+
+        let definition = plant.definition();
+        let mesh = crate::system::generate_mesh(&definition.options, &definition.interpret());
+
+        let obj = parse(&mesh_to_obj(&mesh, "X  with spaces"));
+        assert_eq!(obj.name, "X_with_spaces");
+        assert_eq!(obj.vertices.len(), mesh.vertices.len());
+        assert_eq!(obj.faces.len(), mesh.indices.len() / 3);
+
+        // upright, with the colors after the position
+        for (vertex, written) in mesh.vertices.iter().zip(&obj.vertices) {
+            let Color { r, g, b, .. } = vertex.color;
+            let expected = [vertex.position.x, -vertex.position.y, 0.0, r, g, b];
+            assert_eq!(*written, expected);
+        }
+
+        // every face is wound counter-clockwise, facing `+z`
+        for face in &obj.faces {
+            assert!(face
+                .iter()
+                .all(|&index| (1..=obj.vertices.len()).contains(&index)));
+
+            let [a, b, c] = face.map(|index| obj.vertices[index - 1]);
+            let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+            assert!(area >= 0.0, "{:?} is clockwise", face);
+        }
+    }
+
+    #[test]
+    fn unnamed_objects() {
+        assert_eq!(object_name(" \t"), "lily");
+        assert_eq!(object_name("F"), "F");
+    }
+}
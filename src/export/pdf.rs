@@ -0,0 +1,188 @@
+use std::fmt::Write;
+
+use ori::prelude::*;
+
+use crate::system::{Segment, TurtleBackend};
+
+/// Points per millimeter, PDF measures everything in points.
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdfOptions {
+    /// The size of the page in millimeters.
+    pub page: Size,
+    /// The space left free on every side of the page, in millimeters.
+    pub margin: f32,
+    /// Fills the page before drawing, the page is left blank by default.
+    pub background: Option<Color>,
+}
+
+impl PdfOptions {
+    pub const A4: Size = Size::new(210.0, 297.0);
+    pub const A5: Size = Size::new(148.0, 210.0);
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            page: Self::A4,
+            margin: 10.0,
+            background: None,
+        }
+    }
+}
+
+/// Writes every [`Segment`] as a stroked path on a single PDF page.
+#[derive(Clone, Debug, Default)]
+pub struct PdfBackend {
+    segments: Vec<Segment>,
+}
+
+impl PdfBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bounds of every segment, including its width.
+    fn bounds(&self) -> Option<Rect> {
+        let first = self.segments.first()?;
+        let mut bounds = Rect::new(first.from, first.from);
+
+        for segment in self.segments.iter() {
+            let margin = Vector::new(segment.width, segment.width) / 2.0;
+
+            for point in [segment.from, segment.to] {
+                bounds.min = Point::min(bounds.min, point - margin);
+                bounds.max = Point::max(bounds.max, point + margin);
+            }
+        }
+
+        Some(bounds)
+    }
+
+    /// The page contents, one `m`/`l`/`S` path per segment.
+    fn content(&self, options: &PdfOptions) -> String {
+        let mut content = String::new();
+
+        let page = Size::new(
+            options.page.width * POINTS_PER_MM,
+            options.page.height * POINTS_PER_MM,
+        );
+        let margin = options.margin * POINTS_PER_MM;
+
+        if let Some(color) = options.background {
+            let _ = writeln!(
+                content,
+                "{:.3} {:.3} {:.3} rg 0 0 {:.3} {:.3} re f",
+                color.r, color.g, color.b, page.width, page.height
+            );
+        }
+
+        let Some(bounds) = self.bounds() else {
+            return content;
+        };
+
+        let area = Size::new(
+            f32::max(page.width - margin * 2.0, 0.0),
+            f32::max(page.height - margin * 2.0, 0.0),
+        );
+
+        let scale = f32::min(
+            area.width / bounds.width().max(f32::EPSILON),
+            area.height / bounds.height().max(f32::EPSILON),
+        );
+
+        let offset = Vector::new(
+            margin + (area.width - bounds.width() * scale) / 2.0,
+            margin + (area.height - bounds.height() * scale) / 2.0,
+        );
+
+        // pdf has y pointing up
+        let place = |point: Point| {
+            Point::new(
+                offset.x + (point.x - bounds.min.x) * scale,
+                offset.y + (bounds.max.y - point.y) * scale,
+            )
+        };
+
+        content.push_str("1 J 1 j\n");
+
+        let mut color = None;
+        let mut width = None;
+
+        for segment in self.segments.iter() {
+            if color != Some(segment.color) {
+                let c = segment.color;
+                let _ = writeln!(content, "{:.3} {:.3} {:.3} RG", c.r, c.g, c.b);
+                color = Some(segment.color);
+            }
+
+            if width != Some(segment.width) {
+                let _ = writeln!(content, "{:.3} w", segment.width * scale);
+                width = Some(segment.width);
+            }
+
+            let from = place(segment.from);
+            let to = place(segment.to);
+
+            let _ = writeln!(
+                content,
+                "{:.3} {:.3} m {:.3} {:.3} l S",
+                from.x, from.y, to.x, to.y
+            );
+        }
+
+        content
+    }
+
+    /// Finishes the document, the drawing is fit inside the margins.
+    pub fn finish(&self, options: &PdfOptions) -> Vec<u8> {
+        let content = self.content(options);
+
+        let objects = [
+            String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+            String::from("<< /Type /Pages /Kids [3 0 R] /Count 1 >>"),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.3} {:.3}] /Contents 4 0 R /Resources << >> >>",
+                options.page.width * POINTS_PER_MM,
+                options.page.height * POINTS_PER_MM,
+            ),
+            format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                content.len(),
+                content
+            ),
+        ];
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            let _ = write!(pdf, "{} 0 obj\n{}\nendobj\n", i + 1, object);
+        }
+
+        // every entry of the cross-reference table is exactly 20 bytes
+        let xref = pdf.len();
+        let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+
+        for offset in offsets {
+            let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+        }
+
+        let _ = write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        );
+
+        pdf.into_bytes()
+    }
+}
+
+impl TurtleBackend for PdfBackend {
+    fn segment(&mut self, segment: &Segment) {
+        self.segments.push(*segment);
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt::Write;
+
+use ori::prelude::*;
+
+use super::Polyline;
+
+/// How a G-code plotter lifts and lowers its pen.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PenControl {
+    /// Moves the Z axis to the given heights, in millimeters.
+    Z { up: f32, down: f32 },
+    /// Runs the given commands, like `M3 S30` for a servo.
+    Commands { up: String, down: String },
+}
+
+/// The units G-code is written in, HPGL always uses plotter units.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GcodeUnits {
+    #[default]
+    Millimeters,
+    Inches,
+}
+
+impl GcodeUnits {
+    /// Converts `millimeters` to these units.
+    fn convert(self, millimeters: f32) -> f32 {
+        match self {
+            Self::Millimeters => millimeters,
+            Self::Inches => millimeters / 25.4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotterOptions {
+    /// The size of the paper in millimeters.
+    pub paper: Size,
+    /// The space left free on every side of the paper, in millimeters.
+    pub margin: f32,
+    /// Millimeters per turtle unit, by default the drawing is fit to the paper.
+    ///
+    /// A fixed scale may run off the paper.
+    pub scale: Option<f32>,
+    pub pen: PenControl,
+    /// The speed while drawing, in millimeters per minute.
+    pub feed_rate: f32,
+    /// Reorders and reverses polylines to shorten the moves with the pen up.
+    pub optimize_travel: bool,
+    /// Every length is given in millimeters and converted when it's written.
+    pub units: GcodeUnits,
+}
+
+impl PlotterOptions {
+    pub const A4: Size = Size::new(210.0, 297.0);
+    pub const A3: Size = Size::new(297.0, 420.0);
+    pub const LETTER: Size = Size::new(215.9, 279.4);
+}
+
+impl Default for PlotterOptions {
+    fn default() -> Self {
+        Self {
+            paper: Self::A4,
+            margin: 10.0,
+            scale: None,
+            pen: PenControl::Z { up: 5.0, down: 0.0 },
+            feed_rate: 3000.0,
+            optimize_travel: true,
+            units: GcodeUnits::default(),
+        }
+    }
+}
+
+/// Polylines laid out on the paper, ready to be written for a plotter.
+///
+/// Coordinates are in millimeters from the bottom left corner, with y up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plot {
+    pub polylines: Vec<Vec<Point>>,
+    /// The distance moved with the pen up, starting and ending at the origin.
+    pub travel: f32,
+    /// The travel in the order the turtle drew the polylines.
+    pub unoptimized_travel: f32,
+    options: PlotterOptions,
+}
+
+impl Plot {
+    pub fn new(polylines: &[Polyline], options: &PlotterOptions) -> Self {
+        let polylines = layout(polylines, options);
+        let unoptimized_travel = travel(&polylines);
+
+        let polylines = match options.optimize_travel {
+            true => nearest_neighbor(polylines),
+            false => polylines,
+        };
+
+        Self {
+            travel: travel(&polylines),
+            unoptimized_travel,
+            polylines,
+            options: options.clone(),
+        }
+    }
+
+    /// Writes HPGL, in plotter units of 0.025 mm.
+    pub fn to_hpgl(&self) -> String {
+        let unit = |x: f32| (x * 40.0).round() as i32;
+
+        let mut hpgl = String::from("IN;SP1;");
+        // the velocity is in centimeters per second
+        let _ = write!(hpgl, "VS{:.1};", self.options.feed_rate / 600.0);
+
+        for polyline in self.polylines.iter() {
+            let Some((first, rest)) = polyline.split_first() else {
+                continue;
+            };
+
+            let _ = write!(hpgl, "\nPU{},{};PD", unit(first.x), unit(first.y));
+
+            for (i, point) in rest.iter().enumerate() {
+                let separator = if i == 0 { "" } else { "," };
+                let _ = write!(hpgl, "{}{},{}", separator, unit(point.x), unit(point.y));
+            }
+
+            hpgl.push(';');
+        }
+
+        hpgl.push_str("\nPU0,0;SP0;\n");
+        hpgl
+    }
+
+    /// Writes G-code, in [`PlotterOptions::units`] with absolute
+    /// coordinates.
+    pub fn to_gcode(&self) -> String {
+        let units = self.options.units;
+        let feed_rate = units.convert(self.options.feed_rate);
+
+        let (up, down) = match self.options.pen {
+            PenControl::Z { up, down } => (
+                format!("G0 Z{:.3}", units.convert(up)),
+                format!("G1 Z{:.3} F{:.0}", units.convert(down), feed_rate),
+            ),
+            PenControl::Commands { ref up, ref down } => (up.clone(), down.clone()),
+        };
+
+        let mut gcode = String::from(match units {
+            GcodeUnits::Millimeters => "G21 ; millimeters\n",
+            GcodeUnits::Inches => "G20 ; inches\n",
+        });
+        gcode.push_str("G90 ; absolute coordinates\n");
+        let _ = writeln!(gcode, "{}", up);
+
+        for polyline in self.polylines.iter() {
+            let Some((first, rest)) = polyline.split_first() else {
+                continue;
+            };
+
+            let (x, y) = (units.convert(first.x), units.convert(first.y));
+            let _ = writeln!(gcode, "G0 X{:.3} Y{:.3}", x, y);
+            let _ = writeln!(gcode, "{}", down);
+
+            for point in rest.iter() {
+                let (x, y) = (units.convert(point.x), units.convert(point.y));
+                let _ = writeln!(gcode, "G1 X{:.3} Y{:.3} F{:.0}", x, y, feed_rate);
+            }
+
+            let _ = writeln!(gcode, "{}", up);
+        }
+
+        gcode.push_str("G0 X0 Y0\n");
+        gcode
+    }
+}
+
+/// Writes `polylines` as HPGL, see [`Plot::to_hpgl`].
+pub fn export_hpgl(polylines: &[Polyline], options: &PlotterOptions) -> String {
+    Plot::new(polylines, options).to_hpgl()
+}
+
+/// Writes `polylines` as G-code, see [`Plot::to_gcode`].
+pub fn export_gcode(polylines: &[Polyline], options: &PlotterOptions) -> String {
+    Plot::new(polylines, options).to_gcode()
+}
+
+/// Scales and centers the polylines on the paper, flipping y to point up.
+fn layout(polylines: &[Polyline], options: &PlotterOptions) -> Vec<Vec<Point>> {
+    let mut points = polylines.iter().flat_map(|polyline| &polyline.points);
+
+    let Some(&first) = points.next() else {
+        return Vec::new();
+    };
+
+    let mut bounds = Rect::new(first, first);
+    for &point in points {
+        bounds.min = Point::min(bounds.min, point);
+        bounds.max = Point::max(bounds.max, point);
+    }
+
+    let area = Size::new(
+        f32::max(options.paper.width - options.margin * 2.0, 0.0),
+        f32::max(options.paper.height - options.margin * 2.0, 0.0),
+    );
+
+    let scale = options.scale.unwrap_or_else(|| {
+        let width = area.width / bounds.width().max(f32::EPSILON);
+        let height = area.height / bounds.height().max(f32::EPSILON);
+        f32::min(width, height)
+    });
+
+    let offset = Vector::new(
+        options.margin + (area.width - bounds.width() * scale) / 2.0,
+        options.margin + (area.height - bounds.height() * scale) / 2.0,
+    );
+
+    let place = |point: &Point| {
+        Point::new(
+            offset.x + (point.x - bounds.min.x) * scale,
+            offset.y + (bounds.max.y - point.y) * scale,
+        )
+    };
+
+    (polylines.iter())
+        .map(|polyline| polyline.points.iter().map(place).collect())
+        .filter(|points: &Vec<Point>| !points.is_empty())
+        .collect()
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (b - a).length()
+}
+
+fn travel(polylines: &[Vec<Point>]) -> f32 {
+    let mut position = Point::ZERO;
+    let mut travel = 0.0;
+
+    for polyline in polylines.iter() {
+        if let (Some(&first), Some(&last)) = (polyline.first(), polyline.last()) {
+            travel += distance(position, first);
+            position = last;
+        }
+    }
+
+    travel + distance(position, Point::ZERO)
+}
+
+/// Greedily draws the closest polyline next, from whichever end is closer.
+///
+/// This is quadratic in the number of polylines, which is fine for what a
+/// plotter can draw in a reasonable time.
+fn nearest_neighbor(mut polylines: Vec<Vec<Point>>) -> Vec<Vec<Point>> {
+    let mut ordered = Vec::with_capacity(polylines.len());
+    let mut position = Point::ZERO;
+
+    while !polylines.is_empty() {
+        let mut best = (0, false, f32::INFINITY);
+
+        for (i, polyline) in polylines.iter().enumerate() {
+            let start = distance(position, polyline[0]);
+            let end = distance(position, polyline[polyline.len() - 1]);
+
+            if start < best.2 {
+                best = (i, false, start);
+            }
+
+            if end < best.2 {
+                best = (i, true, end);
+            }
+        }
+
+        let (index, reverse, _) = best;
+        let mut polyline = polylines.swap_remove(index);
+
+        if reverse {
+            polyline.reverse();
+        }
+
+        position = polyline[polyline.len() - 1];
+        ordered.push(polyline);
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Vec<Polyline> {
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+
+        vec![Polyline {
+            points: points.map(|(x, y)| Point::new(x, y)).to_vec(),
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn gcode_in_inches() {
+        let options = PlotterOptions {
+            scale: Some(25.4),
+            margin: 0.0,
+            paper: Size::new(25.4, 25.4),
+            units: GcodeUnits::Inches,
+            feed_rate: 2540.0,
+            ..Default::default()
+        };
+
+        let gcode = export_gcode(&square(), &options);
+
+        assert!(gcode.starts_with("G20 ; inches\n"));
+        assert!(gcode.contains("G1 X1.000 Y0.000 F100\n"));
+        assert!(gcode.contains(&format!("G0 Z{:.3}\n", 5.0 / 25.4)));
+    }
+
+    #[test]
+    fn fixed_scale() {
+        let options = PlotterOptions {
+            scale: Some(10.0),
+            margin: 0.0,
+            paper: Size::new(100.0, 100.0),
+            optimize_travel: false,
+            ..Default::default()
+        };
+
+        // a 10 mm square in the middle of the paper
+        let plot = Plot::new(&square(), &options);
+        let xs: Vec<_> = plot.polylines[0].iter().map(|point| point.x).collect();
+        assert_eq!(xs, [45.0, 55.0, 55.0, 45.0, 45.0]);
+    }
+}
@@ -0,0 +1,396 @@
+use std::io::Write;
+
+use ori::prelude::*;
+
+use crate::{error::Result, LilyError};
+
+/// Each pixel is sampled on a grid of this many points per side.
+const SAMPLES: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngOptions {
+    /// The longest side of the image in pixels.
+    pub size: u32,
+    /// Fills the image before drawing, the image is transparent by default.
+    pub background: Option<Color>,
+    /// Roughly the number of pixels rendered at once, as a square.
+    ///
+    /// The image is rendered in strips of full rows covering about
+    /// `tile_size * tile_size` pixels, which bounds the memory used for any
+    /// image size.
+    pub tile_size: u32,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            background: None,
+            tile_size: 2048,
+        }
+    }
+}
+
+/// Maps mesh positions to pixels, fitting the mesh inside the image.
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    width: usize,
+    height: usize,
+    min: Point,
+    scale: f32,
+}
+
+impl Layout {
+    fn new(mesh: &Mesh, size: u32) -> Self {
+        let mut positions = mesh.vertices.iter().map(|vertex| vertex.position);
+
+        let Some(first) = positions.next() else {
+            return Self {
+                width: 1,
+                height: 1,
+                min: Point::ZERO,
+                scale: 1.0,
+            };
+        };
+
+        let mut bounds = Rect::new(first, first);
+        for position in positions {
+            bounds.min = Point::min(bounds.min, position);
+            bounds.max = Point::max(bounds.max, position);
+        }
+
+        let extent = f32::max(bounds.width(), bounds.height()).max(f32::EPSILON);
+        let scale = size as f32 / extent;
+
+        Self {
+            width: ((bounds.width() * scale).ceil() as usize).max(1),
+            height: ((bounds.height() * scale).ceil() as usize).max(1),
+            min: bounds.min,
+            scale,
+        }
+    }
+
+    fn place(&self, point: Point) -> Point {
+        Point::new(
+            (point.x - self.min.x) * self.scale,
+            (point.y - self.min.y) * self.scale,
+        )
+    }
+}
+
+/// A horizontal strip of the image, in straight alpha.
+struct Strip {
+    top: usize,
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Strip {
+    fn new(top: usize, width: usize, height: usize, background: Option<Color>) -> Self {
+        let fill = background.map_or([0.0; 4], |c| [c.r, c.g, c.b, c.a]);
+
+        Self {
+            top,
+            width,
+            height,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    /// Blends `color` over the pixel by the fraction of it that's covered.
+    fn blend(&mut self, x: usize, y: usize, color: [f32; 4], coverage: f32) {
+        let pixel = &mut self.pixels[(y - self.top) * self.width + x];
+
+        let alpha = color[3] * coverage;
+        let below = pixel[3] * (1.0 - alpha);
+        let out = alpha + below;
+
+        if out <= 0.0 {
+            return;
+        }
+
+        for i in 0..3 {
+            pixel[i] = (color[i] * alpha + pixel[i] * below) / out;
+        }
+
+        pixel[3] = out;
+    }
+
+    fn row(&self, y: usize, bytes: &mut Vec<u8>) {
+        bytes.clear();
+
+        for pixel in self.pixels[y * self.width..(y + 1) * self.width].iter() {
+            for channel in pixel {
+                bytes.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+    }
+
+    /// Draws every triangle of `mesh` overlapping the strip.
+    ///
+    /// Samples are placed in image coordinates, so a pixel comes out the same
+    /// whichever strip it's drawn in.
+    fn draw(&mut self, mesh: &Mesh, layout: &Layout) {
+        for triangle in mesh.indices.chunks_exact(3) {
+            let vertex = |i: usize| mesh.vertices[triangle[i] as usize];
+            let vertices = [vertex(0), vertex(1), vertex(2)];
+            let points = vertices.map(|vertex| layout.place(vertex.position));
+
+            self.triangle(points, vertices.map(|vertex| vertex.color));
+        }
+    }
+
+    fn triangle(&mut self, points: [Point; 3], colors: [Color; 3]) {
+        let [a, b, c] = points;
+
+        let edge =
+            |a: Point, b: Point, p: Point| (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+
+        let area = edge(a, b, c);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+
+        let min_x = f32::min(a.x, f32::min(b.x, c.x)).floor().max(0.0) as usize;
+        let max_x = (f32::max(a.x, f32::max(b.x, c.x)).ceil().max(0.0) as usize).min(self.width);
+        let min_y = (f32::min(a.y, f32::min(b.y, c.y)).floor().max(0.0) as usize).max(self.top);
+        let max_y = (f32::max(a.y, f32::max(b.y, c.y)).ceil().max(0.0) as usize)
+            .min(self.top + self.height);
+
+        // samples exactly on an edge only count for one of the triangles
+        // sharing it, like the top-left rule of gpu rasterizers
+        let owns = |a: Point, b: Point| {
+            let (dx, dy) = (b.x - a.x, b.y - a.y);
+            let (dx, dy) = if area < 0.0 { (-dx, -dy) } else { (dx, dy) };
+            dy < 0.0 || (dy == 0.0 && dx > 0.0)
+        };
+        let owned = [owns(b, c), owns(c, a), owns(a, b)];
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let mut covered = 0;
+                let mut color = [0.0; 4];
+
+                for sy in 0..SAMPLES {
+                    for sx in 0..SAMPLES {
+                        let p = Point::new(
+                            x as f32 + (sx as f32 + 0.5) / SAMPLES as f32,
+                            y as f32 + (sy as f32 + 0.5) / SAMPLES as f32,
+                        );
+
+                        let weights = [
+                            edge(b, c, p) / area,
+                            edge(c, a, p) / area,
+                            edge(a, b, p) / area,
+                        ];
+                        let inside = (weights.iter().zip(owned))
+                            .all(|(&w, owned)| w > 0.0 || (w == 0.0 && owned));
+
+                        if !inside {
+                            continue;
+                        }
+
+                        covered += 1;
+
+                        for (w, c) in weights.iter().zip(colors) {
+                            color[0] += w * c.r;
+                            color[1] += w * c.g;
+                            color[2] += w * c.b;
+                            color[3] += w * c.a;
+                        }
+                    }
+                }
+
+                if covered > 0 {
+                    let color = color.map(|channel| channel / covered as f32);
+                    let coverage = covered as f32 / (SAMPLES * SAMPLES) as f32;
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+}
+
+/// Rasterizes `mesh` into a PNG written to `writer`, fitting it inside the image.
+///
+/// The image is rendered and encoded a strip at a time, see
+/// [`PngOptions::tile_size`], so very large images don't need a framebuffer
+/// of their own.
+pub fn export_png(mesh: &Mesh, options: &PngOptions, writer: impl Write) -> Result<()> {
+    let layout = Layout::new(mesh, options.size);
+    let export_error = |err: png::EncodingError| LilyError::Export(err.to_string());
+
+    let mut encoder = png::Encoder::new(writer, layout.width as u32, layout.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(export_error)?;
+    let mut stream = writer.stream_writer().map_err(export_error)?;
+
+    let tile_area = options.tile_size.max(1) as usize * options.tile_size.max(1) as usize;
+    let strip_height = (tile_area / layout.width).clamp(1, layout.height);
+
+    let mut row = Vec::with_capacity(layout.width * 4);
+
+    for top in (0..layout.height).step_by(strip_height) {
+        let height = usize::min(strip_height, layout.height - top);
+
+        let mut strip = Strip::new(top, layout.width, height, options.background);
+        strip.draw(mesh, &layout);
+
+        for y in 0..height {
+            strip.row(y, &mut row);
+            stream
+                .write_all(&row)
+                .map_err(|err| LilyError::Export(err.to_string()))?;
+        }
+    }
+
+    stream.finish().map_err(export_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(color: Color) -> Mesh {
+        let mut mesh = Mesh::new();
+
+        for (x, y) in [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)] {
+            mesh.vertices.push(Vertex {
+                position: Point::new(x, y),
+                tex_coords: Point::ZERO,
+                color,
+            });
+        }
+
+        mesh.indices = vec![0, 1, 2, 0, 2, 3];
+        mesh
+    }
+
+    fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        (info.width, info.height, pixels)
+    }
+
+    fn render(mesh: &Mesh, size: u32, tile_size: u32) -> (u32, u32, Vec<u8>) {
+        let options = PngOptions {
+            size,
+            tile_size,
+            ..Default::default()
+        };
+
+        let mut png = Vec::new();
+        export_png(mesh, &options, &mut png).unwrap();
+        decode(&png)
+    }
+
+    #[test]
+    fn strips_match_a_single_render() {
+        // a fan of thin triangles crossing every strip boundary
+        let mut mesh = square(Color::rgb(0.2, 0.6, 0.3));
+        for i in 0..8 {
+            let angle = i as f32 * 0.4;
+            let first = mesh.vertices.len() as u32;
+
+            for (x, y) in [
+                (5.0, 5.0),
+                (5.0 + 6.0 * angle.cos(), 5.0 + 6.0 * angle.sin()),
+            ] {
+                mesh.vertices.push(Vertex {
+                    position: Point::new(x, y),
+                    tex_coords: Point::ZERO,
+                    color: Color::rgba(0.9, 0.3, 0.4, 0.7),
+                });
+            }
+
+            mesh.vertices.push(Vertex {
+                position: Point::new(
+                    5.0 + 6.0 * (angle + 0.1).cos(),
+                    5.0 + 6.0 * (angle + 0.1).sin(),
+                ),
+                tex_coords: Point::ZERO,
+                color: Color::rgba(0.9, 0.3, 0.4, 0.7),
+            });
+            mesh.indices.extend([first, first + 1, first + 2]);
+        }
+
+        let whole = render(&mesh, 97, 4096);
+
+        // strips of a single row, and of rows that don't divide the height
+        for tile_size in [1, 13, 40] {
+            assert_eq!(render(&mesh, 97, tile_size), whole);
+        }
+    }
+
+    #[test]
+    fn strips_have_no_seams() {
+        let (width, height, pixels) = render(&square(Color::rgb(1.0, 0.0, 0.0)), 64, 9);
+        assert_eq!((width, height), (64, 64));
+
+        // every pixel is inside the square, including those on the rows
+        // where one strip ends and the next begins, only the diagonal the
+        // two triangles share is covered by halves
+        for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 64, i / 64);
+
+            if x.abs_diff(y) > 1 {
+                assert_eq!(pixel, [255, 0, 0, 255], "pixel {}, {}", x, y);
+            }
+        }
+    }
+
+    /// A triangle in the top left half of a 20 by 10 rectangle.
+    fn wide_triangle() -> Mesh {
+        let mut mesh = Mesh::new();
+
+        for (x, y) in [(0.0, 0.0), (20.0, 0.0), (0.0, 10.0)] {
+            mesh.vertices.push(Vertex {
+                position: Point::new(x, y),
+                tex_coords: Point::ZERO,
+                color: Color::rgb(0.0, 0.0, 1.0),
+            });
+        }
+
+        mesh.indices = vec![0, 1, 2];
+        mesh
+    }
+
+    #[test]
+    fn longest_side_is_the_size() {
+        for size in [16, 300, 640] {
+            let (width, height, _) = render(&wide_triangle(), size, 2048);
+            assert_eq!((width, height), (size, size / 2));
+        }
+    }
+
+    #[test]
+    fn backgrounds() {
+        let pixel = |pixels: &[u8], x: usize, y: usize| {
+            let i = (y * 100 + x) * 4;
+            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+        };
+
+        // transparent by default
+        let (_, _, pixels) = render(&wide_triangle(), 100, 2048);
+        assert_eq!(pixel(&pixels, 5, 5), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 95, 45), [0, 0, 0, 0]);
+
+        let options = PngOptions {
+            size: 100,
+            background: Some(Color::rgb(1.0, 1.0, 0.0)),
+            ..Default::default()
+        };
+
+        let mut png = Vec::new();
+        export_png(&wide_triangle(), &options, &mut png).unwrap();
+
+        let (_, _, pixels) = decode(&png);
+        assert_eq!(pixel(&pixels, 5, 5), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 95, 45), [255, 255, 0, 255]);
+    }
+}
@@ -0,0 +1,61 @@
+use ori::prelude::*;
+
+use crate::system::{Segment, TurtleBackend};
+
+/// A run of connected segments, drawn without lifting the pen.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<Point>,
+    /// The width of the first segment.
+    pub width: f32,
+    pub color: Color,
+}
+
+impl Polyline {
+    /// The length of the line through every point.
+    pub fn length(&self) -> f32 {
+        (self.points.windows(2))
+            .map(|pair| (pair[1] - pair[0]).length())
+            .sum()
+    }
+}
+
+/// Joins consecutive segments into [`Polyline`]s, a new one is started
+/// whenever the turtle moves without drawing or the color changes.
+#[derive(Clone, Debug, Default)]
+pub struct PolylineBackend {
+    polylines: Vec<Polyline>,
+}
+
+impl PolylineBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn polylines(&self) -> &[Polyline] {
+        &self.polylines
+    }
+
+    pub fn finish(self) -> Vec<Polyline> {
+        self.polylines
+    }
+}
+
+impl TurtleBackend for PolylineBackend {
+    fn segment(&mut self, segment: &Segment) {
+        if let Some(polyline) = self.polylines.last_mut() {
+            let connected = polyline.points.last() == Some(&segment.from);
+
+            if connected && polyline.color == segment.color {
+                polyline.points.push(segment.to);
+                return;
+            }
+        }
+
+        self.polylines.push(Polyline {
+            points: vec![segment.from, segment.to],
+            width: segment.width,
+            color: segment.color,
+        });
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::Write;
+
+use ori::prelude::*;
+
+use crate::system::{format_color, Segment, TurtleBackend};
+
+/// Writes every [`Segment`] as a stroked `<line>`, keeping the drawing vector.
+#[derive(Clone, Debug, Default)]
+pub struct SvgBackend {
+    segments: Vec<Segment>,
+}
+
+impl SvgBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of lines drawn so far.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// The bounds of every segment, including its width.
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut segments = self.segments.iter();
+        let first = segments.next()?;
+
+        let mut bounds = Rect::new(first.from, first.from);
+
+        for segment in self.segments.iter() {
+            let margin = Vector::new(segment.width, segment.width) / 2.0;
+
+            for point in [segment.from, segment.to] {
+                bounds.min = Point::min(bounds.min, point - margin);
+                bounds.max = Point::max(bounds.max, point + margin);
+            }
+        }
+
+        Some(bounds)
+    }
+
+    /// Finishes the document, the longest side of the image is `size` units.
+    pub fn finish(&self, size: f32) -> String {
+        let mut svg = header(self.bounds(), size);
+
+        svg.push_str("<g fill=\"none\" stroke-linecap=\"round\">\n");
+        self.write_lines(&mut svg);
+        svg.push_str("</g>\n</svg>\n");
+
+        svg
+    }
+
+    pub(super) fn write_lines(&self, svg: &mut String) {
+        for segment in self.segments.iter() {
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+                segment.from.x,
+                segment.from.y,
+                segment.to.x,
+                segment.to.y,
+                format_color(segment.color),
+                segment.width,
+            );
+        }
+    }
+}
+
+/// Opens an `<svg>` showing `bounds`, the longest side of the image is `size` units.
+fn header(bounds: Option<Rect>, size: f32) -> String {
+    let mut svg = String::new();
+
+    // an empty drawing still produces a valid document
+    let bounds = bounds.unwrap_or(Rect::new(Point::ZERO, Point::new(1.0, 1.0)));
+    let extent = f32::max(bounds.width(), bounds.height()).max(f32::EPSILON);
+    let scale = size / extent;
+
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+        bounds.min.x,
+        bounds.min.y,
+        bounds.width(),
+        bounds.height(),
+        bounds.width() * scale,
+        bounds.height() * scale,
+    );
+
+    svg
+}
+
+/// The longest side of the image [`mesh_to_svg`] writes, the default size of
+/// the command line's exports.
+pub const MESH_SVG_SIZE: f32 = 1024.0;
+
+/// Writes the triangles of `mesh` as filled paths, showing `bounds`, which is
+/// usually [`mesh_bounds`](crate::system::mesh_bounds).
+///
+/// Unlike [`SvgBackend`] this keeps what's only in the mesh, like shading and
+/// the tubes of 3D systems. Meshes already have `y` pointing down like SVG
+/// does, so the drawing is the right way up. Each triangle is filled with the
+/// average of its vertex colors, and runs of triangles with the same color
+/// share a path so there are no seams between them.
+///
+/// The longest side of the image is [`MESH_SVG_SIZE`] units.
+pub fn mesh_to_svg(mesh: &Mesh, bounds: Rect) -> String {
+    let mut svg = header(Some(bounds), MESH_SVG_SIZE);
+    // the color and outline of the path being built
+    let mut path: Option<(String, String)> = None;
+
+    fn finish(svg: &mut String, path: Option<(String, String)>) {
+        if let Some((color, d)) = path {
+            let _ = writeln!(svg, r#"<path fill="{}" d="{}"/>"#, color, d.trim_end());
+        }
+    }
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let vertex = |i: usize| mesh.vertices[triangle[i] as usize];
+        let vertices = [vertex(0), vertex(1), vertex(2)];
+
+        let colors = vertices.map(|vertex| vertex.color);
+        let average = |channel: fn(Color) -> f32| colors.map(channel).iter().sum::<f32>() / 3.0;
+        let color = format_color(Color::rgba(
+            average(|c| c.r),
+            average(|c| c.g),
+            average(|c| c.b),
+            average(|c| c.a),
+        ));
+
+        if path.as_ref().is_some_and(|(other, _)| *other != color) {
+            finish(&mut svg, path.take());
+        }
+
+        let (_, d) = path.get_or_insert_with(|| (color, String::new()));
+        let [a, mut b, mut c] = vertices.map(|vertex| vertex.position);
+
+        // triangles wound the other way would cut holes where they overlap
+        if (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+
+        let _ = write!(d, "M{} {}L{} {}L{} {}Z ", a.x, a.y, b.x, b.y, c.x, c.y);
+    }
+
+    finish(&mut svg, path);
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// How an animated SVG plays, see [`animated_svg`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgAnimation {
+    /// The time it takes to show every frame, in seconds.
+    pub duration: f32,
+    /// Starts over after the last frame instead of stopping on it.
+    pub repeat: bool,
+}
+
+impl Default for SvgAnimation {
+    fn default() -> Self {
+        Self {
+            duration: 4.0,
+            repeat: true,
+        }
+    }
+}
+
+/// Writes `frames` into one SVG, showing them one after the other, usually
+/// the iterations of a system.
+///
+/// Frames are groups whose visibility is animated with SMIL, only the last one
+/// is visible without animation so viewers that don't animate show the result.
+pub fn animated_svg(frames: &[SvgBackend], size: f32, animation: &SvgAnimation) -> String {
+    let mut bounds: Option<Rect> = None;
+
+    for frame in frames.iter().filter_map(SvgBackend::bounds) {
+        bounds = Some(match bounds {
+            Some(bounds) => Rect::new(
+                Point::min(bounds.min, frame.min),
+                Point::max(bounds.max, frame.max),
+            ),
+            None => frame,
+        });
+    }
+
+    let mut svg = header(bounds, size);
+    svg.push_str("<g fill=\"none\" stroke-linecap=\"round\">\n");
+
+    let count = frames.len();
+    let repeat = match animation.repeat {
+        true => "indefinite",
+        false => "1",
+    };
+
+    for (i, frame) in frames.iter().enumerate() {
+        let last = i + 1 == count;
+        let start = i as f32 / count as f32;
+        let end = (i + 1) as f32 / count as f32;
+
+        let visibility = if last { "visible" } else { "hidden" };
+        let _ = writeln!(svg, r#"<g visibility="{}">"#, visibility);
+
+        let keys = match (i, last) {
+            (_, true) if count == 1 => None,
+            (_, true) => Some((String::from("hidden;visible"), format!("0;{}", start))),
+            (0, false) => Some((String::from("visible;hidden"), format!("0;{}", end))),
+            _ => Some((
+                String::from("hidden;visible;hidden"),
+                format!("0;{};{}", start, end),
+            )),
+        };
+
+        if let Some((values, key_times)) = keys {
+            let _ = writeln!(
+                svg,
+                r#"<animate attributeName="visibility" values="{}" keyTimes="{}" calcMode="discrete" dur="{}s" repeatCount="{}" fill="freeze"/>"#,
+                values, key_times, animation.duration, repeat,
+            );
+        }
+
+        frame.write_lines(&mut svg);
+        svg.push_str("</g>\n");
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+impl TurtleBackend for SvgBackend {
+    fn segment(&mut self, segment: &Segment) {
+        self.segments.push(*segment);
+    }
+}
@@ -0,0 +1,100 @@
+//! Noticing when a file is changed by another program, which isn't available
+//! on the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::{
+        path::{Path, PathBuf},
+        sync::mpsc::{self, Receiver},
+    };
+
+    use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+    /// Whether `event` changed the file at `path`.
+    pub fn touches(event: &Event, path: &Path) -> bool {
+        let touches = (event.paths.iter())
+            .any(|other| other.file_name().is_some() && other.file_name() == path.file_name());
+
+        touches && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+    }
+
+    /// The directory to watch for changes to `path`.
+    ///
+    /// Editors that save atomically write a new file and rename it over the
+    /// old one, which ends a watch on the file itself, so the directory is
+    /// watched instead.
+    pub fn directory(path: &Path) -> &Path {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    }
+
+    /// Watches a single file, collecting changes until they're asked for.
+    pub struct FileWatcher {
+        path: PathBuf,
+        receiver: Receiver<notify::Result<Event>>,
+        // events stop when the watcher is dropped
+        _watcher: RecommendedWatcher,
+    }
+
+    impl FileWatcher {
+        pub fn new(path: impl Into<PathBuf>) -> Result<Self, String> {
+            let path = path.into();
+
+            let (sender, receiver) = mpsc::channel();
+            let mut watcher = notify::recommended_watcher(sender).map_err(|err| err.to_string())?;
+
+            watcher
+                .watch(directory(&path), RecursiveMode::NonRecursive)
+                .map_err(|err| err.to_string())?;
+
+            Ok(Self {
+                path,
+                receiver,
+                _watcher: watcher,
+            })
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Whether the file changed since this was last called.
+        pub fn changed(&self) -> bool {
+            let mut changed = false;
+
+            // drain everything, a single save is often several events
+            while let Ok(event) = self.receiver.try_recv() {
+                changed |= event.is_ok_and(|event| touches(&event, &self.path));
+            }
+
+            changed
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod native {
+    use std::path::{Path, PathBuf};
+
+    pub struct FileWatcher {
+        path: PathBuf,
+    }
+
+    impl FileWatcher {
+        pub fn new(_path: impl Into<PathBuf>) -> Result<Self, String> {
+            Err(String::from("watching files isn't available on the web"))
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        pub fn changed(&self) -> bool {
+            false
+        }
+    }
+}
+
+pub use native::*;
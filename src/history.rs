@@ -0,0 +1,89 @@
+//! Undoing and redoing edits of the definition.
+
+use std::collections::VecDeque;
+
+use lily::project::Definition;
+
+/// The most edits that can be undone, older ones are forgotten.
+const MAX_UNDOS: usize = 100;
+
+/// What an edit changed, consecutive edits of the same field, like typing
+/// into it, are undone together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Axiom,
+    Rules,
+    Instructions,
+    Iterations,
+    Blend,
+    Jitter,
+    Gradient,
+    Tropism,
+    Falloff,
+    LeafFalloff,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undos: VecDeque<Definition>,
+    redos: Vec<Definition>,
+    /// The field of the last edit, `None` after edits that are undone alone.
+    last: Option<Field>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the definition from before an edit of `field`, edits without
+    /// a field are always undone on their own.
+    pub fn record(&mut self, field: Option<Field>, before: Definition) {
+        self.redos.clear();
+
+        if field.is_some() && field == self.last {
+            return;
+        }
+
+        self.last = field;
+        self.undos.push_back(before);
+
+        if self.undos.len() > MAX_UNDOS {
+            self.undos.pop_front();
+        }
+    }
+
+    /// Ends the entry edits are being joined into, the next edit gets one of
+    /// its own.
+    pub fn seal(&mut self) {
+        self.last = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undos.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redos.is_empty()
+    }
+
+    /// The definition from before the last edit, `current` can be redone.
+    pub fn undo(&mut self, current: Definition) -> Option<Definition> {
+        let previous = self.undos.pop_back()?;
+
+        self.redos.push(current);
+        self.last = None;
+
+        Some(previous)
+    }
+
+    /// The definition the last undo went back from, `current` can be undone.
+    pub fn redo(&mut self, current: Definition) -> Option<Definition> {
+        let next = self.redos.pop()?;
+
+        self.undos.push_back(current);
+        self.last = None;
+
+        Some(next)
+    }
+}
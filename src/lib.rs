@@ -0,0 +1,12 @@
+//! Lily, a playground for L-systems.
+
+pub mod diagnostics;
+pub mod error;
+pub mod export;
+pub mod presets;
+pub mod project;
+mod render;
+pub mod system;
+
+pub use error::LilyError;
+pub use render::{render, render_svg, render_svg_within, render_within, Limits, SVG_SIZE};
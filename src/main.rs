@@ -1,174 +1,1831 @@
-mod system;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+mod clipboard;
+mod file_watch;
+mod history;
+mod worker;
 
+use std::{mem, path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use lily::{
+    diagnostics::{self, Diagnostic},
+    error::BracketError,
+    export::{self, ContactSheetOptions, PdfBackend, PdfOptions, PngOptions, SvgBackend},
+    presets::{Preset, PRESETS},
+    project::{Definition, Project, Snapshot, ViewOptions},
+    system::{
+        self, DensityShading, Derivation, FitMode, IncrementalMesh, Instruction, Instructions,
+        JoinStyle, Pcg32, Rules, SystemOptions, TurtleProbe,
+    },
+    LilyError,
+};
 use ori::prelude::*;
-use system::{Instruction, Instructions, Rules, SystemOptions};
+
+use crate::{
+    file_watch::FileWatcher,
+    history::{Field, History},
+    worker::{Finished, Job, JobFinished, SheetJob, SheetProgress, Worker},
+};
 
 const DISPLAY_SIZE: Size = Size::all(450.0);
 const INITIAL_AXIOM: &str = "A";
 const INITIAL_RULES: &str = "A -> F[-A]F[-A]+FA\nF -> FF";
 const INITIAL_INSTRUCTIONS: &str = include_str!("instructions.txt");
 
-struct Data {
-    mesh: Option<Mesh>,
-    axiom: String,
-    rules: String,
-    instructions: String,
-    options: SystemOptions,
-    iterations: String,
+/// The number of frames the animation takes to draw the whole mesh, at a
+/// speed of 1.
+const ANIMATION_FRAMES: usize = 180;
+
+/// The fastest the animation can be played.
+const MAX_ANIMATION_SPEED: f32 = 8.0;
+
+/// The stages of turning the definition into the mesh on screen, in order.
+///
+/// Marking a stage dirty also invalidates every stage after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    /// Applying the rules to the axiom.
+    Expand,
+    /// Turning the expanded string into instructions.
+    Interpret,
+    /// Generating the mesh from the instructions.
+    Mesh,
+    /// Recoloring the mesh, which leaves its geometry alone.
+    Shade,
+    /// Fitting the mesh into the display.
+    Fit,
+}
+
+/// The project file the app was opened with.
+struct OpenFile {
+    watcher: FileWatcher,
+    /// The definition as it was last read from the file.
+    saved: Definition,
+}
+
+/// Blending from the pinned definition to another, see [`Definition::blend`].
+struct Blend {
+    /// The definition blended towards.
+    target: Definition,
+    /// The definition the blend last produced.
+    applied: Definition,
+    t: f32,
+}
+
+/// The blend slider moves in steps this big, which limits how often a drag
+/// regenerates the system.
+const BLEND_STEP: f32 = 0.01;
+
+struct Data {
+    axiom: String,
+    rules_text: String,
+    rules: Rules,
+    /// The lines of the rules that couldn't be parsed and were left out.
+    rule_errors: Vec<LilyError>,
+    instructions_text: String,
+    instructions: Instructions,
+    /// The lines of the instructions that couldn't be parsed or are never
+    /// used.
+    instruction_problems: Vec<Diagnostic>,
+    options: SystemOptions,
+    /// The trunk and tip colors as entered, see [`SystemOptions::trunk_color`].
+    gradient_text: [String; 2],
+    /// The minimum width as entered, see [`SystemOptions::min_width`].
+    min_width: String,
+    iterations: String,
+    /// The most symbols the expansion may grow to.
+    symbol_limit: String,
+    /// The limit the last expansion was stopped at, the previous mesh is kept.
+    truncated: Option<usize>,
+    /// The unmatched bracket in the expansion, the previous mesh is kept.
+    brackets: Option<BracketError>,
+    seed: u64,
+    /// The factor entered for "Scale lengths".
+    length_factor: String,
+    /// The longest side entered for "Export PNG", in pixels.
+    png_size: String,
+    /// Whether exported PNGs are transparent instead of filled with the
+    /// background color.
+    png_transparent: bool,
+    /// The definition the blend slider starts from.
+    pinned: Option<Definition>,
+    blend: Option<Blend>,
+    /// The snapshots of the loaded project.
+    snapshots: Vec<Snapshot>,
+    /// The definitions edits can be undone to.
+    history: History,
+    /// The system from before the first preset was picked.
+    custom: Option<Definition>,
+    /// A message for the user, like an error from loading.
+    message: Option<String>,
+    dirty: Option<Stage>,
+    /// The stages run so far, a job counts as the stage it starts at.
+    #[cfg(test)]
+    ran: Vec<Stage>,
+    /// Expands and generates the mesh, see [`worker`].
+    worker: Worker,
+    /// The first stage of the job the worker is running.
+    running: Option<Stage>,
+    /// The expansions of the system so far, it's with the worker while a job
+    /// is running.
+    derivation: Option<Derivation>,
+    tree: String,
+    stream: Vec<Instruction>,
+    /// Keeps the geometry of the unchanged start of the stream between edits,
+    /// it's with the worker while a job is running.
+    generator: IncrementalMesh,
+    raw_mesh: Arc<Mesh>,
+    /// How long the job that generated the mesh took.
+    generation_time: Option<Duration>,
+    shading: DensityShading,
+    /// The raw mesh after shading, shared with it when there's no shading.
+    shaded_mesh: Arc<Mesh>,
+    /// The scale and offset the mesh was fit into the display with.
+    fit: (f32, Vector),
+    /// The zoom and pan on top of fitting the mesh into the display.
+    view: ViewOptions,
+    /// Where the pointer was while dragging the view.
+    drag: Option<Point>,
+    /// Whether new meshes are drawn a few triangles at a time.
+    animating: bool,
+    /// Whether the animation is advancing, it pauses at the end.
+    playing: bool,
+    /// How much of the mesh the animation has drawn, from 0 to 1.
+    playhead: f32,
+    /// How many times faster than [`ANIMATION_FRAMES`] the animation plays.
+    animation_speed: f32,
+    /// Whether the turtle inspector is open.
+    inspecting: bool,
+    /// The number of instructions shown while inspecting.
+    scrub: usize,
+    file: Option<OpenFile>,
+    /// The path entered for "Save" and "Load".
+    file_path: String,
+    /// The open file changed on disk while there were unsaved changes.
+    reload_pending: bool,
+}
+
+impl Data {
+    fn new() -> Self {
+        let mut instructions_text = String::from(INITIAL_INSTRUCTIONS);
+        instructions_text.pop();
+        let (instructions, instruction_problems) = Self::parse_instructions(&instructions_text);
+
+        Self {
+            axiom: String::from(INITIAL_AXIOM),
+            rules_text: String::from(INITIAL_RULES),
+            rules: Rules::parse(INITIAL_RULES),
+            rule_errors: Vec::new(),
+            instructions,
+            instruction_problems,
+            instructions_text,
+            options: SystemOptions::default(),
+            gradient_text: Self::gradient_text(&SystemOptions::default()),
+            min_width: SystemOptions::default().min_width.to_string(),
+            iterations: String::from("7"),
+            symbol_limit: system::DEFAULT_SYMBOL_LIMIT.to_string(),
+            truncated: None,
+            brackets: None,
+            seed: 0,
+            length_factor: String::from("2"),
+            png_size: String::from("4096"),
+            png_transparent: true,
+            pinned: None,
+            blend: None,
+            snapshots: Vec::new(),
+            history: History::new(),
+            custom: None,
+            message: None,
+            dirty: Some(Stage::Expand),
+            #[cfg(test)]
+            ran: Vec::new(),
+            worker: Worker::new(),
+            running: None,
+            derivation: None,
+            tree: String::new(),
+            stream: Vec::new(),
+            generator: IncrementalMesh::new(),
+            raw_mesh: Arc::new(Mesh::new()),
+            generation_time: None,
+            shading: DensityShading::default(),
+            shaded_mesh: Arc::new(Mesh::new()),
+            fit: (1.0, Vector::ZERO),
+            view: ViewOptions::default(),
+            drag: None,
+            animating: false,
+            playing: false,
+            playhead: 0.0,
+            animation_speed: 1.0,
+            inspecting: false,
+            scrub: 0,
+            file: None,
+            file_path: String::from("system.lily"),
+            reload_pending: false,
+        }
+    }
+
+    fn definition(&self) -> Definition {
+        Definition {
+            axiom: self.axiom.clone(),
+            rules: self.rules_text.clone(),
+            instructions: self.instructions_text.clone(),
+            iterations: self.iterations(),
+            seed: self.seed,
+            options: self.options.clone(),
+        }
+    }
+
+    /// Replaces the definition, the current one can be brought back with undo.
+    fn load_definition(&mut self, definition: Definition) {
+        if self.definition() != definition {
+            self.record(None);
+        }
+
+        self.replace_definition(definition);
+    }
+
+    /// Replaces the definition without recording it in the history.
+    fn replace_definition(&mut self, definition: Definition) {
+        self.axiom = definition.axiom;
+        (self.rules, self.rule_errors) = Self::parse_rules(&definition.rules);
+        self.rules_text = definition.rules;
+        (self.instructions, self.instruction_problems) =
+            Self::parse_instructions(&definition.instructions);
+        self.instructions_text = definition.instructions;
+        self.iterations = definition.iterations.to_string();
+        self.seed = definition.seed;
+        self.gradient_text = Self::gradient_text(&definition.options);
+        self.min_width = definition.options.min_width.to_string();
+        self.options = definition.options;
+
+        self.invalidate(Stage::Expand);
+    }
+
+    fn project(&self) -> Project {
+        Project {
+            snapshots: self.snapshots.clone(),
+            view: self.view.clone(),
+            ..Project::new(self.definition())
+        }
+    }
+
+    fn load_project(&mut self, project: Project) {
+        self.snapshots = project.snapshots;
+        self.view = project.view;
+        self.load_definition(project.definition);
+    }
+
+    /// Replaces the system with `preset`, keeping the system being edited so
+    /// it can be brought back.
+    fn load_preset(&mut self, preset: &Preset) {
+        let definition = self.definition();
+
+        if !PRESETS
+            .iter()
+            .any(|preset| preset.definition() == definition)
+        {
+            self.custom = Some(definition);
+        }
+
+        self.load_definition(preset.definition());
+    }
+
+    /// Watches `path`, the file the current project was loaded from.
+    fn watch_file(&mut self, path: PathBuf) {
+        self.file_path = path.display().to_string();
+
+        match FileWatcher::new(path) {
+            Ok(watcher) => {
+                let saved = self.definition();
+                self.file = Some(OpenFile { watcher, saved });
+            }
+            Err(err) => self.message = Some(format!("Not watching the file: {}", err)),
+        }
+    }
+
+    /// Saves the project to the path entered, which becomes the open file.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_file(&mut self) {
+        let path = PathBuf::from(self.file_path.trim());
+
+        match self.project().save(&path) {
+            Ok(()) => {
+                self.message = Some(format!("Saved {}", path.display()));
+                self.watch_file(path);
+            }
+            Err(err) => self.message = Some(format!("Could not save: {}", err)),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn save_file(&mut self) {
+        self.message = Some(String::from("Saving isn't available on the web yet"));
+    }
+
+    /// Loads the project at the path entered, keeping everything as is when
+    /// it can't be read.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_file(&mut self) {
+        let path = PathBuf::from(self.file_path.trim());
+
+        match Project::load(&path) {
+            Ok(project) => {
+                self.load_project(project);
+                self.message = Some(format!("Loaded {}", path.display()));
+                self.watch_file(path);
+            }
+            Err(err) => self.message = Some(format!("Could not load: {}", err)),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn load_file(&mut self) {
+        self.message = Some(String::from("Loading isn't available on the web yet"));
+    }
+
+    /// Picks up changes made to the open file by other programs, reloading
+    /// right away unless that would throw away unsaved changes.
+    ///
+    /// This runs when the view is rebuilt, so a change is noticed on the next
+    /// interaction with the window.
+    fn check_file(&mut self) {
+        let Some(ref file) = self.file else {
+            return;
+        };
+
+        if !file.watcher.changed() {
+            return;
+        }
+
+        match self.definition() == file.saved {
+            true => self.reload_file(),
+            false => self.reload_pending = true,
+        }
+    }
+
+    /// Loads the open file again, keeping everything as is when it can't be read.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_file(&mut self) {
+        self.reload_pending = false;
+
+        let Some(ref mut file) = self.file else {
+            return;
+        };
+
+        // an editor may still be writing the file, another change will follow
+        match Project::load(file.watcher.path()) {
+            Ok(project) => {
+                file.saved = project.definition.clone();
+                self.load_project(project);
+                self.message = None;
+            }
+            Err(err) => self.message = Some(format!("Could not reload: {}", err)),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn reload_file(&mut self) {
+        self.reload_pending = false;
+    }
+
+    /// Marks `stage`, and every stage after it, to be recomputed by [`Data::update`].
+    fn invalidate(&mut self, stage: Stage) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.min(stage),
+            None => stage,
+        });
+    }
+
+    /// Recomputes the dirty stages, reusing the results of the clean ones.
+    ///
+    /// Expanding and generating the mesh is left to the worker, the last mesh
+    /// stays on screen until it's done. The worker rebuilds the view when
+    /// it is, see [`worker_events`].
+    fn update(&mut self) {
+        self.update_sheet();
+
+        // jobs hand the expansions and the generator back, cancelled or not
+        if let Some(finished) = self.worker.finished() {
+            self.finish_job(finished);
+        }
+
+        if !self.worker.is_busy() {
+            self.running = None;
+        }
+
+        let Some(dirty) = self.dirty else {
+            return;
+        };
+
+        if dirty > Stage::Mesh {
+            self.dirty = None;
+
+            if dirty <= Stage::Shade {
+                self.shade();
+            }
+
+            if dirty <= Stage::Fit {
+                self.fit_mesh();
+            }
+        } else if self.running.is_some() {
+            // the dirty stages are started once the job is back, so they
+            // continue from its expansions instead of starting over
+            self.worker.cancel();
+        } else {
+            self.dirty = None;
+            self.start_job(dirty);
+        }
+    }
+
+    /// Saves the contact sheet the worker rendered once it's done, and shows
+    /// how far along it is until then.
+    fn update_sheet(&mut self) {
+        if let Some(result) = self.worker.sheet_finished() {
+            if let Err(LilyError::Cancelled) = result {
+                self.message = Some(String::from("Contact sheet export cancelled"));
+                return;
+            }
+
+            let result = result
+                .map_err(|err| err.to_string())
+                .and_then(|png| save_export("png", &png));
+
+            self.message = match result {
+                Ok(path) => Some(format!("Exported {}", path.display())),
+                Err(err) => Some(format!("Could not export contact sheet: {}", err)),
+            };
+        } else if let Some((done, total)) = self.worker.sheet_progress() {
+            self.message = Some(format!(
+                "Exporting contact sheet, {} of {} cells",
+                done, total
+            ));
+        }
+    }
+
+    /// Starts rendering the snapshots into a contact sheet on the worker, see
+    /// [`Data::update_sheet`].
+    fn export_contact_sheet(&mut self) {
+        if self.snapshots.is_empty() {
+            self.message = Some(String::from("The project has no snapshots to export"));
+            return;
+        }
+
+        let job = SheetJob {
+            cells: (self.snapshots.iter())
+                .map(|snapshot| (snapshot.name.clone(), snapshot.definition.clone()))
+                .collect(),
+            options: ContactSheetOptions::default(),
+            symbol_limit: self.symbol_limit(),
+        };
+
+        if !self.worker.start_sheet(job) {
+            self.message = Some(String::from("A contact sheet is already being exported"));
+        }
+    }
+
+    /// Hands the stages from `stage` up to the mesh to the worker.
+    fn start_job(&mut self, stage: Stage) {
+        // the expansions are kept until the axiom, rules or seed change
+        let derivation = match self.derivation.take() {
+            Some(derivation) if derivation.is_for(&self.rules, &self.axiom, self.seed) => {
+                derivation
+            }
+            _ => Derivation::new(self.rules.clone(), &self.axiom, self.seed),
+        };
+
+        let job = Job {
+            stage,
+            derivation,
+            iterations: self.iterations(),
+            symbol_limit: self.symbol_limit(),
+            instructions: self.instructions.clone(),
+            options: self.options.clone(),
+            tree: match stage {
+                Stage::Expand => String::new(),
+                _ => self.tree.clone(),
+            },
+            stream: match stage {
+                Stage::Mesh => self.stream.clone(),
+                _ => Vec::new(),
+            },
+            generator: mem::take(&mut self.generator),
+        };
+
+        #[cfg(test)]
+        self.ran.push(stage);
+
+        self.running = Some(stage);
+        self.worker.start(job);
+    }
+
+    fn finish_job(&mut self, finished: Finished) {
+        let job = finished.job;
+        self.generator = job.generator;
+        self.derivation = Some(job.derivation);
+        self.running = None;
+
+        match finished.problem {
+            None => {
+                self.truncated = None;
+                self.brackets = None;
+                self.tree = job.tree;
+                self.stream = job.stream;
+
+                let mesh = self.generator.mesh().cloned().unwrap_or_else(Mesh::new);
+                self.raw_mesh = Arc::new(mesh);
+                self.generation_time = finished.elapsed;
+
+                self.shade();
+                self.fit_mesh();
+
+                // a new mesh grows from the start again
+                self.playhead = 0.0;
+                self.playing = true;
+            }
+            // keep showing the last mesh that fit
+            Some(LilyError::ExpansionBudget { limit, .. }) => self.truncated = Some(limit),
+            Some(LilyError::Brackets(error)) => {
+                self.truncated = None;
+                self.brackets = Some(error);
+                self.tree = job.tree;
+            }
+            // what the job didn't get to is redone with the next one
+            Some(LilyError::Cancelled) => self.invalidate(job.stage),
+            Some(err) => self.message = Some(err.to_string()),
+        }
+    }
+
+    fn shade(&mut self) {
+        #[cfg(test)]
+        self.ran.push(Stage::Shade);
+
+        self.shaded_mesh = match self.shading.strength == 0.0 {
+            true => Arc::clone(&self.raw_mesh),
+            false => {
+                let mut mesh = Mesh::clone(&self.raw_mesh);
+                system::shade_density(&mut mesh, &self.shading);
+                Arc::new(mesh)
+            }
+        };
+    }
+
+    /// Fits the shaded mesh into the display, the painter places it with
+    /// [`Data::fit`] instead of a fitted copy being kept.
+    fn fit_mesh(&mut self) {
+        #[cfg(test)]
+        self.ran.push(Stage::Fit);
+
+        let rect = Rect::min_size(Point::ZERO, DISPLAY_SIZE);
+        self.fit = system::mesh_fit(&self.shaded_mesh, rect, self.options.growth, self.view.fit);
+    }
+
+    /// The turtle at the scrubbed instruction, while inspecting.
+    fn probe(&self) -> Option<TurtleProbe> {
+        match self.inspecting {
+            true => self.generator.probe(self.scrub),
+            false => None,
+        }
+    }
+
+    /// Records the definition as it is before an edit of `field`, see
+    /// [`History::record`].
+    fn record(&mut self, field: Option<Field>) {
+        let before = self.definition();
+        self.history.record(field, before);
+    }
+
+    fn undo(&mut self) {
+        let current = self.definition();
+
+        if let Some(definition) = self.history.undo(current) {
+            self.replace_definition(definition);
+        }
+    }
+
+    fn redo(&mut self) {
+        let current = self.definition();
+
+        if let Some(definition) = self.history.redo(current) {
+            self.replace_definition(definition);
+        }
+    }
+
+    fn set_axiom(&mut self, axiom: String) {
+        if self.axiom != axiom {
+            self.record(Some(Field::Axiom));
+            self.axiom = axiom;
+            self.invalidate(Stage::Expand);
+        }
+    }
+
+    /// Parses the rules, with an error for each line that isn't a rule.
+    fn parse_rules(text: &str) -> (Rules, Vec<LilyError>) {
+        #[cfg(test)]
+        tests::count_parse(Field::Rules);
+
+        Rules::parse_checked(text)
+    }
+
+    fn set_rules(&mut self, text: String) {
+        let (rules, errors) = Self::parse_rules(&text);
+        self.rule_errors = errors;
+
+        if self.rules != rules {
+            self.record(Some(Field::Rules));
+            self.rules = rules;
+            self.invalidate(Stage::Expand);
+        }
+
+        self.rules_text = text;
+    }
+
+    /// Parses instructions, binding `[` and `]` to push and pop, with the
+    /// problems found while parsing them.
+    fn parse_instructions(text: &str) -> (Instructions, Vec<Diagnostic>) {
+        #[cfg(test)]
+        tests::count_parse(Field::Instructions);
+
+        let (mut instructions, errors, bindings) = Instructions::parse_bindings(text);
+        let problems = diagnostics::diagnose_instructions(text, &errors, &bindings);
+
+        instructions.insert_defaults();
+        (instructions, problems)
+    }
+
+    fn set_instructions(&mut self, text: String) {
+        let (instructions, problems) = Self::parse_instructions(&text);
+        self.instruction_problems = problems;
+
+        if self.instructions != instructions {
+            self.record(Some(Field::Instructions));
+            self.instructions = instructions;
+            self.invalidate(Stage::Interpret);
+        }
+
+        self.instructions_text = text;
+    }
+
+    /// Rewrites the instructions text with `transform`, regenerating the system.
+    fn transform_instructions(&mut self, transform: impl FnMut(&mut Instruction)) {
+        let text = Instructions::transform_text(&self.instructions_text, transform);
+
+        // a transform is undone on its own, not with the typing around it
+        self.history.seal();
+        self.set_instructions(text);
+        self.history.seal();
+    }
+
+    /// The definition the blend slider goes towards, the current one unless
+    /// it's what the slider last produced.
+    fn blend_target(&self) -> Definition {
+        let definition = self.definition();
+
+        match self.blend {
+            Some(ref blend) if blend.applied == definition => blend.target.clone(),
+            _ => definition,
+        }
+    }
+
+    fn set_blend(&mut self, t: f32) {
+        let Some(ref pinned) = self.pinned else {
+            return;
+        };
+
+        let t = (t / BLEND_STEP).round() * BLEND_STEP;
+        let target = self.blend_target();
+
+        if self
+            .blend
+            .as_ref()
+            .is_some_and(|blend| blend.t == t && blend.target == target)
+        {
+            return;
+        }
+
+        match pinned.blend(&target, t) {
+            Ok(definition) => {
+                // dragging the slider is undone all at once
+                self.record(Some(Field::Blend));
+                self.replace_definition(definition);
+
+                let applied = self.definition();
+                self.blend = Some(Blend { target, applied, t });
+            }
+            Err(reason) => self.message = Some(format!("Can't blend: {}", reason)),
+        }
+    }
+
+    fn set_turn_jitter(&mut self, jitter: f32) {
+        if self.options.turn_jitter != jitter {
+            self.record(Some(Field::Jitter));
+            self.options.turn_jitter = jitter;
+            self.invalidate(Stage::Mesh);
+        }
+    }
+
+    fn set_width_falloff(&mut self, falloff: f32) {
+        if self.options.width_falloff != falloff {
+            self.record(Some(Field::Falloff));
+            self.options.width_falloff = falloff;
+            self.invalidate(Stage::Mesh);
+        }
+    }
+
+    fn set_leaf_falloff(&mut self, falloff: f32) {
+        if self.options.leaf_falloff != falloff {
+            self.record(Some(Field::LeafFalloff));
+            self.options.leaf_falloff = falloff;
+            self.invalidate(Stage::Mesh);
+        }
+    }
+
+    /// Sets the minimum width once `text` is a width that isn't negative.
+    fn set_min_width(&mut self, text: String) {
+        let min_width = text.trim().parse::<f32>().ok();
+        self.min_width = text;
+
+        match min_width {
+            Some(min_width) if min_width >= 0.0 && min_width != self.options.min_width => {
+                self.record(Some(Field::Falloff));
+                self.options.min_width = min_width;
+                self.invalidate(Stage::Mesh);
+            }
+            _ => {}
+        }
+    }
+
+    fn set_tropism_strength(&mut self, strength: f32) {
+        if self.options.tropism_strength != strength {
+            self.record(Some(Field::Tropism));
+            self.options.tropism_strength = strength;
+            self.invalidate(Stage::Mesh);
+        }
+    }
+
+    /// Picks another jitter seed, the next one drawn from the current seed so
+    /// rerolling is reproducible too.
+    fn reroll_jitter(&mut self) {
+        self.record(None);
+        // kept to what a TOML integer holds, so saved seeds read as shown
+        let seed = Pcg32::new(self.options.jitter_seed).next_u64() >> 1;
+        self.options.jitter_seed = seed;
+        self.invalidate(Stage::Mesh);
+    }
+
+    fn gradient_text(options: &SystemOptions) -> [String; 2] {
+        [options.trunk_color, options.tip_color].map(system::format_color)
+    }
+
+    /// Sets the trunk color when `tip` is false, the tip color otherwise,
+    /// once `text` is a valid color.
+    fn set_gradient_color(&mut self, tip: bool, text: String) {
+        let color = system::parse_color(&text);
+        self.gradient_text[tip as usize] = text;
+
+        let Some(color) = color else {
+            return;
+        };
+
+        let current = match tip {
+            false => self.options.trunk_color,
+            true => self.options.tip_color,
+        };
+
+        if current != color {
+            self.record(Some(Field::Gradient));
+
+            match tip {
+                false => self.options.trunk_color = color,
+                true => self.options.tip_color = color,
+            }
+
+            self.invalidate(Stage::Mesh);
+        }
+    }
+
+    fn iterations(&self) -> usize {
+        self.iterations.parse().unwrap_or(0)
+    }
+
+    fn set_iterations(&mut self, iterations: String) {
+        if iterations.parse().unwrap_or(0) != self.iterations() {
+            self.record(Some(Field::Iterations));
+            self.invalidate(Stage::Expand);
+        }
+
+        self.iterations = iterations;
+    }
+
+    fn symbol_limit(&self) -> usize {
+        (self.symbol_limit.trim().parse()).unwrap_or(system::DEFAULT_SYMBOL_LIMIT)
+    }
+
+    fn set_symbol_limit(&mut self, symbol_limit: String) {
+        let prev = self.symbol_limit();
+        self.symbol_limit = symbol_limit;
+
+        if prev != self.symbol_limit() {
+            self.invalidate(Stage::Expand);
+        }
+    }
+}
+
+fn background(content: impl View<Data>) -> impl View<Data> {
+    let colors = &[style(Palette::BACKGROUND), style(Palette::BACKGROUND_LIGHT)];
+    container(content).background(gradient(-45.0, colors))
+}
+
+/// An arrow at the turtle, pointing where it's heading.
+fn turtle_marker(probe: &TurtleProbe, (scale, offset): (f32, Vector)) -> Mesh {
+    let state = &probe.state;
+
+    let position = state.position.to_point() * scale + offset;
+    let heading = state.heading().to_vector();
+    let left = state.left().to_vector();
+
+    let color = hex("#3b4cc0");
+    let vertex = |position| Vertex {
+        position,
+        tex_coords: Point::ZERO,
+        color,
+    };
+
+    let mut mesh = Mesh::new();
+    mesh.vertices.push(vertex(position + heading * 10.0));
+    mesh.vertices
+        .push(vertex(position - heading * 4.0 + left * 6.0));
+    mesh.vertices
+        .push(vertex(position - heading * 4.0 - left * 6.0));
+    mesh.indices.extend([0, 1, 2]);
+
+    mesh
+}
+
+/// How much one step of the scroll wheel zooms.
+const ZOOM_STEP: f32 = 1.2;
+
+/// How far the view zooms out and in.
+const ZOOM_RANGE: (f32, f32) = (0.1, 20.0);
+
+/// Moves a point fit into the display by the zoom and pan of `view`,
+/// zooming about the center of the display.
+fn view_point(view: &ViewOptions, point: Point) -> Point {
+    let center = display_center();
+    center + (point - center) * view.zoom + Vector::new(view.pan[0], view.pan[1])
+}
+
+fn display_center() -> Point {
+    Point::new(DISPLAY_SIZE.width / 2.0, DISPLAY_SIZE.height / 2.0)
+}
+
+/// Zooms `view` to `zoom` about `cursor`, the point under the cursor stays
+/// where it is.
+fn zoom_at(view: &mut ViewOptions, cursor: Point, zoom: f32) {
+    let zoom = zoom.clamp(ZOOM_RANGE.0, ZOOM_RANGE.1);
+    let pan = Vector::new(view.pan[0], view.pan[1]);
+
+    let from_center = cursor - display_center();
+    let pan = from_center - (from_center - pan) * (zoom / view.zoom);
+
+    view.zoom = zoom;
+    view.pan = [pan.x, pan.y];
+}
+
+/// The first `indices` of `mesh` as drawn, fit into the display with `fit`
+/// and moved by `view`, in a single copy for the canvas.
+fn shown_mesh(mesh: &Mesh, indices: usize, fit: (f32, Vector), view: &ViewOptions) -> Mesh {
+    let (scale, offset) = fit;
+
+    let mut shown = Mesh::new();
+    shown.vertices = (mesh.vertices.iter())
+        .map(|vertex| Vertex {
+            position: view_point(view, vertex.position * scale + offset),
+            ..*vertex
+        })
+        .collect();
+    shown.indices = mesh.indices[..indices.min(mesh.indices.len())].to_vec();
+
+    shown
+}
+
+fn apply_view(mesh: &mut Mesh, view: &ViewOptions) {
+    if view.zoom == 1.0 && view.pan == [0.0, 0.0] {
+        return;
+    }
+
+    for vertex in mesh.vertices.iter_mut() {
+        vertex.position = view_point(view, vertex.position);
+    }
+}
+
+fn mesh_painter() -> impl View<Data> {
+    let painter = painter(|cx, data: &mut Data, canvas| {
+        let mesh = Arc::clone(&data.shaded_mesh);
+        if mesh.indices.is_empty() {
+            return;
+        }
+
+        let mut indices = mesh.indices.len();
+        let mut marker = None;
+
+        if data.animating {
+            if data.playing {
+                let step = data.animation_speed / ANIMATION_FRAMES as f32;
+                data.playhead = f32::min(data.playhead + step, 1.0);
+                data.playing = data.playhead < 1.0;
+
+                // the timeline follows the playhead
+                cx.request_rebuild();
+                cx.request_draw();
+            }
+
+            // the triangles are in the order the turtle drew them
+            let triangles = mesh.indices.len() / 3;
+            let count = (triangles as f32 * data.playhead).round() as usize;
+            indices = count * 3;
+        } else if let Some(probe) = data.probe() {
+            // the segments are drawn in order, so the start of the index
+            // buffer is everything drawn before the scrubbed instruction
+            indices = probe.indices;
+            marker = Some(turtle_marker(&probe, data.fit));
+        }
+
+        // the mesh is shared with the stages, the canvas gets the only copy
+        canvas.draw(shown_mesh(&mesh, indices, data.fit, &data.view));
+
+        if let Some(mut marker) = marker {
+            apply_view(&mut marker, &data.view);
+            canvas.draw(marker);
+        }
+    });
+
+    // scrolling zooms and dragging pans, on top of fitting the mesh
+    let painter = on_event(painter, |cx, data: &mut Data, event| {
+        if let Some(scrolled) = event.get::<PointerScrolled>() {
+            if cx.is_hot() {
+                let zoom = data.view.zoom * ZOOM_STEP.powf(scrolled.delta.y);
+                zoom_at(&mut data.view, cx.local(scrolled.position), zoom);
+                cx.request_draw();
+            }
+        }
+
+        if let Some(pressed) = event.get::<PointerPressed>() {
+            if cx.is_hot() {
+                data.drag = Some(pressed.position);
+            }
+        }
+
+        if let Some(moved) = event.get::<PointerMoved>() {
+            if let Some(from) = data.drag {
+                let delta = moved.position - from;
+                data.view.pan[0] += delta.x;
+                data.view.pan[1] += delta.y;
+                data.drag = Some(moved.position);
+                cx.request_draw();
+            }
+        }
+
+        if event.is::<PointerReleased>() {
+            data.drag = None;
+        }
+    });
+
+    let painter = container(painter)
+        .border_bottom(1.0)
+        .border_color(style(Palette::ACCENT_DARKER));
+
+    size(DISPLAY_SIZE, painter)
+}
+
+/// Shows that a new mesh is on its way while the last one is still drawn.
+fn generating(data: &mut Data) -> Option<impl View<Data>> {
+    data.running.map(|_| text("Generating…"))
+}
+
+fn regenerate() -> impl View<Data> {
+    let button = button(text("Regenerate").font_size(24.0)).fancy(6.0);
+
+    on_click(button, |_, data: &mut Data| {
+        data.invalidate(Stage::Expand);
+        info!("Regenerating mesh");
+    })
+}
+
+fn restart() -> impl View<Data> {
+    let button = button(text("Restart").font_size(24.0)).fancy(6.0);
+
+    on_click(button, |_, data: &mut Data| {
+        *data = Data::new();
+        info!("Restarting");
+    })
+}
+
+fn button_row() -> impl View<Data> {
+    hstack![regenerate(), restart()].gap(10.0)
+}
+
+fn undo() -> impl View<Data> {
+    let button = button(text("Undo")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| data.undo())
+}
+
+fn redo() -> impl View<Data> {
+    let button = button(text("Redo")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| data.redo())
+}
+
+fn history_row(data: &mut Data) -> impl View<Data> {
+    let undo = data.history.can_undo().then(undo);
+    let redo = data.history.can_redo().then(redo);
+
+    hstack![undo, redo].gap(10.0)
+}
+
+/// Ctrl+Z undoes, Ctrl+Y and Ctrl+Shift+Z redo, wherever the focus is.
+fn history_keys(content: impl View<Data>) -> impl View<Data> {
+    on_event(content, |cx, data: &mut Data, event| {
+        let Some(pressed) = event.get::<KeyPressed>() else {
+            return;
+        };
+
+        if !pressed.modifiers.ctrl {
+            return;
+        }
+
+        match pressed.key {
+            Key::Z if pressed.modifiers.shift => data.redo(),
+            Key::Z => data.undo(),
+            Key::Y => data.redo(),
+            _ => return,
+        }
+
+        cx.request_rebuild();
+    })
+}
+
+/// Rebuilds as soon as the worker finishes a job, rather than at the next
+/// input.
+fn worker_events(content: impl View<Data>) -> impl View<Data> {
+    on_event(content, |cx, data: &mut Data, event| {
+        if data.worker.needs_proxy() {
+            data.worker.set_proxy(cx.proxy());
+
+            // a job may have finished before the worker could say so
+            cx.request_rebuild();
+        }
+
+        if event.is::<JobFinished>() || event.is::<SheetProgress>() {
+            cx.request_rebuild();
+        }
+    })
+}
+
+fn preset_button(preset: &'static Preset) -> impl View<Data> {
+    let button = button(text(preset.name)).fancy(4.0);
+    on_click(button, move |_, data: &mut Data| data.load_preset(preset))
+}
+
+fn custom(data: &mut Data) -> Option<impl View<Data>> {
+    data.custom.as_ref()?;
+
+    let button = button(text("Custom")).fancy(4.0);
+    let button = on_click(button, |_, data: &mut Data| {
+        if let Some(definition) = data.custom.take() {
+            data.load_definition(definition);
+        }
+    });
+
+    Some(alt(
+        "Back to the system from before picking a preset",
+        button,
+    ))
+}
+
+/// The most preset buttons side by side, more go on another row.
+const PRESETS_PER_ROW: usize = 3;
+
+fn preset_row(data: &mut Data) -> impl View<Data> {
+    let rows: Vec<_> = (PRESETS.chunks(PRESETS_PER_ROW))
+        .map(|presets| {
+            let buttons: Vec<_> = presets.iter().map(preset_button).collect();
+            hstack(buttons).gap(8.0)
+        })
+        .collect();
+
+    hstack![vstack(rows).gap(8.0), custom(data)].gap(8.0)
+}
+
+fn save() -> impl View<Data> {
+    let button = button(text("Save")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| data.save_file())
+}
+
+fn load() -> impl View<Data> {
+    let button = button(text("Load")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| data.load_file())
+}
+
+fn file_path(data: &mut Data) -> impl View<Data> {
+    let input = text_input()
+        .on_change(|_, data: &mut Data, text| data.file_path = text)
+        .text(&data.file_path)
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
+
+    alt(
+        "The project file, .lily and .json are JSON, .toml is TOML",
+        flex_grow(1.0, input_container(input)),
+    )
+}
+
+fn file_row(data: &mut Data) -> impl View<Data> {
+    width(FILL, hstack![save(), load(), file_path(data)].gap(10.0))
+}
+
+fn copy_share_code() -> impl View<Data> {
+    let button = button(text("Copy share code")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        let code = system::encode_share(&data.project());
+
+        data.message = match clipboard::set(code) {
+            Ok(()) => Some(String::from("Share code copied")),
+            Err(err) => Some(format!("Could not copy share code: {}", err)),
+        };
+    })
+}
+
+fn paste_share_code() -> impl View<Data> {
+    let button = button(text("Paste share code")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        let project = clipboard::get().and_then(|code| {
+            // report decoding errors without touching the current system
+            system::decode_share(&code).map_err(|err| err.to_string())
+        });
+
+        match project {
+            Ok(project) => {
+                data.load_project(project);
+                data.message = None;
+            }
+            Err(err) => data.message = Some(format!("Could not paste share code: {}", err)),
+        }
+    })
+}
+
+/// The most symbols "Copy expansion" puts on the clipboard, some clipboards
+/// freeze on anything much longer.
+const MAX_COPIED_SYMBOLS: usize = 1_000_000;
+
+fn copy_expansion() -> impl View<Data> {
+    let button = button(text("Copy expansion")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        let (expansion, cut) = match data.tree.char_indices().nth(MAX_COPIED_SYMBOLS) {
+            Some((end, _)) => (&data.tree[..end], true),
+            None => (data.tree.as_str(), false),
+        };
+
+        data.message = match clipboard::set(String::from(expansion)) {
+            Ok(()) if cut => Some(format!(
+                "Copied the first {} symbols of the expansion",
+                group_digits(MAX_COPIED_SYMBOLS)
+            )),
+            Ok(()) => Some(String::from("Expansion copied")),
+            Err(err) => Some(format!("Could not copy expansion: {}", err)),
+        };
+    })
+}
+
+fn expansion_length(data: &mut Data) -> impl View<Data> {
+    let length = data.tree.chars().count();
+    text(format!("{} symbols", group_digits(length)))
+}
+
+/// How heavy the mesh is, to tell why a system is slow.
+fn mesh_stats(data: &mut Data) -> impl View<Data> {
+    let vertices = group_digits(data.raw_mesh.vertices.len());
+    let triangles = group_digits(data.raw_mesh.indices.len() / 3);
+
+    let time = match data.generation_time {
+        Some(time) => format!(", {} ms", time.as_millis()),
+        None => String::new(),
+    };
+
+    text(format!(
+        "{} vertices, {} triangles{}",
+        vertices, triangles, time
+    ))
+}
+
+fn expansion_row(data: &mut Data) -> impl View<Data> {
+    hstack![copy_expansion(), expansion_length(data), mesh_stats(data)].gap(10.0)
+}
+
+fn share_row() -> impl View<Data> {
+    hstack![copy_share_code(), paste_share_code()].gap(10.0)
+}
+
+/// Writes an export to the working directory, named by when it was made.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_export(extension: &str, bytes: &[u8]) -> Result<PathBuf, String> {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let path = PathBuf::from(format!("lily-{}.{}", time.as_secs(), extension));
+    std::fs::write(&path, bytes).map_err(|err| err.to_string())?;
+
+    Ok(path)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_export(_extension: &str, _bytes: &[u8]) -> Result<PathBuf, String> {
+    Err(String::from("exporting isn't available on the web yet"))
+}
+
+fn export_obj() -> impl View<Data> {
+    let button = button(text("Export OBJ")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        let obj = export::mesh_to_obj(&data.shaded_mesh, &data.axiom);
+
+        data.message = match save_export("obj", obj.as_bytes()) {
+            Ok(path) => Some(format!("Exported {}", path.display())),
+            Err(err) => Some(format!("Could not export OBJ: {}", err)),
+        };
+    })
+}
+
+fn export_pdf() -> impl View<Data> {
+    let button = button(text("Export PDF")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        let mut backend = PdfBackend::new();
+        let customs = data.instructions.customs();
+        system::interpret(&data.options, &data.stream, customs, &mut backend);
+
+        let pdf = backend.finish(&PdfOptions::default());
+
+        data.message = match save_export("pdf", &pdf) {
+            Ok(path) => Some(format!("Exported {}", path.display())),
+            Err(err) => Some(format!("Could not export PDF: {}", err)),
+        };
+    })
+}
+
+/// The instructions as stroked lines, like the command line's SVG.
+fn lines_svg(data: &Data) -> String {
+    let mut backend = SvgBackend::new();
+    let customs = data.instructions.customs();
+    system::interpret(&data.options, &data.stream, customs, &mut backend);
+
+    backend.finish(lily::SVG_SIZE)
+}
+
+/// The shaded mesh as filled triangles.
+fn mesh_svg(data: &Data) -> String {
+    let mesh = &data.shaded_mesh;
+    export::mesh_to_svg(mesh, system::mesh_bounds(mesh))
+}
+
+fn export_svg() -> impl View<Data> {
+    let button = button(text("Export SVG")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        data.message = match save_export("svg", lines_svg(data).as_bytes()) {
+            Ok(path) => Some(format!("Exported {}", path.display())),
+            Err(err) => Some(format!("Could not export SVG: {}", err)),
+        };
+    })
+}
+
+fn export_mesh_svg() -> impl View<Data> {
+    let button = button(text("Export mesh SVG")).fancy(4.0);
+
+    // the mesh has the shading and the tubes of 3D systems, which the lines
+    // leave out
+    on_click(button, |_, data: &mut Data| {
+        data.message = match save_export("svg", mesh_svg(data).as_bytes()) {
+            Ok(path) => Some(format!("Exported {}", path.display())),
+            Err(err) => Some(format!("Could not export mesh SVG: {}", err)),
+        };
+    })
+}
+
+/// The largest image "Export PNG" renders, which is already hundreds of
+/// megabytes of pixels.
+const MAX_PNG_SIZE: u32 = 16384;
+
+fn export_png() -> impl View<Data> {
+    let button = button(text("Export PNG")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        let size = match data.png_size.trim().parse() {
+            Ok(size @ 1..=MAX_PNG_SIZE) => size,
+            _ => {
+                data.message = Some(format!(
+                    "The PNG size has to be between 1 and {}",
+                    MAX_PNG_SIZE
+                ));
+                return;
+            }
+        };
+
+        let options = PngOptions {
+            size,
+            background: (!data.png_transparent).then(|| palette().background),
+            ..Default::default()
+        };
+
+        let mut png = Vec::new();
+        let result = export::export_png(&data.shaded_mesh, &options, &mut png)
+            .map_err(|err| err.to_string())
+            .and_then(|()| save_export("png", &png));
+
+        data.message = match result {
+            Ok(path) => Some(format!("Exported {}", path.display())),
+            Err(err) => Some(format!("Could not export PNG: {}", err)),
+        };
+    })
+}
+
+fn png_size(data: &mut Data) -> impl View<Data> {
+    let input = text_input()
+        .on_change(|_, data: &mut Data, text| data.png_size = text)
+        .text(&data.png_size)
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
+
+    alt(
+        "Longest side of the exported PNG in pixels",
+        width(80.0, input_container(input)),
+    )
+}
+
+fn png_background(data: &mut Data) -> impl View<Data> {
+    let label = match data.png_transparent {
+        true => "Transparent",
+        false => "Background",
+    };
+
+    let button = button(text(label)).fancy(4.0);
+    let button = on_click(button, |_, data: &mut Data| {
+        data.png_transparent = !data.png_transparent;
+    });
+
+    alt("What's behind the exported PNG", button)
+}
+
+fn png_row(data: &mut Data) -> impl View<Data> {
+    hstack![export_png(), png_size(data), png_background(data)].gap(10.0)
+}
+
+fn export_contact_sheet() -> impl View<Data> {
+    let button = button(text("Export contact sheet")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| data.export_contact_sheet())
+}
+
+/// Stops the contact sheet being exported, shown until it's done.
+fn cancel_sheet(data: &mut Data) -> Option<impl View<Data>> {
+    data.worker.sheet_progress()?;
+
+    let button = button(text("Cancel export")).fancy(4.0);
+    Some(on_click(button, |_, data: &mut Data| {
+        data.worker.cancel_sheet();
+    }))
+}
+
+fn export_row() -> impl View<Data> {
+    hstack![
+        export_svg(),
+        export_mesh_svg(),
+        export_pdf(),
+        export_obj(),
+        export_contact_sheet()
+    ]
+    .gap(10.0)
+}
+
+fn reload_banner(data: &mut Data) -> Option<impl View<Data>> {
+    if !data.reload_pending {
+        return None;
+    }
+
+    let reload = on_click(button(text("Reload")).fancy(4.0), |_, data: &mut Data| {
+        data.reload_file();
+    });
+
+    let keep = on_click(
+        button(text("Keep mine")).fancy(4.0),
+        |_, data: &mut Data| {
+            data.reload_pending = false;
+        },
+    );
+
+    let label = text("The file changed on disk");
+    Some(hstack![label, reload, keep].gap(10.0))
+}
+
+fn inspect() -> impl View<Data> {
+    let button = button(text("Inspect turtle")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        data.inspecting = !data.inspecting;
+        data.scrub = data.stream.len();
+    })
+}
+
+fn animate(data: &mut Data) -> impl View<Data> {
+    let label = match data.animating {
+        true => "Stop animating",
+        false => "Animate",
+    };
+
+    let button = button(text(label)).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        data.animating = !data.animating;
+        data.playhead = 0.0;
+        data.playing = true;
+    })
+}
+
+fn play_pause(data: &mut Data) -> impl View<Data> {
+    let label = match data.playing {
+        true => "Pause",
+        false => "Play",
+    };
+
+    let button = button(text(label)).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        // playing at the end starts over
+        if !data.playing && data.playhead >= 1.0 {
+            data.playhead = 0.0;
+        }
+
+        data.playing = !data.playing;
+    })
+}
+
+/// Plays, pauses and scrubs the animation while animating.
+fn animation_row(data: &mut Data) -> Option<impl View<Data>> {
+    if !data.animating {
+        return None;
+    }
+
+    let timeline =
+        slider(data.playhead)
+            .range(0.0..=1.0)
+            .on_input(|_, data: &mut Data, playhead| {
+                data.playhead = playhead;
+                data.playing = false;
+            });
+
+    let speed = slider(data.animation_speed)
+        .range(0.25..=MAX_ANIMATION_SPEED)
+        .on_input(|_, data: &mut Data, speed| {
+            data.animation_speed = (speed * 4.0).round() / 4.0;
+        });
+
+    let speed = alt(
+        "How fast the mesh grows",
+        hstack![
+            text(format!("{}×", data.animation_speed)),
+            width(100.0, speed)
+        ]
+        .gap(10.0),
+    );
+
+    let content = hstack![
+        play_pause(data),
+        flex_grow(1.0, width(FILL, timeline)),
+        speed
+    ]
+    .gap(10.0);
+    Some(width(FILL, content))
+}
+
+fn reset_view() -> impl View<Data> {
+    let button = button(text("Reset view")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| {
+        // the fit mode and slider are settings rather than where the view
+        // was moved to
+        data.view = ViewOptions {
+            fit: data.view.fit,
+            max_iterations: data.view.max_iterations,
+            ..ViewOptions::default()
+        }
+    })
+}
+
+fn fit_mode(data: &mut Data) -> impl View<Data> {
+    let label = match data.view.fit {
+        FitMode::BottomCenter => "Fit to bottom",
+        FitMode::Center => "Fit to center",
+    };
+
+    let button = button(text(label)).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        data.view.fit = match data.view.fit {
+            FitMode::BottomCenter => FitMode::Center,
+            FitMode::Center => FitMode::BottomCenter,
+        };
+        data.invalidate(Stage::Fit);
+    })
+}
+
+fn inspect_row(data: &mut Data) -> impl View<Data> {
+    hstack![inspect(), animate(data), reset_view(), fit_mode(data)].gap(10.0)
+}
+
+/// What the turtle looks like at `probe`, one property per line.
+fn turtle_readout(probe: &TurtleProbe, total: usize) -> String {
+    let state = &probe.state;
+    let color = state.color;
+
+    format!(
+        "Instruction {} of {}\nPosition {:.2}, {:.2}\nHeading {:.1}°\nDepth {}\nWidth {:.3}\nScale {:.3}\nColor {:.2} {:.2} {:.2} {:.2}",
+        probe.index,
+        total,
+        state.position.x,
+        state.position.y,
+        state.orientation.heading_degrees(),
+        probe.depth,
+        probe.width(),
+        state.scale,
+        color.r,
+        color.g,
+        color.b,
+        color.a,
+    )
+}
+
+fn inspector(data: &mut Data) -> Option<impl View<Data>> {
+    let probe = data.probe()?;
+    let total = data.stream.len();
+
+    let scrubber = slider(probe.index as f32)
+        .range(0.0..=total as f32)
+        .on_input(|_, data: &mut Data, index| data.scrub = index.round() as usize);
+
+    let readout = text(turtle_readout(&probe, total))
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
+
+    Some(vstack![width(FILL, scrubber), readout].gap(8.0))
+}
+
+fn flip_handedness() -> impl View<Data> {
+    let button = button(text("Flip handedness")).fancy(4.0);
+
+    on_click(button, |_, data: &mut Data| {
+        data.transform_instructions(Instruction::negate_angle);
+    })
 }
 
-impl Data {
-    fn new() -> Self {
-        let options = SystemOptions {
-            branch_color: hex("#6ac974"),
-            branch_width: 3.0,
-        };
+fn scale_lengths() -> impl View<Data> {
+    let button = button(text("Scale lengths")).fancy(4.0);
 
-        let mut data = Self {
-            mesh: None,
-            axiom: String::from(INITIAL_AXIOM),
-            rules: String::from(INITIAL_RULES),
-            instructions: String::from(INITIAL_INSTRUCTIONS),
-            options,
-            iterations: String::from("7"),
+    on_click(button, |_, data: &mut Data| {
+        let Ok(factor) = data.length_factor.trim().parse::<f32>() else {
+            data.message = Some(format!("`{}` is not a number", data.length_factor));
+            return;
         };
 
-        data.instructions.pop();
-
-        data.generate_mesh();
-        data
-    }
+        data.transform_instructions(|instruction| instruction.scale_length(factor));
+    })
+}
 
-    fn mesh_bounds(mesh: &Mesh) -> Rect {
-        let mut bounds = Rect::ZERO;
+fn length_factor(data: &mut Data) -> impl View<Data> {
+    let input = text_input()
+        .on_change(|_, data: &mut Data, text| data.length_factor = text)
+        .text(&data.length_factor)
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
 
-        for vertex in mesh.vertices.iter() {
-            bounds.min = Point::min(bounds.min, vertex.position);
-            bounds.max = Point::max(bounds.max, vertex.position);
-        }
+    alt(
+        "Factor to scale lengths by",
+        width(60.0, input_container(input)),
+    )
+}
 
-        bounds
-    }
+fn transform_row(data: &mut Data) -> impl View<Data> {
+    hstack![flip_handedness(), scale_lengths(), length_factor(data)].gap(10.0)
+}
 
-    fn generate_mesh(&mut self) {
-        let rect = Rect::min_size(Point::ZERO, DISPLAY_SIZE);
+fn shading(data: &mut Data) -> impl View<Data> {
+    let shading =
+        slider(data.shading.strength)
+            .range(0.0..=1.0)
+            .on_input(|_, data: &mut Data, strength| {
+                data.shading.strength = strength;
+                data.invalidate(Stage::Shade);
+            });
 
-        let rules = Rules::parse(&self.rules);
+    let content = hstack![text("Shading"), flex_grow(1.0, width(FILL, shading))].gap(10.0);
+    alt("Darkens dense parts of the drawing", width(FILL, content))
+}
 
-        let mut tree = self.axiom.clone();
+/// The most turn jitter the slider goes up to, in degrees.
+const MAX_TURN_JITTER: f32 = 30.0;
 
-        for _ in 0..self.iterations() {
-            tree = rules.apply(&tree);
-        }
+fn new_seed() -> impl View<Data> {
+    let button = button(text("New seed")).fancy(4.0);
+    on_click(button, |_, data: &mut Data| data.reroll_jitter())
+}
 
-        let instructions = self.instructions().apply(&tree);
-        let mut mesh = system::generate_mesh(&self.options, &instructions);
-        let bounds = Self::mesh_bounds(&mesh);
+fn jitter_row(data: &mut Data) -> impl View<Data> {
+    let jitter = slider(data.options.turn_jitter)
+        .range(0.0..=MAX_TURN_JITTER)
+        .on_input(|_, data: &mut Data, jitter| data.set_turn_jitter(jitter.round()));
 
-        // scale and center the mesh
-        let scale = rect.size() / bounds.size();
-        let scale = f32::min(scale.width, scale.height);
-        let offset = rect.bottom() - bounds.bottom() * scale;
+    let label = text(format!("Jitter {}°", data.options.turn_jitter));
+    let content = hstack![label, flex_grow(1.0, width(FILL, jitter)), new_seed()].gap(10.0);
 
-        for vertex in mesh.vertices.iter_mut() {
-            vertex.position *= scale;
-            vertex.position += offset;
-        }
+    alt(
+        "Turns are off by up to this much either way",
+        width(FILL, content),
+    )
+}
 
-        self.mesh = Some(mesh);
-    }
+/// The least width and leaf falloff the sliders go down to, 1 is none at
+/// all.
+const MIN_WIDTH_FALLOFF: f32 = 0.5;
 
-    fn set_axiom(&mut self, axiom: String) {
-        if self.axiom != axiom {
-            self.axiom = axiom;
-            self.generate_mesh();
-        }
-    }
+fn falloff_row(data: &mut Data) -> impl View<Data> {
+    let falloff = slider(data.options.width_falloff)
+        .range(MIN_WIDTH_FALLOFF..=1.0)
+        .on_input(|_, data: &mut Data, falloff| {
+            data.set_width_falloff((falloff * 100.0).round() / 100.0)
+        });
 
-    fn rules(&self) -> Rules {
-        Rules::parse(&self.rules)
-    }
+    let min_width = text_input()
+        .on_change(|_, data: &mut Data, text| data.set_min_width(text))
+        .text(&data.min_width)
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
 
-    fn set_rules(&mut self, rules: String) {
-        let prev = self.rules();
-        self.rules = rules;
+    let min_width = alt(
+        "The narrowest branches are drawn",
+        width(60.0, input_container(min_width)),
+    );
 
-        if prev != self.rules() {
-            self.generate_mesh();
-        }
-    }
+    let label = text(format!("Falloff {}", data.options.width_falloff));
+    let content = hstack![label, flex_grow(1.0, width(FILL, falloff)), min_width].gap(10.0);
 
-    fn instructions(&self) -> Instructions {
-        let mut instructions = Instructions::parse(&self.instructions);
+    alt(
+        "What the width is multiplied by for every branch deeper",
+        width(FILL, content),
+    )
+}
 
-        instructions.insert('[', Instruction::Push);
-        instructions.insert(']', Instruction::Pop);
+fn leaf_falloff_row(data: &mut Data) -> impl View<Data> {
+    let falloff = slider(data.options.leaf_falloff)
+        .range(MIN_WIDTH_FALLOFF..=1.0)
+        .on_input(|_, data: &mut Data, falloff| {
+            data.set_leaf_falloff((falloff * 100.0).round() / 100.0)
+        });
 
-        instructions
-    }
+    let label = text(format!("Leaf falloff {}", data.options.leaf_falloff));
+    let content = hstack![label, flex_grow(1.0, width(FILL, falloff))].gap(10.0);
 
-    fn set_instructions(&mut self, instructions: String) {
-        let prev = self.instructions();
-        self.instructions = instructions;
+    alt(
+        "What the length of leaves is multiplied by for every branch deeper",
+        width(FILL, content),
+    )
+}
 
-        if prev != self.instructions() {
-            self.generate_mesh();
-        }
-    }
+/// The strongest tropism the slider goes up to, in degrees per unit.
+const MAX_TROPISM_STRENGTH: f32 = 2.0;
 
-    fn iterations(&self) -> usize {
-        self.iterations.parse().unwrap_or(0)
-    }
+fn tropism_row(data: &mut Data) -> impl View<Data> {
+    let strength = slider(data.options.tropism_strength)
+        .range(0.0..=MAX_TROPISM_STRENGTH)
+        .on_input(|_, data: &mut Data, strength| {
+            // in steps of a hundredth, so the label stays short
+            data.set_tropism_strength((strength * 100.0).round() / 100.0)
+        });
 
-    fn set_iterations(&mut self, iterations: String) {
-        let prev = self.iterations();
-        self.iterations = iterations;
+    let label = text(format!("Bend {}", data.options.tropism_strength));
+    let content = hstack![label, flex_grow(1.0, width(FILL, strength))].gap(10.0);
 
-        if prev != self.iterations() {
-            self.generate_mesh();
-        }
-    }
+    alt(
+        "How much branches bend toward the tropism",
+        width(FILL, content),
+    )
 }
 
-fn background(content: impl View<Data>) -> impl View<Data> {
-    let colors = &[style(Palette::BACKGROUND), style(Palette::BACKGROUND_LIGHT)];
-    container(content).background(gradient(-45.0, colors))
+fn gradient_color(data: &mut Data, tip: bool) -> impl View<Data> {
+    let label = match tip {
+        false => "Trunk",
+        true => "Tip",
+    };
+
+    let input = text_input()
+        .on_change(move |_, data: &mut Data, text| data.set_gradient_color(tip, text))
+        .text(&data.gradient_text[tip as usize])
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
+
+    hstack![text(label), width(100.0, input_container(input))].gap(10.0)
 }
 
-fn mesh_painter() -> impl View<Data> {
-    let painter = painter(|_cx, data: &mut Data, canvas| {
-        if let Some(mesh) = data.mesh.clone() {
-            canvas.draw(mesh);
-        }
+fn join_style(data: &mut Data) -> impl View<Data> {
+    let label = match data.options.join_style {
+        JoinStyle::Bevel => "Bevel joins",
+        JoinStyle::Round => "Round joins",
+        JoinStyle::Miter => "Miter joins",
+    };
+
+    let button = button(text(label)).fancy(4.0);
+
+    let button = on_click(button, |_, data: &mut Data| {
+        data.record(None);
+        data.options.join_style = match data.options.join_style {
+            JoinStyle::Bevel => JoinStyle::Round,
+            JoinStyle::Round => JoinStyle::Miter,
+            JoinStyle::Miter => JoinStyle::Bevel,
+        };
+        data.invalidate(Stage::Mesh);
     });
 
-    let painter = container(painter)
-        .border_bottom(1.0)
-        .border_color(style(Palette::ACCENT_DARKER));
+    alt("How branches look at turns and at their ends", button)
+}
 
-    size(DISPLAY_SIZE, painter)
+fn gradient_row(data: &mut Data) -> impl View<Data> {
+    let colors = hstack![gradient_color(data, false), gradient_color(data, true)].gap(10.0);
+    let colors = alt(
+        "Branches shade from the trunk color to the tip color, the same color turns it off",
+        colors,
+    );
+
+    hstack![colors, join_style(data)].gap(10.0)
 }
 
-fn regenerate() -> impl View<Data> {
-    let button = button(text("Regenerate").font_size(24.0)).fancy(6.0);
+fn pin() -> impl View<Data> {
+    let button = button(text("Pin A")).fancy(4.0);
 
     on_click(button, |_, data: &mut Data| {
-        data.generate_mesh();
-        info!("Regenerating mesh");
+        data.pinned = Some(data.definition());
+        data.blend = None;
     })
 }
 
-fn restart() -> impl View<Data> {
-    let button = button(text("Restart").font_size(24.0)).fancy(6.0);
+fn unpin() -> impl View<Data> {
+    let button = button(text("Unpin")).fancy(4.0);
 
     on_click(button, |_, data: &mut Data| {
-        *data = Data::new();
-        info!("Restarting");
+        data.pinned = None;
+        data.blend = None;
     })
 }
 
-fn button_row() -> impl View<Data> {
-    hstack![regenerate(), restart()].gap(10.0)
+fn blend_row(data: &mut Data) -> impl View<Data> {
+    let t = data.blend.as_ref().map_or(1.0, |blend| blend.t);
+
+    let blendable =
+        (data.pinned.as_ref()).map(|pinned| pinned.blend(&data.blend_target(), t).map(|_| ()));
+
+    // the slider only shows up once there's something to blend between
+    let blend_slider = matches!(blendable, Some(Ok(()))).then(|| {
+        let slider = slider(t)
+            .range(0.0..=1.0)
+            .on_input(|_, data: &mut Data, t| data.set_blend(t));
+
+        flex_grow(1.0, width(FILL, slider))
+    });
+
+    let reason = match blendable {
+        Some(Err(reason)) => Some(text(format!("Can't blend: {}", reason))),
+        _ => None,
+    };
+
+    hstack![pin(), unpin(), blend_slider, reason].gap(10.0)
+}
+
+fn message(data: &mut Data) -> impl View<Data> {
+    text(data.message.as_deref().unwrap_or_default())
+}
+
+/// `n` with its digits grouped by thousands, like 5,000,000.
+fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+
+        grouped.push(digit);
+    }
+
+    grouped
+}
+
+fn truncated(data: &mut Data) -> Option<impl View<Data>> {
+    let limit = data.truncated?;
+    let message = format!("Expansion truncated at {} symbols", group_digits(limit));
+
+    Some(text(message))
 }
 
 fn input_container(content: impl View<Data>) -> impl View<Data> {
@@ -196,18 +1853,46 @@ fn iterations(data: &mut Data) -> impl View<Data> {
         .text(&data.iterations)
         .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
 
-    alt("Number of iterations", width(100.0, input_container(input)))
+    // a new job waits for the running one to hand the expansions back, so
+    // dragging regenerates as it goes without queueing up work
+    let max = usize::max(data.iterations(), data.view.max_iterations);
+    let slider = slider(data.iterations() as f32)
+        .range(0.0..=max as f32)
+        .on_input(|_, data: &mut Data, iterations| {
+            data.set_iterations(iterations.round().to_string())
+        });
+
+    let content = hstack![width(120.0, slider), width(60.0, input_container(input))].gap(10.0);
+    alt("Number of iterations", content)
+}
+
+fn symbol_limit(data: &mut Data) -> impl View<Data> {
+    let input = text_input()
+        .on_change(|_, data: &mut Data, text| data.set_symbol_limit(text))
+        .text(&data.symbol_limit)
+        .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
+
+    alt(
+        "Most symbols the expansion may grow to",
+        width(100.0, input_container(input)),
+    )
 }
 
 fn axiom_row(data: &mut Data) -> impl View<Data> {
-    let content = hstack![flex_grow(1.0, axiom(data)), iterations(data)].gap(10.0);
+    let content = hstack![
+        flex_grow(1.0, axiom(data)),
+        iterations(data),
+        symbol_limit(data)
+    ]
+    .gap(10.0);
+
     width(FILL, content)
 }
 
 fn rules(data: &mut Data) -> impl View<Data> {
     let input = text_input()
         .on_change(|_, data: &mut Data, text| data.set_rules(text))
-        .text(&data.rules)
+        .text(&data.rules_text)
         .multiline(true)
         .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
 
@@ -217,10 +1902,33 @@ fn rules(data: &mut Data) -> impl View<Data> {
     )
 }
 
+/// The color of problems that don't stop the system from being drawn.
+fn warning_color() -> Color {
+    hex("#7c3f00")
+}
+
+/// Lists `problems` one per line, nothing when there are none.
+fn problems(problems: &[impl ToString]) -> Option<impl View<Data>> {
+    if problems.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<_> = problems.iter().map(ToString::to_string).collect();
+    Some(text(lines.join("\n")).color(warning_color()))
+}
+
+fn rule_errors(data: &mut Data) -> Option<impl View<Data>> {
+    problems(&data.rule_errors)
+}
+
+fn instruction_problems(data: &mut Data) -> Option<impl View<Data>> {
+    problems(&data.instruction_problems)
+}
+
 fn instructions(data: &mut Data) -> impl View<Data> {
     let input = text_input()
         .on_change(|_, data: &mut Data, text| data.set_instructions(text))
-        .text(&data.instructions)
+        .text(&data.instructions_text)
         .multiline(true)
         .font_family(FontFamily::Name(String::from("Noto Sans Mono")));
 
@@ -230,18 +1938,53 @@ fn instructions(data: &mut Data) -> impl View<Data> {
     )
 }
 
+fn brackets(data: &mut Data) -> Option<impl View<Data>> {
+    let error = data.brackets?;
+    Some(text(format!("Unbalanced brackets: {}", error)))
+}
+
 fn ui(data: &mut Data) -> impl View<Data> {
+    // the view is rebuilt after every batch of changes, regenerate once here
+    data.check_file();
+    data.update();
+
     let content = vstack![
         mesh_painter(),
+        generating(data),
         button_row(),
+        history_row(data),
+        preset_row(data),
+        file_row(data),
+        share_row(),
+        expansion_row(data),
+        export_row(),
+        png_row(data),
+        transform_row(data),
+        blend_row(data),
+        shading(data),
+        falloff_row(data),
+        leaf_falloff_row(data),
+        jitter_row(data),
+        tropism_row(data),
+        gradient_row(data),
+        inspect_row(data),
+        animation_row(data),
+        inspector(data),
+        reload_banner(data),
+        message(data),
+        cancel_sheet(data),
+        truncated(data),
         axiom_row(data),
         rules(data),
-        instructions(data)
+        rule_errors(data),
+        instructions(data),
+        instruction_problems(data),
+        brackets(data)
     ]
     .align_items(Align::Center)
     .gap(12.0);
 
-    let content = vscroll(content);
+    let content = worker_events(history_keys(vscroll(content)));
 
     size(FILL, background(pad(20.0, top(content))))
 }
@@ -254,14 +1997,504 @@ fn palette() -> Palette {
     }
 }
 
-fn main() {
+fn main() -> ExitCode {
+    #[allow(unused_mut)]
+    let mut data = Data::new();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::args_os().len() > 1 {
+        match cli::run() {
+            cli::Action::Exit(code) => return code,
+            cli::Action::Launch(project, path) => {
+                data.load_project(*project);
+
+                if let Some(path) = path {
+                    data.watch_file(path);
+                }
+            }
+        }
+    }
+
     let window = WindowDescriptor::new()
         .title("Lily")
         .size(500, 800)
         .resizable(false);
 
-    Launcher::new(Data::new())
+    Launcher::new(data)
         .window(window, ui)
         .theme(palette)
         .launch();
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use lily::project::ProjectFormat;
+
+    use super::*;
+
+    thread_local! {
+        /// The texts parsed on this thread, see [`parses`].
+        static PARSES: RefCell<Vec<Field>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub(super) fn count_parse(field: Field) {
+        PARSES.with(|parses| parses.borrow_mut().push(field));
+    }
+
+    /// The texts `edit` parses.
+    fn parses(data: &mut Data, edit: impl FnOnce(&mut Data)) -> Vec<Field> {
+        PARSES.with(|parses| parses.borrow_mut().clear());
+        edit(data);
+        PARSES.with(|parses| mem::take(&mut *parses.borrow_mut()))
+    }
+
+    /// Updates until the worker is done with every edit.
+    fn settle(data: &mut Data) {
+        loop {
+            data.update();
+
+            if data.running.is_none() && data.dirty.is_none() {
+                return;
+            }
+
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn slider_steps_continue_the_expansions() {
+        let mut data = Data::new();
+        settle(&mut data);
+
+        let kept = data.derivation.as_ref().unwrap().trees()[7].as_ptr();
+
+        // each step replaces the job of the one before while it's running
+        for iterations in 8..=10 {
+            data.set_iterations(iterations.to_string());
+            data.update();
+        }
+
+        settle(&mut data);
+
+        // the expansion from before the steps is the same one, not redone
+        let derivation = data.derivation.as_ref().unwrap();
+        assert_eq!(derivation.trees()[7].as_ptr(), kept);
+        assert_eq!(data.tree, derivation.trees()[10]);
+    }
+
+    #[test]
+    fn expansions_are_kept_until_the_rules_change() {
+        use Stage::*;
+
+        let mut data = Data::new();
+        settle(&mut data);
+
+        let trees = |data: &Data| data.derivation.as_ref().unwrap().trees().len();
+        let kept = |data: &Data| data.derivation.as_ref().unwrap().trees()[7].as_ptr();
+        let before = kept(&data);
+
+        // options only regenerate the mesh
+        let ran = stages_run(&mut data, |data| data.set_width_falloff(0.5));
+        assert_eq!(ran, [Mesh, Shade, Fit]);
+        assert_eq!(kept(&data), before);
+
+        // one more iteration applies the rules once more
+        let ran = stages_run(&mut data, |data| data.set_iterations(String::from("8")));
+        assert_eq!(ran, [Expand, Shade, Fit]);
+        assert_eq!(trees(&data), 9);
+        assert_eq!(kept(&data), before);
+
+        // new rules or a new axiom start over
+        let tree = data.tree.clone();
+        stages_run(&mut data, |data| {
+            data.set_rules(String::from("A -> F[+A]-A\nF -> FF"))
+        });
+        assert_ne!(data.tree, tree);
+        assert!(data
+            .derivation
+            .as_ref()
+            .unwrap()
+            .is_for(&data.rules, &data.axiom, data.seed));
+        assert_eq!(trees(&data), 9);
+
+        stages_run(&mut data, |data| data.set_axiom(String::from("FX")));
+        assert!(data
+            .derivation
+            .as_ref()
+            .unwrap()
+            .is_for(&data.rules, "FX", data.seed));
+        assert_eq!(data.tree, data.derivation.as_ref().unwrap().trees()[8]);
+    }
+
+    #[test]
+    fn rerolled_seeds_save_as_toml() {
+        let mut data = Data::new();
+
+        for _ in 0..16 {
+            data.reroll_jitter();
+            assert!(data.options.jitter_seed <= i64::MAX as u64);
+
+            let text = data.project().to_string(ProjectFormat::Toml).unwrap();
+            let project = Project::parse(&text, ProjectFormat::Toml).unwrap();
+            assert_eq!(
+                project.definition.options.jitter_seed,
+                data.options.jitter_seed
+            );
+        }
+    }
+
+    #[test]
+    fn shown_mesh_places_the_shared_mesh() {
+        let mut data = Data::new();
+        settle(&mut data);
+
+        data.view.zoom = 2.0;
+        data.view.pan = [15.0, -4.0];
+
+        // what fitting and viewing a copy of the whole mesh used to draw
+        let rect = Rect::min_size(Point::ZERO, DISPLAY_SIZE);
+        let mut expected = Mesh::clone(&data.shaded_mesh);
+        system::fit_mesh(&mut expected, rect, data.options.growth, data.view.fit);
+        apply_view(&mut expected, &data.view);
+        expected.indices.truncate(30);
+
+        let shown = shown_mesh(&data.shaded_mesh, 30, data.fit, &data.view);
+        assert_eq!(shown.indices, expected.indices);
+        assert_eq!(shown.vertices.len(), expected.vertices.len());
+
+        for (shown, expected) in shown.vertices.iter().zip(&expected.vertices) {
+            assert!((shown.position - expected.position).length() < 1e-3);
+            assert_eq!(shown.color, expected.color);
+        }
+    }
+
+    #[test]
+    fn fits_center_the_mesh_in_the_display() {
+        let mut data = Data::new();
+
+        // the dragon grows off to one side of where it starts
+        let dragon = PRESETS
+            .iter()
+            .find(|preset| preset.name == "Dragon")
+            .unwrap();
+        data.load_preset(dragon);
+        settle(&mut data);
+
+        let bounds = system::mesh_bounds(&data.shaded_mesh);
+        assert!((bounds.min.x + bounds.max.x).abs() > 1.0);
+
+        let fitted = |data: &Data| {
+            let bounds = system::mesh_bounds(&data.shaded_mesh);
+            let (scale, offset) = data.fit;
+            (bounds.min * scale + offset, bounds.max * scale + offset)
+        };
+
+        let (min, max) = fitted(&data);
+        assert!((min.x + max.x - DISPLAY_SIZE.width).abs() < 1e-3);
+        assert!((max.y - DISPLAY_SIZE.height).abs() < 1e-3);
+        assert!(min.x >= -1e-3 && min.y >= -1e-3);
+
+        data.view.fit = FitMode::Center;
+        data.invalidate(Stage::Fit);
+        settle(&mut data);
+
+        let (min, max) = fitted(&data);
+        assert!((min.x + max.x - DISPLAY_SIZE.width).abs() < 1e-3);
+        assert!((min.y + max.y - DISPLAY_SIZE.height).abs() < 1e-3);
+        assert!(min.x >= -1e-3 && min.y >= -1e-3);
+        assert!(max.x <= DISPLAY_SIZE.width + 1e-3 && max.y <= DISPLAY_SIZE.height + 1e-3);
+    }
+
+    #[test]
+    fn cancelled_sheets_write_nothing() {
+        let exports = || {
+            let mut paths: Vec<_> = (std::fs::read_dir(".").unwrap())
+                .map(|entry| entry.unwrap().file_name())
+                .filter(|name| name.to_string_lossy().starts_with("lily-"))
+                .collect();
+            paths.sort();
+            paths
+        };
+        let before = exports();
+
+        let mut data = Data::new();
+        data.snapshots = (0..100)
+            .map(|i| Snapshot {
+                name: i.to_string(),
+                definition: data.definition(),
+            })
+            .collect();
+
+        data.export_contact_sheet();
+        data.worker.cancel_sheet();
+
+        while data.worker.sheet_progress().is_some() {
+            data.update_sheet();
+            std::thread::yield_now();
+        }
+
+        assert_eq!(
+            data.message.as_deref(),
+            Some("Contact sheet export cancelled")
+        );
+        assert_eq!(exports(), before);
+    }
+
+    #[test]
+    fn both_svg_exports() {
+        let mut data = Data::new();
+        settle(&mut data);
+
+        let segments = data
+            .stream
+            .iter()
+            .filter(|instruction| matches!(instruction, Instruction::Forward(_)));
+
+        let lines = lines_svg(&data);
+        assert_eq!(lines.matches("<line").count(), segments.count());
+        assert!(!lines.contains("<path"));
+
+        let mesh = mesh_svg(&data);
+        assert!(mesh.contains("<path"));
+        assert!(!mesh.contains("<line"));
+    }
+
+    #[test]
+    fn edits_are_parsed_once() {
+        let mut data = Data::new();
+        settle(&mut data);
+
+        // the parsed rules are kept, an edit that parses the same changes nothing
+        let rules = data.rules.clone();
+        let text = format!("{}\n# a comment", data.rules_text);
+        assert_eq!(
+            parses(&mut data, |data| data.set_rules(text)),
+            [Field::Rules]
+        );
+        assert_eq!(data.rules, rules);
+        assert_eq!(data.dirty, None);
+
+        let text = String::from("F -> FF");
+        assert_eq!(
+            parses(&mut data, |data| data.set_rules(text)),
+            [Field::Rules]
+        );
+        assert_eq!(data.rules, Rules::parse("F -> FF"));
+        assert_eq!(data.dirty, Some(Stage::Expand));
+        settle(&mut data);
+
+        // `[` and `]` are bound once, when the text is parsed
+        let instructions = data.instructions.clone();
+        assert_eq!(instructions.get('['), Some(&Instruction::Push));
+
+        // the duplicate is found without parsing the text again
+        let text = format!("{}\nQ = forward 1\nQ = forward 2", data.instructions_text);
+        let parsed = parses(&mut data, |data| data.set_instructions(text));
+        assert_eq!(parsed, [Field::Instructions]);
+        assert_eq!(data.instruction_problems.len(), 1);
+        assert_eq!(data.instruction_problems[0].line, Some(5));
+        assert_eq!(data.dirty, Some(Stage::Interpret));
+        settle(&mut data);
+
+        let text = data.instructions_text.clone();
+        assert_eq!(
+            parses(&mut data, |data| data.set_instructions(text)),
+            [Field::Instructions]
+        );
+        assert_eq!(data.dirty, None);
+
+        // loading parses each text once too
+        let definition = data.definition();
+        let parsed = parses(&mut data, |data| data.load_definition(definition));
+        assert_eq!(parsed, [Field::Rules, Field::Instructions]);
+
+        // and running the stages doesn't parse anything
+        assert_eq!(parses(&mut data, settle), []);
+    }
+
+    /// The stages `edit` runs once it's settled.
+    fn stages_run(data: &mut Data, edit: impl FnOnce(&mut Data)) -> Vec<Stage> {
+        settle(data);
+        data.ran.clear();
+
+        edit(data);
+        settle(data);
+
+        mem::take(&mut data.ran)
+    }
+
+    #[test]
+    fn edits_run_only_the_dirty_stages() {
+        use Stage::*;
+
+        let mut data = Data::new();
+
+        let plant = PRESETS
+            .iter()
+            .find(|preset| preset.name == "Plant")
+            .unwrap();
+        let ran = stages_run(&mut data, |data| data.load_preset(plant));
+        assert_eq!(ran, [Expand, Shade, Fit]);
+
+        // several edits before an update still run once
+        let ran = stages_run(&mut data, |data| {
+            data.set_turn_jitter(3.0);
+            data.set_width_falloff(0.8);
+            data.set_instructions(format!("{}\n+ = turn 20", data.instructions_text));
+        });
+        assert_eq!(ran, [Interpret, Shade, Fit]);
+
+        let ran = stages_run(&mut data, |data| data.set_width_falloff(0.7));
+        assert_eq!(ran, [Mesh, Shade, Fit]);
+
+        let ran = stages_run(&mut data, |data| data.invalidate(Shade));
+        assert_eq!(ran, [Shade, Fit]);
+
+        let ran = stages_run(&mut data, |data| data.invalidate(Fit));
+        assert_eq!(ran, [Fit]);
+
+        let ran = stages_run(&mut data, |_| {});
+        assert_eq!(ran, []);
+    }
+
+    #[test]
+    fn min_width_regenerates_the_mesh() {
+        use Stage::*;
+
+        let mut data = Data::new();
+
+        let ran = stages_run(&mut data, |data| data.set_min_width(String::from("1.5")));
+        assert_eq!(ran, [Mesh, Shade, Fit]);
+        assert_eq!(data.options.min_width, 1.5);
+
+        // the text is kept as typed, but only a width changes the options
+        for text in ["-1", "wide", "1.5 "] {
+            let ran = stages_run(&mut data, |data| data.set_min_width(String::from(text)));
+            assert_eq!(ran, []);
+            assert_eq!(data.min_width, text);
+            assert_eq!(data.options.min_width, 1.5);
+        }
+    }
+
+    #[test]
+    fn transforms_are_undone_on_their_own() {
+        let mut data = Data::new();
+        settle(&mut data);
+
+        data.set_instructions(String::from("F = forward 2\n+ = turn 25"));
+        let typed = data.instructions.clone();
+
+        let ran = stages_run(&mut data, |data| {
+            data.transform_instructions(Instruction::negate_angle)
+        });
+        assert_eq!(ran, [Stage::Interpret, Stage::Shade, Stage::Fit]);
+        assert_eq!(data.instructions.get('+'), Some(&Instruction::Turn(-25.0)));
+
+        data.transform_instructions(|instruction| instruction.scale_length(3.0));
+        assert_eq!(data.instructions.get('F'), Some(&Instruction::Forward(6.0)));
+
+        data.undo();
+        assert_eq!(data.instructions.get('F'), Some(&Instruction::Forward(2.0)));
+        assert_eq!(data.instructions.get('+'), Some(&Instruction::Turn(-25.0)));
+
+        data.undo();
+        assert_eq!(data.instructions, typed);
+    }
+
+    #[test]
+    fn blends_from_the_pinned_definition() {
+        let mut data = Data::new();
+        data.set_instructions(String::from("F = forward 2\n+ = turn 20"));
+        data.pinned = Some(data.definition());
+
+        data.set_instructions(String::from("F = forward 4\n+ = turn 40"));
+        let target = data.definition();
+
+        // the slider keeps blending towards the edit, not what it produced
+        data.set_blend(0.5);
+        assert_eq!(data.instructions.get('+'), Some(&Instruction::Turn(30.0)));
+        data.set_blend(0.254);
+        assert_eq!(data.instructions.get('+'), Some(&Instruction::Turn(25.0)));
+        data.set_blend(1.0);
+        assert_eq!(data.definition(), target);
+
+        data.set_rules(String::from("F -> FF"));
+        data.set_blend(0.5);
+        assert_eq!(
+            data.message.as_deref(),
+            Some("Can't blend: the rules are different")
+        );
+    }
+
+    /// The fields a project file keeps.
+    fn saved_fields(data: &Data) -> (String, String, String, String, SystemOptions) {
+        (
+            data.axiom.clone(),
+            data.rules_text.clone(),
+            data.instructions_text.clone(),
+            data.iterations.clone(),
+            data.options.clone(),
+        )
+    }
+
+    #[test]
+    fn projects_save_and_load() {
+        let directory = std::env::temp_dir().join(format!("lily-save-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for name in ["system.lsys", "system.toml"] {
+            let mut data = Data::new();
+            data.set_axiom(String::from("FX"));
+            data.set_rules(String::from("X -> F[+X]-X # fork"));
+            data.set_instructions(String::from("F = forward 3\nX = leaf 4"));
+            data.set_iterations(String::from("5"));
+            data.options.branch_color = Color::rgb(0.2, 0.4, 0.6);
+            data.options.branch_width = 6.5;
+            let saved = saved_fields(&data);
+
+            data.file_path = directory.join(name).display().to_string();
+            data.save_file();
+            assert!(data.message.as_ref().unwrap().starts_with("Saved"));
+
+            let mut loaded = Data::new();
+            loaded.file_path = data.file_path.clone();
+            loaded.load_file();
+            assert_eq!(saved_fields(&loaded), saved);
+            assert_eq!(loaded.dirty, Some(Stage::Expand));
+        }
+
+        // a malformed file leaves everything as it was
+        let malformed = directory.join("malformed.lsys");
+        std::fs::write(&malformed, "{ \"axiom\": ").unwrap();
+
+        let mut data = Data::new();
+        let before = saved_fields(&data);
+        data.file_path = malformed.display().to_string();
+        data.load_file();
+
+        assert!(data.message.as_ref().unwrap().starts_with("Could not load"));
+        assert_eq!(saved_fields(&data), before);
+    }
+
+    #[test]
+    fn parse_errors_are_listed_under_the_editors() {
+        let mut data = Data::new();
+
+        data.set_rules(String::from("F => FF"));
+        let errors: Vec<_> = data.rule_errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors, ["line 1: invalid rule `F => FF`: expected `->`"]);
+
+        data.set_rules(String::from("F -> FF"));
+        assert!(data.rule_errors.is_empty());
+
+        data.set_instructions(String::from("F = forward 1\nG = fly"));
+        let problems = &data.instruction_problems;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(2));
+        assert!(problems[0].message.contains("unknown instruction `fly`"));
+    }
 }
@@ -0,0 +1,52 @@
+//! Well-known systems to start from.
+//!
+//! Every preset is a TOML project in `src/presets`, adding one is adding a
+//! file and a line to [`PRESETS`].
+
+use crate::project::{Definition, Project, ProjectFormat};
+
+/// A named system that ships with lily.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preset {
+    pub name: &'static str,
+    /// The project, as TOML.
+    pub source: &'static str,
+}
+
+impl Preset {
+    pub fn definition(&self) -> Definition {
+        let project = Project::parse(self.source, ProjectFormat::Toml);
+        project.expect("presets are valid projects").definition
+    }
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "Koch",
+        source: include_str!("presets/koch.toml"),
+    },
+    Preset {
+        name: "Dragon",
+        source: include_str!("presets/dragon.toml"),
+    },
+    Preset {
+        name: "Sierpinski",
+        source: include_str!("presets/sierpinski.toml"),
+    },
+    Preset {
+        name: "Plant",
+        source: include_str!("presets/plant.toml"),
+    },
+    Preset {
+        name: "Bush",
+        source: include_str!("presets/bush.toml"),
+    },
+    Preset {
+        name: "Tree",
+        source: include_str!("presets/tree.toml"),
+    },
+    Preset {
+        name: "Hilbert",
+        source: include_str!("presets/hilbert.toml"),
+    },
+];
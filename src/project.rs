@@ -0,0 +1,530 @@
+//! Project files bundling a whole system.
+//!
+//! A project is stored as either JSON or TOML, picked by the file extension.
+//! A definition can also be written as plain text, see
+//! [`Definition::parse_text`], which loses everything but the definition.
+//!
+//! Every file carries a `version`, files written by a newer lily are rejected
+//! and files written by an older one are migrated, with missing fields taking
+//! their defaults.
+//!
+//! There's no file system on the web, so [`Project::load`] and
+//! [`Project::save`] are only available on native targets, use
+//! [`Project::parse`] and [`Project::to_string`] there instead.
+
+mod text;
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    system::{
+        self, parse_modules, FitMode, Instruction, Instructions, Module, Pcg32, Rules,
+        SystemOptions,
+    },
+    LilyError,
+};
+
+pub use text::SourceLines;
+
+/// The newest project version this build can read, and the one it writes.
+pub const PROJECT_VERSION: u32 = 1;
+
+/// Everything needed to regenerate a system.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Definition {
+    pub axiom: String,
+    pub rules: String,
+    pub instructions: String,
+    pub iterations: usize,
+    /// Seeds the choices between stochastic rules.
+    #[serde(with = "system::seed_i64")]
+    pub seed: u64,
+    pub options: SystemOptions,
+}
+
+impl Definition {
+    pub fn rules(&self) -> Rules {
+        Rules::parse(&self.rules)
+    }
+
+    /// Parses the instructions, with `[` and `]` bound to push and pop.
+    pub fn instructions(&self) -> Instructions {
+        let mut instructions = Instructions::parse(&self.instructions);
+        instructions.insert_defaults();
+        instructions
+    }
+
+    /// Applies the rules to the axiom `iterations` times, stochastic rules
+    /// are picked with [`Definition::seed`].
+    pub fn expand(&self) -> String {
+        let rules = self.rules();
+        let mut rng = Pcg32::new(self.seed);
+        let mut tree = self.axiom.clone();
+
+        for _ in 0..self.iterations {
+            tree = rules.apply(&tree, &mut rng);
+        }
+
+        tree
+    }
+
+    /// Like [`Definition::expand`], keeping the tree as modules.
+    pub fn expand_modules(&self) -> Vec<Module> {
+        let rules = self.rules();
+        let mut rng = Pcg32::new(self.seed);
+        let mut tree = parse_modules(&self.axiom);
+
+        for _ in 0..self.iterations {
+            tree = rules.apply_modules(&tree, &mut rng);
+        }
+
+        tree
+    }
+
+    /// Like [`Definition::expand`], stopping with
+    /// [`LilyError::ExpansionBudget`] once an expansion grows past `limit`
+    /// symbols.
+    pub fn try_expand(&self, limit: usize) -> Result<String> {
+        let rules = self.rules();
+        let expansions = (rules.expansions(&self.axiom))
+            .with_limit(limit)
+            .with_seed(self.seed);
+
+        // an expansion past the limit is the last one yielded
+        let last = expansions.take(self.iterations + 1).last();
+        last.unwrap_or_else(|| Ok(self.axiom.clone()))
+    }
+
+    /// Like [`Definition::expand_modules`], stopping with
+    /// [`LilyError::ExpansionBudget`] once an expansion grows past `limit`
+    /// modules.
+    pub fn try_expand_modules(&self, limit: usize) -> Result<Vec<Module>> {
+        let rules = self.rules();
+        let mut rng = Pcg32::new(self.seed);
+        let mut tree = parse_modules(&self.axiom);
+
+        for iteration in 1..=self.iterations {
+            let next = rules.apply_modules(&tree, &mut rng);
+
+            if next.len() > limit {
+                return Err(LilyError::ExpansionBudget {
+                    limit,
+                    iteration,
+                    partial: tree.iter().map(Module::to_string).collect(),
+                });
+            }
+
+            tree = next;
+        }
+
+        Ok(tree)
+    }
+
+    /// Expands the system and turns it into instructions for the turtle.
+    ///
+    /// Parametric systems are expanded as modules.
+    pub fn interpret(&self) -> Vec<Instruction> {
+        if self.rules().is_parametric() {
+            return self.instructions().apply_modules(&self.expand_modules());
+        }
+
+        self.instructions().apply(&self.expand())
+    }
+
+    /// Like [`Definition::interpret`], expanding to at most `limit` symbols,
+    /// see [`Definition::try_expand`].
+    pub fn try_interpret(&self, limit: usize) -> Result<Vec<Instruction>> {
+        if self.rules().is_parametric() {
+            let modules = self.try_expand_modules(limit)?;
+            return Ok(self.instructions().apply_modules(&modules));
+        }
+
+        Ok(self.instructions().apply(&self.try_expand(limit)?))
+    }
+
+    /// Blends the numbers of two versions of a system, at `t` between 0 and 1.
+    ///
+    /// Only instruction arguments and options are blended, the axiom, rules,
+    /// iterations and coordinate conventions have to match and the seed of
+    /// `self` is kept. The
+    /// reason they can't be blended is returned otherwise.
+    pub fn blend(&self, other: &Self, t: f32) -> std::result::Result<Self, String> {
+        if self.axiom != other.axiom {
+            return Err(String::from("the axioms are different"));
+        }
+
+        if self.rules() != other.rules() {
+            return Err(String::from("the rules are different"));
+        }
+
+        if self.iterations != other.iterations {
+            return Err(String::from("the iterations are different"));
+        }
+
+        if self.options.axes() != other.options.axes() {
+            return Err(String::from("the turn directions or growth are different"));
+        }
+
+        Ok(Self {
+            instructions: Instructions::blend_text(&self.instructions, &other.instructions, t)?,
+            options: self.options.lerp(&other.options, t),
+            ..self.clone()
+        })
+    }
+}
+
+/// How the system is displayed, this doesn't affect the generated mesh.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewOptions {
+    pub zoom: f32,
+    pub pan: [f32; 2],
+    pub fit: FitMode,
+    /// The end of the app's iterations slider, typing more iterations than
+    /// this takes the slider past it.
+    pub max_iterations: usize,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: [0.0, 0.0],
+            fit: FitMode::default(),
+            max_iterations: 12,
+        }
+    }
+}
+
+/// A named definition saved alongside a project.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub name: String,
+    #[serde(flatten)]
+    pub definition: Definition,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    pub version: u32,
+    #[serde(flatten)]
+    pub definition: Definition,
+    pub view: ViewOptions,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            definition: Definition::default(),
+            view: ViewOptions::default(),
+            snapshots: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectFormat {
+    Json,
+    Toml,
+    /// The plain text format, see [`Definition::parse_text`].
+    Text,
+}
+
+impl ProjectFormat {
+    /// Picks the format from the extension of `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+
+        match extension.to_ascii_lowercase().as_str() {
+            // `.lily` is the native project format, which is JSON
+            "json" | "lily" | "lsys" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "txt" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the contents of a file.
+    ///
+    /// Plain text is never valid TOML, a `name: value` or `A -> B` line is
+    /// enough to tell them apart.
+    pub fn detect(text: &str) -> Self {
+        if text.trim_start().starts_with('{') {
+            Self::Json
+        } else if toml::from_str::<toml::Table>(text).is_ok() {
+            Self::Toml
+        } else {
+            Self::Text
+        }
+    }
+}
+
+impl Project {
+    pub fn new(definition: Definition) -> Self {
+        Self {
+            definition,
+            ..Default::default()
+        }
+    }
+
+    /// Loads a project, the format is picked by extension and falls back to
+    /// [`ProjectFormat::detect`] for unknown extensions.
+    ///
+    /// Fractint `.l` files are imported, keeping the first system.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| LilyError::io(path, err))?;
+
+        if path.extension().is_some_and(|extension| extension == "l") {
+            let system = system::import_fractint(&text)?.into_iter().next();
+            let system =
+                system.ok_or_else(|| LilyError::Project(String::from("no systems found")))?;
+
+            return Ok(Self::new(system.definition));
+        }
+
+        let format = ProjectFormat::from_path(path).unwrap_or_else(|| ProjectFormat::detect(&text));
+        Self::parse(&text, format)
+    }
+
+    /// Saves the project, the format is picked by extension and defaults to JSON.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let format = ProjectFormat::from_path(path).unwrap_or(ProjectFormat::Json);
+        let text = self.to_string(format)?;
+
+        std::fs::write(path, text).map_err(|err| LilyError::io(path, err))
+    }
+
+    pub fn parse(text: &str, format: ProjectFormat) -> Result<Self> {
+        let malformed = |err: &dyn std::fmt::Display| LilyError::Project(err.to_string());
+
+        let value = match format {
+            ProjectFormat::Json => serde_json::from_str(text).map_err(|err| malformed(&err))?,
+            ProjectFormat::Toml => {
+                let table: toml::Table = toml::from_str(text).map_err(|err| malformed(&err))?;
+                serde_json::to_value(table).map_err(|err| malformed(&err))?
+            }
+            ProjectFormat::Text => return Ok(Self::new(Definition::parse_text(text))),
+        };
+
+        let mut project: Self =
+            serde_json::from_value(migrate(value)?).map_err(|err| malformed(&err))?;
+        project.version = PROJECT_VERSION;
+
+        Ok(project)
+    }
+
+    /// The parts of the project that writing it as `format` would drop.
+    pub fn lost_in(&self, format: ProjectFormat) -> Vec<&'static str> {
+        let mut lost = Vec::new();
+
+        if format == ProjectFormat::Text {
+            if self.definition.options != SystemOptions::default() {
+                lost.push("options");
+            }
+
+            if self.view != ViewOptions::default() {
+                lost.push("view");
+            }
+
+            if !self.snapshots.is_empty() {
+                lost.push("snapshots");
+            }
+        }
+
+        lost
+    }
+
+    pub fn to_string(&self, format: ProjectFormat) -> Result<String> {
+        let malformed = |err: &dyn std::fmt::Display| LilyError::Project(err.to_string());
+
+        match format {
+            ProjectFormat::Json => {
+                serde_json::to_string_pretty(self).map_err(|err| malformed(&err))
+            }
+            ProjectFormat::Toml => toml::to_string_pretty(self).map_err(|err| malformed(&err)),
+            ProjectFormat::Text => Ok(self.definition.to_text()),
+        }
+    }
+}
+
+/// Brings a project written by an older version up to [`PROJECT_VERSION`].
+///
+/// Files without a `version` predate versioning and are treated as version 1.
+fn migrate(value: serde_json::Value) -> Result<serde_json::Value> {
+    let version = match value.get("version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| LilyError::Project(format!("invalid version `{}`", version)))?,
+        None => 1,
+    };
+
+    if version > PROJECT_VERSION {
+        return Err(LilyError::UnsupportedVersion {
+            found: version,
+            supported: PROJECT_VERSION,
+        });
+    }
+
+    // version 1 is the first format, every field added since has a default
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_seeds(seed: u64, jitter_seed: u64) -> Project {
+        let mut definition = Definition {
+            axiom: String::from("F"),
+            seed,
+            ..Default::default()
+        };
+        definition.options.jitter_seed = jitter_seed;
+
+        Project::new(definition)
+    }
+
+    #[test]
+    fn large_seeds_round_trip() {
+        let project = with_seeds(1 << 63, u64::MAX - 5);
+
+        for format in [ProjectFormat::Json, ProjectFormat::Toml] {
+            let text = project.to_string(format).unwrap();
+            let parsed = Project::parse(&text, format).unwrap();
+
+            assert_eq!(parsed.definition.seed, 1 << 63);
+            assert_eq!(parsed.definition.options.jitter_seed, u64::MAX - 5);
+        }
+    }
+
+    #[test]
+    fn unsigned_seeds_are_read() {
+        let text = r#"{ "version": 1, "seed": 18446744073709551615 }"#;
+        let project = Project::parse(text, ProjectFormat::Json).unwrap();
+
+        assert_eq!(project.definition.seed, u64::MAX);
+    }
+
+    #[test]
+    fn bounded_expansions() {
+        let mut definition = Definition {
+            axiom: String::from("A(1)"),
+            rules: String::from("A(x) -> A(x*2)B"),
+            iterations: 3,
+            ..Default::default()
+        };
+
+        // within the limit it's the same as without
+        let modules = definition.try_expand_modules(4).unwrap();
+        assert_eq!(modules, definition.expand_modules());
+
+        match definition.try_expand_modules(3) {
+            Err(LilyError::ExpansionBudget {
+                iteration, partial, ..
+            }) => assert_eq!((iteration, partial.as_str()), (3, "A(4)BB")),
+            other => panic!("expected an expansion budget error, got {:?}", other),
+        }
+
+        definition.axiom = String::from("F");
+        definition.rules = String::from("F -> FF");
+        assert_eq!(definition.try_expand(8).unwrap(), definition.expand());
+        assert!(definition.try_expand(7).is_err());
+    }
+
+    #[test]
+    fn blends_numbers_only() {
+        let a = Definition {
+            axiom: String::from("F"),
+            rules: String::from("F -> F[+F]F"),
+            instructions: String::from("F = forward 2\n+ = turn 20"),
+            iterations: 3,
+            seed: 7,
+            ..Default::default()
+        };
+
+        let mut b = Definition {
+            instructions: String::from("F = forward 4\n+ = turn 40"),
+            seed: 9,
+            ..a.clone()
+        };
+        b.options.taper = 0.5;
+
+        // the seed is held, everything else moves
+        let blended = a.blend(&b, 0.5).unwrap();
+        assert_eq!(blended.instructions, "F = forward 3\n+ = turn 30");
+        assert_eq!(blended.options.taper, 0.75);
+        assert_eq!(blended.seed, 7);
+
+        let reason = |b: &Definition| a.blend(b, 0.5).unwrap_err();
+
+        let rules = Definition {
+            rules: String::from("F -> F[-F]F"),
+            ..b.clone()
+        };
+        assert_eq!(reason(&rules), "the rules are different");
+
+        // comments don't change the rules
+        let comment = Definition {
+            rules: format!("{} # branch", a.rules),
+            ..b.clone()
+        };
+        assert!(a.blend(&comment, 0.5).is_ok());
+
+        let axiom = Definition {
+            axiom: String::from("FF"),
+            ..b.clone()
+        };
+        assert_eq!(reason(&axiom), "the axioms are different");
+
+        let iterations = Definition {
+            iterations: 4,
+            ..b.clone()
+        };
+        assert_eq!(reason(&iterations), "the iterations are different");
+    }
+
+    #[test]
+    fn lily_files_save_and_open() {
+        let directory = std::env::temp_dir().join(format!("lily-project-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("design.lily");
+
+        let mut definition = Definition {
+            axiom: String::from("X"),
+            // comments and layout are kept, they're saved as text
+            rules: String::from("# the branching\nX -> F[+X]-X\n\nF -> FF"),
+            instructions: String::from("F = forward 2\n+ = turn 25 # left\n- = turn -25"),
+            iterations: 4,
+            ..Default::default()
+        };
+        definition.options.branch_color = system::parse_color("#336699").unwrap();
+        definition.options.branch_width = 5.0;
+
+        let project = Project::new(definition);
+        project.save(&path).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(ProjectFormat::detect(&text), ProjectFormat::Json);
+        assert_eq!(Project::load(&path).unwrap(), project);
+
+        // a malformed file is an error, as is one that isn't there
+        std::fs::write(&path, &text[..text.len() / 2]).unwrap();
+        assert!(matches!(Project::load(&path), Err(LilyError::Project(_))));
+        assert!(Project::load(directory.join("missing.lily")).is_err());
+    }
+}
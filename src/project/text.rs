@@ -0,0 +1,146 @@
+//! The plain text format, a definition written the way it's typed into the app.
+//!
+//! ```text
+//! axiom: A
+//! iterations: 7
+//!
+//! A -> F[-A]F[-A]+FA
+//! F -> FF
+//!
+//! F = forward 2
+//! + = turn 25
+//! - = turn -25
+//! ```
+//!
+//! `axiom`, `iterations` and `seed` are written as `name: value`, lines with
+//! `->` are rules and lines with `=` are instructions. Anything else is kept
+//! with the rules, so it's reported when they're parsed. Comments, see
+//! [`split_comment`](crate::system::split_comment), go with the rule or
+//! instruction after them.
+
+use super::Definition;
+use crate::system::strip_comment;
+
+/// The lines of a plain text file that the rules and instructions came from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceLines {
+    /// The file line of each line of [`Definition::rules`].
+    pub rules: Vec<usize>,
+    /// The file line of each line of [`Definition::instructions`].
+    pub instructions: Vec<usize>,
+}
+
+impl SourceLines {
+    /// The file line of the 1-based `line` of the rules.
+    pub fn rule(&self, line: usize) -> Option<usize> {
+        self.rules.get(line.checked_sub(1)?).copied()
+    }
+
+    /// The file line of the 1-based `line` of the instructions.
+    pub fn instruction(&self, line: usize) -> Option<usize> {
+        self.instructions.get(line.checked_sub(1)?).copied()
+    }
+}
+
+/// Splits `name: value`, for the names the format knows.
+fn header(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.split_once(':')?;
+
+    match name.trim() {
+        name @ ("axiom" | "iterations" | "seed") => Some((name, value.trim())),
+        _ => None,
+    }
+}
+
+impl Definition {
+    /// Parses the plain text format, see [`Definition::parse_text_with_lines`].
+    pub fn parse_text(text: &str) -> Self {
+        Self::parse_text_with_lines(text).0
+    }
+
+    /// Parses the plain text format, keeping track of where every rule and
+    /// instruction line came from.
+    ///
+    /// Nothing is rejected here, invalid rules and instructions are kept so
+    /// [`Rules::parse_checked`](crate::system::Rules::parse_checked) and
+    /// [`Instructions::parse_checked`](crate::system::Instructions::parse_checked)
+    /// can report them. Invalid numbers are ignored.
+    pub fn parse_text_with_lines(text: &str) -> (Self, SourceLines) {
+        let mut definition = Self::default();
+        let mut lines = SourceLines::default();
+
+        let mut rules = Vec::new();
+        let mut instructions = Vec::new();
+        let mut comments = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let code = strip_comment(line);
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if code.trim().is_empty() {
+                comments.push((i + 1, line));
+                continue;
+            }
+
+            if let Some((name, value)) = header(code) {
+                match name {
+                    "axiom" => definition.axiom = String::from(value),
+                    "iterations" => definition.iterations = value.parse().unwrap_or(0),
+                    _ => definition.seed = value.parse().unwrap_or(0),
+                }
+            } else if code.contains("->") || !code.contains('=') {
+                for (line, comment) in comments.drain(..) {
+                    rules.push(comment);
+                    lines.rules.push(line);
+                }
+
+                rules.push(line);
+                lines.rules.push(i + 1);
+            } else {
+                for (line, comment) in comments.drain(..) {
+                    instructions.push(comment);
+                    lines.instructions.push(line);
+                }
+
+                instructions.push(line);
+                lines.instructions.push(i + 1);
+            }
+        }
+
+        // comments after everything else are kept with the instructions
+        for (line, comment) in comments {
+            instructions.push(comment);
+            lines.instructions.push(line);
+        }
+
+        definition.rules = rules.join("\n");
+        definition.instructions = instructions.join("\n");
+
+        (definition, lines)
+    }
+
+    /// Writes the plain text format, the options aren't included.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("axiom: {}\niterations: {}\n", self.axiom, self.iterations);
+
+        if self.seed != 0 {
+            text += &format!("seed: {}\n", self.seed);
+        }
+
+        for section in [&self.rules, &self.instructions] {
+            let lines: Vec<_> = section.lines().filter(|l| !l.trim().is_empty()).collect();
+
+            if !lines.is_empty() {
+                text += "\n";
+                text += &lines.join("\n");
+                text += "\n";
+            }
+        }
+
+        text
+    }
+}
@@ -0,0 +1,235 @@
+//! One-call helpers for embedding lily.
+
+use ori::prelude::*;
+
+use crate::{
+    error::Result,
+    export::SvgBackend,
+    project::Definition,
+    system::{self, FitMode, SystemOptions},
+    LilyError,
+};
+
+/// The size of the image [`render_svg`] produces, along its longest side.
+pub const SVG_SIZE: f32 = 1024.0;
+
+/// The budgets [`render_within`] and [`render_svg_within`] stop at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The most symbols an expansion may grow to.
+    pub max_symbols: usize,
+    /// The most vertices the drawing may have, a line of an SVG has two.
+    pub max_vertices: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_symbols: system::DEFAULT_SYMBOL_LIMIT,
+            max_vertices: system::DEFAULT_VERTEX_LIMIT,
+        }
+    }
+}
+
+impl Limits {
+    fn check_vertices(&self, vertices: usize) -> Result<()> {
+        match vertices > self.max_vertices {
+            true => Err(LilyError::GenerationBudget {
+                limit: self.max_vertices,
+                vertices,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+fn definition(
+    axiom: &str,
+    rules: &str,
+    instructions: &str,
+    iterations: usize,
+    options: &SystemOptions,
+) -> Definition {
+    Definition {
+        axiom: String::from(axiom),
+        rules: String::from(rules),
+        instructions: String::from(instructions),
+        iterations,
+        options: options.clone(),
+        ..Default::default()
+    }
+}
+
+/// Generates the mesh of a system, like the app does.
+///
+/// `[` and `]` are bound to push and pop, and the mesh is fitted into the
+/// unit square, resting on its bottom edge. The [default](Limits::default)
+/// budgets apply, see [`render_within`].
+///
+/// ```
+/// use lily::system::SystemOptions;
+///
+/// let mesh = lily::render(
+///     "F",
+///     "F -> F[+F]F[-F]F",
+///     "F = forward 1\n+ = turn 25.7\n- = turn -25.7",
+///     3,
+///     &SystemOptions::default(),
+/// )?;
+///
+/// assert!(!mesh.indices.is_empty());
+/// # Ok::<(), lily::LilyError>(())
+/// ```
+pub fn render(
+    axiom: &str,
+    rules: &str,
+    instructions: &str,
+    iterations: usize,
+    options: &SystemOptions,
+) -> Result<Mesh> {
+    render_within(
+        axiom,
+        rules,
+        instructions,
+        iterations,
+        options,
+        &Limits::default(),
+    )
+}
+
+/// Like [`render`], expansions past `limits.max_symbols` stop with
+/// [`LilyError::ExpansionBudget`] and meshes past `limits.max_vertices` with
+/// [`LilyError::GenerationBudget`].
+pub fn render_within(
+    axiom: &str,
+    rules: &str,
+    instructions: &str,
+    iterations: usize,
+    options: &SystemOptions,
+    limits: &Limits,
+) -> Result<Mesh> {
+    let definition = definition(axiom, rules, instructions, iterations, options);
+    let stream = definition.try_interpret(limits.max_symbols)?;
+
+    let mut mesh = system::generate_mesh(options, &stream);
+    limits.check_vertices(mesh.vertices.len())?;
+
+    let rect = Rect::min_size(Point::ZERO, Size::all(1.0));
+    system::fit_mesh(&mut mesh, rect, options.growth, FitMode::default());
+
+    Ok(mesh)
+}
+
+/// Renders a system as an SVG document, see [`render`].
+///
+/// ```
+/// use lily::system::SystemOptions;
+///
+/// let svg = lily::render_svg(
+///     "F",
+///     "F -> F+F-F-F+F",
+///     "F = forward 1\n+ = turn 90\n- = turn -90",
+///     2,
+///     &SystemOptions::default(),
+/// )?;
+///
+/// assert!(svg.starts_with("<svg"));
+/// # Ok::<(), lily::LilyError>(())
+/// ```
+pub fn render_svg(
+    axiom: &str,
+    rules: &str,
+    instructions: &str,
+    iterations: usize,
+    options: &SystemOptions,
+) -> Result<String> {
+    render_svg_within(
+        axiom,
+        rules,
+        instructions,
+        iterations,
+        options,
+        &Limits::default(),
+    )
+}
+
+/// Like [`render_svg`], with the budgets of [`render_within`].
+pub fn render_svg_within(
+    axiom: &str,
+    rules: &str,
+    instructions: &str,
+    iterations: usize,
+    options: &SystemOptions,
+    limits: &Limits,
+) -> Result<String> {
+    let definition = definition(axiom, rules, instructions, iterations, options);
+    let stream = definition.try_interpret(limits.max_symbols)?;
+
+    let mut backend = SvgBackend::new();
+    system::interpret(options, &stream, &Default::default(), &mut backend);
+    limits.check_vertices(backend.segment_count() * 2)?;
+
+    Ok(backend.finish(SVG_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = "F -> F[+F]F[-F]F";
+    const INSTRUCTIONS: &str = "F = forward 1\n+ = turn 25.7\n- = turn -25.7";
+
+    #[test]
+    fn long_expansions_are_stopped() {
+        let limits = Limits {
+            max_symbols: 1000,
+            ..Limits::default()
+        };
+
+        let options = SystemOptions::default();
+        let result = render_within("F", RULES, INSTRUCTIONS, 4, &options, &limits);
+
+        match result {
+            Err(LilyError::ExpansionBudget {
+                limit, iteration, ..
+            }) => assert_eq!((limit, iteration), (1000, 4)),
+            other => panic!("expected an expansion budget error, got {:?}", other.err()),
+        }
+
+        let result = render_svg_within("F", RULES, INSTRUCTIONS, 4, &options, &limits);
+        assert!(matches!(result, Err(LilyError::ExpansionBudget { .. })));
+    }
+
+    #[test]
+    fn large_drawings_are_stopped() {
+        let limits = Limits {
+            max_vertices: 100,
+            ..Limits::default()
+        };
+
+        let options = SystemOptions::default();
+
+        // 125 segments, far fewer symbols than the default limit
+        let result = render_within("F", RULES, INSTRUCTIONS, 3, &options, &limits);
+        assert!(matches!(
+            result,
+            Err(LilyError::GenerationBudget { limit: 100, .. })
+        ));
+
+        let result = render_svg_within("F", RULES, INSTRUCTIONS, 3, &options, &limits);
+        assert!(matches!(
+            result,
+            Err(LilyError::GenerationBudget {
+                limit: 100,
+                vertices: 250
+            })
+        ));
+    }
+
+    #[test]
+    fn within_the_limits_renders() {
+        let options = SystemOptions::default();
+        let mesh = render("F", RULES, INSTRUCTIONS, 3, &options).unwrap();
+        assert!(!mesh.indices.is_empty());
+    }
+}
@@ -1,311 +1,3622 @@
 //! An implementation of an L-system.
 
+mod blend;
+mod custom;
+mod derivation;
+mod expr;
+mod fractint;
+mod incremental;
+mod math;
+mod mesh3d;
+mod module;
+mod rng;
+mod shading;
+mod share;
+mod stats;
+
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt, mem,
     ops::{Deref, DerefMut},
+    sync::{Arc, OnceLock},
 };
 
 use ori::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Rule {
-    pub rule: String,
-    pub replace: String,
-}
+use crate::{
+    error::{BracketError, Result, Truncated},
+    LilyError,
+};
+use module::Replacement;
 
-impl Rule {
-    pub fn new(rule: &str, replace: &str) -> Self {
-        Self {
-            rule: rule.to_string(),
-            replace: replace.to_string(),
+pub use blend::lerp_color;
+pub use custom::{CustomCallback, CustomId, CustomRegistry};
+pub use derivation::Derivation;
+pub use expr::{Expr, Operator};
+pub use fractint::{import_fractint, NamedSystem, FRACTINT_ITERATIONS};
+pub use incremental::{IncrementalMesh, TurtleProbe};
+pub use math::{Orientation, Scalar, Vec2, Vec3};
+pub use mesh3d::{generate_mesh3d, Mesh3d, Vertex3d};
+pub use module::{format_modules, modules, parse_modules, symbols, Module};
+pub use rng::Pcg32;
+pub use shading::{shade_density, DensityShading};
+pub use share::{decode_share, encode_share};
+pub use stats::{generation_stats, symbol_frequencies, GenerationStats};
+
+/// Whether every `[` in `text` is closed by a `]`.
+fn balanced(text: &str) -> bool {
+    let mut depth = 0usize;
+
+    for c in text.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return false,
+            ']' => depth -= 1,
+            _ => {}
         }
     }
 
-    pub fn parse(input: &str) -> Option<Self> {
-        let mut parts = input.split("->");
-
-        let rule = parts.next()?.trim();
-        let replace = parts.next()?.trim();
+    depth == 0
+}
 
-        Some(Self::new(rule, replace))
+/// Splits a line of rules or instructions into what's before its comment, and
+/// the comment itself.
+///
+/// Comments start at `#`, or take up the whole line when it starts with `//`.
+/// A `#` starting a color like `#6ac974`, a whole word of 6 or 8 hex digits,
+/// isn't a comment, and neither is an escaped `\#`, which is the symbol `#`
+/// once [`unescape`] is applied.
+pub fn split_comment(line: &str) -> (&str, &str) {
+    if line.trim_start().starts_with("//") {
+        return ("", line.trim_start());
     }
-}
 
-impl ToString for Rule {
-    fn to_string(&self) -> String {
-        format!("{} -> {}", self.rule, self.replace)
+    let is_color = |i: usize| {
+        let word = line[i + 1..].split_whitespace().next().unwrap_or_default();
+        let starts_word = line[..i].ends_with(char::is_whitespace);
+
+        starts_word
+            && line[i + 1..].starts_with(word)
+            && matches!(word.len(), 6 | 8)
+            && word.chars().all(|c| c.is_ascii_hexdigit())
+    };
+
+    let is_escaped = |i: usize| line[..i].ends_with('\\');
+
+    match (line.match_indices('#')).find(|&(i, _)| !is_color(i) && !is_escaped(i)) {
+        Some((i, _)) => line.split_at(i),
+        None => (line, ""),
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct Rules {
-    rules: Vec<Rule>,
+/// A line of rules or instructions without its comment.
+pub fn strip_comment(line: &str) -> &str {
+    split_comment(line).0
 }
 
-impl Rules {
-    pub fn new() -> Self {
-        Self { rules: vec![] }
+/// Turns every escaped `\#` of a line without its comment into `#`.
+pub fn unescape(code: &str) -> Cow<'_, str> {
+    match code.contains("\\#") {
+        true => Cow::Owned(code.replace("\\#", "#")),
+        false => Cow::Borrowed(code),
     }
+}
 
-    pub fn parse(input: &str) -> Self {
-        let mut rules = Self::new();
+/// The rules bucketed by their first symbol, so only the ones that can match
+/// are tried at each position.
+///
+/// Built the first time the rules are applied and kept until they change.
+#[derive(Clone, Debug)]
+struct RuleIndex {
+    /// The rules starting with each ASCII symbol, indexed by the symbol.
+    ascii: Vec<Vec<usize>>,
+    /// The rules starting with any other symbol.
+    other: HashMap<char, Vec<usize>>,
+    /// The number of symbols each rule replaces.
+    lengths: Vec<usize>,
+    /// Whether each rule has alternatives to pick between.
+    stochastic: Vec<bool>,
+}
+
+impl RuleIndex {
+    fn new(rules: &[Rule]) -> Self {
+        let mut ascii = vec![Vec::new(); 128];
+        let mut other = HashMap::<char, Vec<usize>>::new();
 
-        for line in input.lines() {
-            if let Some(rule) = Rule::parse(line) {
-                rules.push(&rule.rule, &rule.replace);
+        for (i, rule) in rules.iter().enumerate() {
+            match rule.rule.chars().next() {
+                Some(symbol) if symbol.is_ascii() => ascii[symbol as usize].push(i),
+                Some(symbol) => other.entry(symbol).or_default().push(i),
+                None => {}
             }
         }
 
-        rules
+        let lengths = rules.iter().map(|rule| rule.rule.chars().count()).collect();
+        let stochastic = (rules.iter())
+            .map(|rule| {
+                rules
+                    .iter()
+                    .filter(|other| other.same_predecessor(rule))
+                    .nth(1)
+                    .is_some()
+            })
+            .collect();
+
+        Self {
+            ascii,
+            other,
+            lengths,
+            stochastic,
+        }
     }
 
-    pub fn push(&mut self, rule: &str, replace: &str) {
-        self.rules.push(Rule::new(rule, replace));
+    fn candidates(&self, symbol: char) -> &[usize] {
+        match symbol.is_ascii() {
+            true => &self.ascii[symbol as usize],
+            false => self.other.get(&symbol).map_or(&[], Vec::as_slice),
+        }
     }
 
-    pub fn apply(&self, input: &str) -> String {
-        let mut output = String::new();
-        // the number of characters matched by the last rule
-        // this is used to skip over the matched characters
-        // omitting them from the output
-        let mut skip = 0;
+    /// The index of the rule replacing the most symbols at a position starting
+    /// with `symbol`, the first of them on a tie, and what `matches` gave it.
+    fn find<T>(
+        &self,
+        rules: &[Rule],
+        symbol: char,
+        matches: impl Fn(&Rule) -> Option<T>,
+    ) -> Option<(usize, T)> {
+        let len = |index: usize| self.lengths[index];
 
-        let mut i = 0;
-        while i < input.len() {
-            let Some(c) = input[i..].chars().next() else {
-                break;
-            };
+        self.candidates(symbol)
+            .iter()
+            .fold(None, |longest, &index| match longest {
+                Some((other, _)) if len(other) >= len(index) => longest,
+                _ => (matches(&rules[index]).map(|matched| (index, matched))).or(longest),
+            })
+    }
+}
 
-            if skip > 0 {
-                skip -= c.len_utf8();
-                i += c.len_utf8();
-                continue;
-            }
+/// `text` followed by the comment of `line`, when it has one.
+fn with_comment(mut text: String, line: &str) -> String {
+    let (_, comment) = split_comment(line);
 
-            let mut matched = false;
+    if !comment.is_empty() {
+        text.push(' ');
+        text.push_str(comment);
+    }
 
-            for rule in &self.rules {
-                if input[i..].starts_with(&rule.rule) {
-                    output.push_str(&rule.replace);
-                    skip = rule.rule.len() - c.len_utf8();
-                    matched = true;
-                    break;
-                }
-            }
+    text
+}
 
-            if !matched {
-                output.push(c);
-            }
+/// Leaves out what's between `open` and `close`, the arguments of modules
+/// when walking over an expansion.
+fn skip_arguments(
+    chars: impl Iterator<Item = char>,
+    open: char,
+    close: char,
+) -> impl Iterator<Item = char> {
+    let mut depth = 0usize;
 
-            i += c.len_utf8();
+    chars.filter(move |&c| {
+        match c {
+            c if c == open => depth += 1,
+            c if c == close && depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            _ => return true,
         }
 
-        output
-    }
+        false
+    })
 }
 
-impl Deref for Rules {
-    type Target = Vec<Rule>;
+/// Whether `before` ends with `context`, looking past branches.
+///
+/// Walking back from a symbol, a `]` ends a sibling branch, which is skipped
+/// whole, and a `[` is where the symbol's own branch started, so the symbol
+/// before it is its parent.
+///
+/// `before` is the symbols before it, nearest first.
+fn left_context_matches(context: &str, mut symbols: impl Iterator<Item = char>) -> bool {
+    'context: for expected in context.chars().rev() {
+        let mut depth = 0usize;
 
-    fn deref(&self) -> &Self::Target {
-        &self.rules
-    }
-}
+        for c in symbols.by_ref() {
+            match c {
+                ']' => depth += 1,
+                '[' if depth > 0 => depth -= 1,
+                '[' => {}
+                _ if depth > 0 => {}
+                c if c == expected => continue 'context,
+                _ => return false,
+            }
+        }
 
-impl DerefMut for Rules {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.rules
+        return false;
     }
-}
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Instruction {
-    Forward(f32),
-    Turn(f32),
-    Scale(f32),
-    Push,
-    Pop,
+    true
 }
 
-impl Instruction {
-    fn parse<'a>(mut parts: impl Iterator<Item = &'a str>) -> Option<Self> {
-        match parts.next()? {
-            "forward" => {
-                let length = parts.next()?.parse().ok()?;
-                Some(Self::Forward(length))
-            }
-            "turn" => {
-                let angle = parts.next()?.parse().ok()?;
-                Some(Self::Turn(angle))
+/// Whether `after` starts with `context`, skipping the branches in between.
+///
+/// A `]` ends the branch the symbol is on, there's nothing after it. The
+/// context may go into branches, `[C]D` matches a branch starting with `C`
+/// followed by `D`, where the rest of the branch is skipped at the `]`.
+fn right_context_matches(context: &str, mut symbols: impl Iterator<Item = char>) -> bool {
+    'context: for expected in context.chars() {
+        match expected {
+            // the branch has to come right away, it isn't skipped
+            '[' => match symbols.next() {
+                Some('[') => continue 'context,
+                _ => return false,
+            },
+            // skip what's left of the branch the context went into
+            ']' => {
+                let mut depth = 0usize;
+
+                for c in symbols.by_ref() {
+                    match c {
+                        '[' => depth += 1,
+                        ']' if depth > 0 => depth -= 1,
+                        ']' => continue 'context,
+                        _ => {}
+                    }
+                }
+
+                return false;
             }
-            "scale" => {
-                let scale = parts.next()?.parse().ok()?;
-                Some(Self::Scale(scale))
+            _ => {}
+        }
+
+        let mut depth = 0usize;
+
+        for c in symbols.by_ref() {
+            match c {
+                '[' => depth += 1,
+                ']' if depth > 0 => depth -= 1,
+                ']' => return false,
+                _ if depth > 0 => {}
+                c if c == expected => continue 'context,
+                _ => return false,
             }
-            "push" => Some(Self::Push),
-            "pop" => Some(Self::Pop),
-            _ => None,
         }
+
+        return false;
     }
+
+    true
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct Instructions {
-    instructions: HashMap<char, Instruction>,
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub rule: String,
+    pub replace: String,
+    /// The chance of picking this rule over others replacing the same
+    /// symbols, written `A (0.4) -> …` or `A -> … : 0.4`, see
+    /// [`Rules::probability`].
+    pub probability: Option<f32>,
+    /// The symbols that have to come before [`Rule::rule`], written
+    /// `A < B -> …`.
+    pub left_context: Option<String>,
+    /// The symbols that have to come after [`Rule::rule`], written
+    /// `B > C -> …`.
+    pub right_context: Option<String>,
+    /// The names of the arguments of a parametric rule, written `A(x, y) -> …`.
+    ///
+    /// The rule only replaces modules with as many arguments, and groups in
+    /// the replacement like `F(x*2)` are evaluated with them.
+    pub parameters: Vec<String>,
 }
 
-impl Instructions {
-    fn parse_instruction(input: &str) -> Option<(char, Instruction)> {
-        let mut parts = input.split_whitespace();
+impl Rule {
+    pub fn new(rule: &str, replace: &str) -> Self {
+        Self {
+            rule: rule.to_string(),
+            replace: replace.to_string(),
+            probability: None,
+            left_context: None,
+            right_context: None,
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Matches the rule at the start of `input[index..]`, returning the length
+    /// in bytes of what it replaces and the arguments it has.
+    ///
+    /// `index` is on a char boundary and so is the end of the match, so
+    /// symbols like `λ` are never split.
+    fn match_at(&self, input: &str, index: usize) -> Option<(usize, Vec<f32>)> {
+        let (before, after) = input.split_at(index);
+        let after = after.strip_prefix(self.rule.as_str())?;
 
-        let key = parts.next()?.chars().next()?;
+        let (arguments, len) = module::arguments(after).unwrap_or_default();
 
-        if parts.next()? != "=" {
+        if arguments.len() != self.parameters.len() {
             return None;
         }
 
-        let instruction = Instruction::parse(parts)?;
+        let after = &after[len..];
 
-        Some((key, instruction))
+        let left = (self.left_context.as_deref()).is_none_or(|context| {
+            left_context_matches(context, skip_arguments(before.chars().rev(), ')', '('))
+        });
+        let right = (self.right_context.as_deref()).is_none_or(|context| {
+            right_context_matches(context, skip_arguments(after.chars(), '(', ')'))
+        });
+
+        (left && right).then_some((self.rule.len() + len, arguments))
     }
 
-    pub fn parse(input: &str) -> Self {
-        let mut instructions = Self::new();
+    /// Like [`Rule::match_at`] on modules, returning the number of modules
+    /// the rule replaces.
+    ///
+    /// Only the last of them may have arguments, the ones the rule takes.
+    fn match_modules<'a>(&self, input: &'a [Module], index: usize) -> Option<(usize, &'a [f32])> {
+        let count = self.rule.chars().count();
+        let matched = input.get(index..index + count)?;
+
+        let (last, rest) = matched.split_last()?;
+        let symbols = matched.iter().map(|module| module.symbol);
+
+        if !symbols.eq(self.rule.chars())
+            || rest.iter().any(|module| !module.arguments.is_empty())
+            || last.arguments.len() != self.parameters.len()
+        {
+            return None;
+        }
+
+        let symbols = |modules: &'a [Module]| modules.iter().map(|module| module.symbol);
+
+        let left = (self.left_context.as_deref())
+            .is_none_or(|context| left_context_matches(context, symbols(&input[..index]).rev()));
+        let right = (self.right_context.as_deref())
+            .is_none_or(|context| right_context_matches(context, symbols(&input[index + count..])));
+
+        (left && right).then_some((count, last.arguments.as_slice()))
+    }
+
+    /// Whether the rule replaces the symbols at the start of `input[index..]`.
+    pub fn matches(&self, input: &str, index: usize) -> bool {
+        self.match_at(input, index).is_some()
+    }
+
+    /// The replacement for a match with `arguments`, see [`Rule::parameters`].
+    fn replacement(&self, arguments: &[f32]) -> Cow<'_, str> {
+        if !self.replace.contains('(') {
+            return Cow::Borrowed(&self.replace);
+        }
+
+        let parameter = |name: &str| {
+            let index = self
+                .parameters
+                .iter()
+                .position(|parameter| parameter == name)?;
+            arguments.get(index).copied()
+        };
+
+        Cow::Owned(module::substitute(&self.replace, &parameter))
+    }
+
+    /// Whether the rule depends on its neighbours.
+    pub fn is_context_sensitive(&self) -> bool {
+        self.left_context.is_some() || self.right_context.is_some()
+    }
+
+    /// Whether `other` replaces the same symbols in the same context, making
+    /// them alternatives.
+    pub fn same_predecessor(&self, other: &Self) -> bool {
+        self.rule == other.rule
+            && self.left_context == other.left_context
+            && self.right_context == other.right_context
+            && self.parameters.len() == other.parameters.len()
+    }
+
+    /// Splits the parameters off `A(x, y)`, like [`Rule::parse_probability`]
+    /// parentheses that aren't a list of names are part of the symbols.
+    fn parse_parameters(rule: &str) -> Result<(&str, Vec<String>), String> {
+        let Some((symbols, names)) = (rule.strip_suffix(')')).and_then(|rule| rule.split_once('('))
+        else {
+            return Ok((rule, Vec::new()));
+        };
+
+        let names: Vec<_> = names.split(',').map(str::trim).collect();
+        let is_name = |name: &&str| {
+            let mut chars = name.chars();
+            (chars.next()).is_some_and(|c| c.is_alphabetic() || c == '_')
+                && chars.all(|c| c.is_alphanumeric() || c == '_')
+        };
+
+        if symbols.is_empty() || !names.iter().all(is_name) {
+            return Ok((rule, Vec::new()));
+        }
+
+        if symbols.chars().count() > 1 {
+            return Err(String::from("only a single symbol can have parameters"));
+        }
+
+        Ok((symbols, names.into_iter().map(String::from).collect()))
+    }
 
-        for line in input.lines() {
-            if let Some((key, instruction)) = Self::parse_instruction(line) {
-                instructions.insert(key, instruction);
+    /// Splits `left < rule > right` into its parts.
+    ///
+    /// Only the right context can go into branches, the left context always
+    /// walks back out of them, so brackets there could never match.
+    fn parse_context(rule: &str) -> Result<(Option<&str>, &str, Option<&str>), String> {
+        fn context<'a>(context: &'a str, marker: &str) -> Result<&'a str, String> {
+            match context.trim() {
+                "" => Err(format!("expected a context around `{}`", marker)),
+                context => Ok(context),
             }
         }
 
-        instructions
+        let (left, rest) = match rule.split_once('<') {
+            Some((left, rest)) => (Some(context(left, "<")?), rest),
+            None => (None, rule),
+        };
+
+        let (rule, right) = match rest.split_once('>') {
+            Some((rule, right)) => (rule.trim(), Some(context(right, ">")?)),
+            None => (rest.trim(), None),
+        };
+
+        if [left, right]
+            .iter()
+            .flatten()
+            .any(|context| context.contains('('))
+        {
+            return Err(String::from("contexts can't have parameters"));
+        }
+
+        if left.is_some_and(|left| left.contains(['[', ']'])) {
+            return Err(String::from(
+                "the context before `<` can't contain brackets",
+            ));
+        }
+
+        if right.is_some_and(|right| !balanced(right)) {
+            return Err(String::from(
+                "the brackets in the context after `>` don't match",
+            ));
+        }
+
+        Ok((left, rule, right))
     }
 
-    pub fn new() -> Self {
-        Self {
-            instructions: HashMap::new(),
+    /// Splits the probability off `rule (0.4)`, rules without one are left
+    /// alone.
+    ///
+    /// Parentheses that are part of the symbols, like `(A)`, aren't a
+    /// probability, so anything but a number is only rejected after a space.
+    fn parse_probability(rule: &str) -> Result<(&str, Option<f32>), String> {
+        let Some((rest, probability)) = rule
+            .strip_suffix(')')
+            .and_then(|inner| inner.rsplit_once('('))
+        else {
+            return Ok((rule, None));
+        };
+
+        match probability.trim().parse::<f32>() {
+            Ok(probability) if probability >= 0.0 && probability.is_finite() => {
+                Ok((rest.trim(), Some(probability)))
+            }
+            Ok(_) => Err(format!("invalid probability `{}`", probability.trim())),
+            Err(_) if rest.ends_with(char::is_whitespace) => {
+                Err(format!("invalid probability `{}`", probability.trim()))
+            }
+            Err(_) => Ok((rule, None)),
         }
     }
 
-    pub fn insert(&mut self, c: char, instruction: Instruction) {
-        self.instructions.insert(c, instruction);
+    /// Splits the weight off `replace : 0.4`, like [`Rule::parse_probability`]
+    /// a `:` that's part of the symbols is left alone.
+    fn parse_weight(replace: &str) -> Result<(&str, Option<f32>), String> {
+        let Some((rest, weight)) = replace.rsplit_once(':') else {
+            return Ok((replace, None));
+        };
+
+        match weight.trim().parse::<f32>() {
+            Ok(weight) if weight >= 0.0 && weight.is_finite() => Ok((rest.trim(), Some(weight))),
+            Ok(_) => Err(format!("invalid weight `{}`", weight.trim())),
+            Err(_) if rest.ends_with(char::is_whitespace) => {
+                Err(format!("invalid weight `{}`", weight.trim()))
+            }
+            Err(_) => Ok((replace, None)),
+        }
     }
 
-    pub fn apply(&self, input: &str) -> Vec<Instruction> {
-        let mut output = Vec::new();
+    pub fn parse(input: &str) -> Option<Self> {
+        Self::parse_reason(input).ok()
+    }
+
+    /// Like [`Rule::parse`], explaining why `input` isn't a rule.
+    fn parse_reason(input: &str) -> Result<Self, String> {
+        let code = unescape(strip_comment(input));
+        let mut parts = code.split("->");
 
-        for c in input.chars() {
-            if let Some(instruction) = self.instructions.get(&c) {
-                output.push(instruction.clone());
+        let rule = parts.next().unwrap_or_default().trim();
+        let replace = parts.next().ok_or("expected `->`")?.trim();
+
+        let (rule, probability) = Self::parse_probability(rule)?;
+        let (replace, weight) = Self::parse_weight(replace)?;
+        let (left_context, rule, right_context) = Self::parse_context(rule)?;
+        let (rule, parameters) = Self::parse_parameters(rule)?;
+
+        let probability = match (probability, weight) {
+            (Some(_), Some(_)) => {
+                return Err(String::from(
+                    "a rule can't have both a probability and a weight",
+                ))
             }
+            (probability, weight) => probability.or(weight),
+        };
+
+        // an empty rule would match everywhere without consuming anything
+        if rule.is_empty() {
+            return Err(String::from("nothing to replace before `->`"));
         }
 
-        output
+        Ok(Self {
+            probability,
+            left_context: left_context.map(String::from),
+            right_context: right_context.map(String::from),
+            parameters,
+            ..Self::new(rule, replace)
+        })
     }
 }
 
-pub struct SystemOptions {
-    pub branch_color: Color,
-    pub branch_width: f32,
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref context) = self.left_context {
+            write!(f, "{} < ", context)?;
+        }
+
+        f.write_str(&self.rule)?;
+
+        if !self.parameters.is_empty() {
+            write!(f, "({})", self.parameters.join(", "))?;
+        }
+
+        if let Some(ref context) = self.right_context {
+            write!(f, " > {}", context)?;
+        }
+
+        if let Some(probability) = self.probability {
+            write!(f, " ({})", probability)?;
+        }
+
+        write!(f, " -> {}", self.replace)
+    }
 }
 
-#[derive(Clone)]
-struct Branch {
-    indecies: [u32; 2],
-    position: Point,
-    rotation: Matrix,
-    scale: f32,
+/// The number of symbols an expansion may grow to by default, see
+/// [`Expansions::with_limit`] and [`Rules::with_max_length`].
+pub const DEFAULT_SYMBOL_LIMIT: usize = 10_000_000;
+
+/// The number of vertices a generated mesh may have by default, see
+/// [`LilyError::GenerationBudget`].
+pub const DEFAULT_VERTEX_LIMIT: usize = 20_000_000;
+
+#[derive(Clone, Debug)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    max_length: usize,
+    /// Reset whenever the rules are changed, see [`Rules::index`].
+    index: OnceLock<RuleIndex>,
 }
 
-fn apply_instruction(
-    mesh: &mut Mesh,
-    stack: &mut Vec<Branch>,
-    options: &SystemOptions,
-    instruction: Instruction,
-) {
-    let depth = stack.len();
+impl PartialEq for Rules {
+    fn eq(&self, other: &Self) -> bool {
+        self.rules == other.rules && self.max_length == other.max_length
+    }
+}
 
-    let Some(branch) = stack.last_mut() else {
-        return;
-    };
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    match instruction {
-        Instruction::Forward(mut length) => {
-            // apply the scale
-            length *= branch.scale;
+impl Rules {
+    pub fn new() -> Self {
+        Self {
+            rules: vec![],
+            max_length: DEFAULT_SYMBOL_LIMIT,
+            index: OnceLock::new(),
+        }
+    }
 
-            let depth_scale = f32::powi(0.9, depth as i32);
-            let width = options.branch_width * depth_scale;
+    /// Makes [`Rules::try_apply`] stop once the expansion grows past
+    /// `max_length` characters.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
 
-            let forward = branch.rotation * Vector::NEG_Y * length;
-            let left = branch.rotation * Vector::NEG_X * width / 2.0;
+    /// The length [`Rules::try_apply`] stops at, [`DEFAULT_SYMBOL_LIMIT`]
+    /// unless it's set with [`Rules::with_max_length`].
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
 
-            let index = mesh.vertices.len() as u32;
-            mesh.vertices.push(Vertex {
-                position: branch.position + left,
-                tex_coords: Point::ZERO,
-                color: options.branch_color,
-            });
-            mesh.vertices.push(Vertex {
-                position: branch.position - left,
-                tex_coords: Point::ZERO,
-                color: options.branch_color,
-            });
+    pub fn parse(input: &str) -> Self {
+        Self::parse_checked(input).0
+    }
 
-            mesh.indices.push(branch.indecies[0]);
-            mesh.indices.push(branch.indecies[1]);
-            mesh.indices.push(index);
+    /// Parses every rule it can, returning an error for each line it can't.
+    ///
+    /// Blank lines and comments are skipped, see [`split_comment`].
+    pub fn parse_checked(input: &str) -> (Self, Vec<LilyError>) {
+        let mut rules = Self::new();
+        let mut errors = Vec::new();
 
-            mesh.indices.push(branch.indecies[1]);
-            mesh.indices.push(index);
-            mesh.indices.push(index + 1);
+        for (i, line) in input.lines().enumerate() {
+            if strip_comment(line).trim().is_empty() {
+                continue;
+            }
 
-            branch.indecies = [index, index + 1];
-            branch.position += forward;
+            match Rule::parse_reason(line) {
+                Ok(rule) => rules.rules.push(rule),
+                Err(reason) => errors.push(LilyError::Rule {
+                    line: i + 1,
+                    text: String::from(line.trim()),
+                    reason,
+                }),
+            }
         }
-        Instruction::Turn(angle) => {
-            let rotation = Matrix::from_angle(angle.to_radians());
-            branch.rotation = branch.rotation * rotation;
+
+        (rules, errors)
+    }
+
+    pub fn push(&mut self, rule: &str, replace: &str) {
+        self.deref_mut().push(Rule::new(rule, replace));
+    }
+
+    /// The rules replacing the same symbols as `rule` in the same context,
+    /// including `rule` itself, one is picked at random each time they match.
+    pub fn alternatives<'a>(&'a self, rule: &'a Rule) -> impl Iterator<Item = &'a Rule> + 'a {
+        (self.rules.iter()).filter(move |other| other.same_predecessor(rule))
+    }
+
+    /// The sum of the probabilities of the alternatives to `rule`, and the
+    /// probability of the ones without one.
+    ///
+    /// Rules without a probability share what the others leave of 1 equally.
+    fn probabilities(&self, rule: &Rule) -> (f32, f32) {
+        let (mut given, mut missing) = (0.0, 0);
+
+        for rule in self.alternatives(rule) {
+            match rule.probability {
+                Some(probability) => given += probability,
+                None => missing += 1,
+            }
         }
-        Instruction::Scale(scale) => {
-            branch.scale *= scale;
+
+        let share = match missing {
+            0 => 0.0,
+            missing => f32::max(1.0 - given, 0.0) / missing as f32,
+        };
+
+        (given + share * missing as f32, share)
+    }
+
+    /// The sum of the probabilities of the alternatives to `rule`, they're
+    /// scaled to add up to 1 when they don't.
+    pub fn total_probability(&self, rule: &Rule) -> f32 {
+        self.probabilities(rule).0
+    }
+
+    /// The chance of `rule` being picked when it matches, after scaling the
+    /// probabilities of its alternatives to add up to 1.
+    pub fn probability(&self, rule: &Rule) -> f32 {
+        let (total, share) = self.probabilities(rule);
+
+        match total > 0.0 {
+            true => rule.probability.unwrap_or(share) / total,
+            false => 0.0,
         }
-        Instruction::Push => {
-            let branch = branch.clone();
-            stack.push(branch);
+    }
+
+    /// Picks one of the alternatives to `first`, weighted by their
+    /// probabilities.
+    ///
+    /// Deterministic rules don't draw from `rng`, so adding a stochastic rule
+    /// doesn't change how other symbols expand.
+    fn pick<'a>(&'a self, first: &'a Rule, rng: &mut Pcg32) -> &'a Rule {
+        if self.alternatives(first).nth(1).is_none() {
+            return first;
         }
-        Instruction::Pop => {
-            stack.pop();
+
+        let (total, share) = self.probabilities(first);
+        let mut remaining = rng.next_f32() * total;
+
+        for rule in self.alternatives(first) {
+            remaining -= rule.probability.unwrap_or(share);
+
+            if remaining < 0.0 {
+                return rule;
+            }
         }
+
+        // rounding, or every probability is 0
+        first
     }
-}
 
-pub fn generate_mesh(options: &SystemOptions, instructions: &[Instruction]) -> Mesh {
-    let mut mesh = Mesh::new();
-    let mut stack = Vec::new();
-
-    let x = options.branch_width / 2.0;
-    mesh.vertices.push(Vertex {
-        position: Point::new(-x, 0.0),
-        tex_coords: Point::ZERO,
-        color: options.branch_color,
-    });
-    mesh.vertices.push(Vertex {
-        position: Point::new(x, 0.0),
-        tex_coords: Point::ZERO,
-        color: options.branch_color,
-    });
-
-    stack.push(Branch {
-        indecies: [0, 1],
-        position: Point::ZERO,
-        rotation: Matrix::IDENTITY,
-        scale: 1.0,
-    });
+    /// The rules bucketed by their first symbol, see [`RuleIndex`].
+    fn index(&self) -> &RuleIndex {
+        self.index.get_or_init(|| RuleIndex::new(&self.rules))
+    }
 
-    for instruction in instructions {
-        apply_instruction(&mut mesh, &mut stack, options, instruction.clone());
+    /// Whether any rule takes parameters.
+    pub fn is_parametric(&self) -> bool {
+        self.rules.iter().any(|rule| !rule.parameters.is_empty())
+    }
+
+    /// Like [`Rules::apply`] on modules, which saves parametric systems from
+    /// writing their arguments out and reading them back every iteration.
+    ///
+    /// Both give the same modules for the same `rng`.
+    pub fn apply_modules(&self, input: &[Module], rng: &mut Pcg32) -> Vec<Module> {
+        let replacements: Vec<_> = (self.rules.iter())
+            .map(|rule| Replacement::new(&rule.replace))
+            .collect();
+
+        let rule_index = self.index();
+        let mut output = Vec::with_capacity(input.len());
+
+        let mut i = 0;
+        while i < input.len() {
+            let matched = rule_index.find(&self.rules, input[i].symbol, |rule| {
+                rule.match_modules(input, i)
+            });
+
+            let Some((mut index, (count, arguments))) = matched else {
+                output.push(input[i].clone());
+                i += 1;
+                continue;
+            };
+
+            if rule_index.stochastic[index] {
+                let rule = self.pick(&self.rules[index], rng);
+                index = (self.rules.iter())
+                    .position(|other| std::ptr::eq(other, rule))
+                    .expect("picked rules are in the rules");
+            }
+
+            let rule = &self.rules[index];
+
+            let parameter = |name: &str| {
+                let index = rule.parameters.iter().position(|other| other == name)?;
+                arguments.get(index).copied()
+            };
+
+            replacements[index].write(&parameter, &mut output);
+            i += count;
+        }
+
+        output
     }
 
-    mesh
+    /// Applies the rules once, picking between alternatives with `rng`.
+    ///
+    /// Where several rules match, the one replacing the most symbols wins,
+    /// and of those the first, so `FA -> X` beats `F -> FF` in any order.
+    ///
+    /// This doesn't stop at [`Rules::max_length`], see [`Rules::try_apply`].
+    pub fn apply(&self, input: &str, rng: &mut Pcg32) -> String {
+        (self.apply_within(input, usize::MAX, rng)).expect("no expansion is longer than usize::MAX")
+    }
+
+    /// Like [`Rules::apply`], stopping as soon as the expansion grows past
+    /// [`Rules::max_length`] characters instead of after building it.
+    pub fn try_apply(&self, input: &str, rng: &mut Pcg32) -> Result<String, Truncated> {
+        self.apply_within(input, self.max_length, rng)
+    }
+
+    /// Applies the rules once, stopping once the expansion grows past `limit`
+    /// characters.
+    fn apply_within(
+        &self,
+        input: &str,
+        limit: usize,
+        rng: &mut Pcg32,
+    ) -> Result<String, Truncated> {
+        let rule_index = self.index();
+        let mut output = String::new();
+        let mut length = 0;
+
+        let mut i = 0;
+        while let Some(symbol) = input[i..].chars().next() {
+            if length > limit {
+                return Err(Truncated { length });
+            }
+
+            let matched = rule_index.find(&self.rules, symbol, |rule| rule.match_at(input, i));
+
+            match matched {
+                Some((index, (len, arguments))) => {
+                    let rule = match rule_index.stochastic[index] {
+                        true => self.pick(&self.rules[index], rng),
+                        false => &self.rules[index],
+                    };
+
+                    let replacement = rule.replacement(&arguments);
+                    length += replacement.chars().count();
+                    output.push_str(&replacement);
+                    i += len;
+                }
+                // modules without a rule are copied with their arguments
+                None => {
+                    let len = module::module_len(&input[i..]);
+                    length += input[i..i + len].chars().count();
+                    output.push_str(&input[i..i + len]);
+                    i += len;
+                }
+            }
+        }
+
+        match length > limit {
+            true => Err(Truncated { length }),
+            false => Ok(output),
+        }
+    }
+
+    /// Lazily applies the rules to `axiom` over and over, starting with the
+    /// axiom itself.
+    pub fn expansions(&self, axiom: &str) -> Expansions<'_> {
+        Expansions {
+            rules: self,
+            tree: String::from(axiom),
+            iteration: 0,
+            limit: usize::MAX,
+            rng: Pcg32::new(0),
+            started: false,
+            stopped: false,
+        }
+    }
+
+    /// Estimates the length of the axiom after each of `0..=iterations`.
+    ///
+    /// Only counts symbols instead of expanding, so it's cheap for any number
+    /// of iterations. Rules replacing more than one symbol are ignored, which
+    /// makes this an estimate for systems using them, so are context-sensitive
+    /// rules, and stochastic rules count as the average of their alternatives.
+    pub fn estimate_lengths(&self, axiom: &str, iterations: usize) -> Vec<f64> {
+        let mut counts = HashMap::<char, f64>::new();
+        for c in module::symbols(axiom) {
+            *counts.entry(c).or_default() += 1.0;
+        }
+
+        let mut lengths = vec![counts.values().sum()];
+
+        for _ in 0..iterations {
+            let mut next = HashMap::<char, f64>::new();
+
+            for (&c, &count) in counts.iter() {
+                let mut buf = [0; 4];
+                let symbol = &*c.encode_utf8(&mut buf);
+
+                let first = (self.rules.iter())
+                    .find(|rule| rule.rule == symbol && !rule.is_context_sensitive());
+
+                let Some(first) = first else {
+                    *next.entry(c).or_default() += count;
+                    continue;
+                };
+
+                for rule in self.alternatives(first) {
+                    let count = count * self.probability(rule) as f64;
+
+                    for c in module::symbols(&rule.replace) {
+                        *next.entry(c).or_default() += count;
+                    }
+                }
+            }
+
+            counts = next;
+            lengths.push(counts.values().sum());
+        }
+
+        lengths
+    }
+}
+
+/// The successive expansions of an axiom, see [`Rules::expansions`].
+///
+/// Only the latest expansion is kept, each one is yielded as its own string.
+#[derive(Clone, Debug)]
+pub struct Expansions<'a> {
+    rules: &'a Rules,
+    tree: String,
+    iteration: usize,
+    limit: usize,
+    rng: Pcg32,
+    started: bool,
+    stopped: bool,
+}
+
+impl Expansions<'_> {
+    /// Stops with [`LilyError::ExpansionBudget`] once an expansion grows
+    /// past `limit` symbols, while it's built rather than after.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Picks between stochastic rules with a generator seeded with `seed`,
+    /// the seed is 0 otherwise.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Pcg32::new(seed);
+        self
+    }
+}
+
+impl Iterator for Expansions<'_> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped {
+            return None;
+        }
+
+        if self.started {
+            let tree = (self.rules).apply_within(&self.tree, self.limit, &mut self.rng);
+            self.iteration += 1;
+
+            let Ok(tree) = tree else {
+                self.stopped = true;
+
+                return Some(Err(LilyError::ExpansionBudget {
+                    limit: self.limit,
+                    iteration: self.iteration,
+                    partial: mem::take(&mut self.tree),
+                }));
+            };
+
+            self.tree = tree;
+        }
+
+        self.started = true;
+        Some(Ok(self.tree.clone()))
+    }
+}
+
+impl Deref for Rules {
+    type Target = Vec<Rule>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rules
+    }
+}
+
+impl DerefMut for Rules {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // the rules may change through the reference
+        self.index = OnceLock::new();
+        &mut self.rules
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Forward(f32),
+    /// Moves forward like [`Instruction::Forward`] without drawing.
+    Move(f32),
+    Turn(f32),
+    /// Turns to face `angle` degrees from the starting heading, written
+    /// `heading 90`.
+    ///
+    /// This overwrites every turn before it, including pitches and rolls in
+    /// [`Dimensions::Three`].
+    SetAngle(f32),
+    /// Turns back the way the turtle came, like `turn 180`, bound to `|` by
+    /// default.
+    TurnAround,
+    /// Turns in the plane of the screen like [`Instruction::Turn`], named
+    /// for the other turns in space.
+    Yaw(f32),
+    /// Tips the heading out of the plane, only in [`Dimensions::Three`].
+    Pitch(f32),
+    /// Turns about the heading, only in [`Dimensions::Three`].
+    Roll(f32),
+    Scale(f32),
+    /// Draws the branch this wide from here on, without thinning it out as it
+    /// branches deeper.
+    Width(f32),
+    /// Multiplies the width of the branch, like [`Instruction::Scale`] does
+    /// the length, written `! = taper 0.7`.
+    Taper(f32),
+    /// Draws a leaf this long from where the turtle is, pointing along its
+    /// heading and filled with [`SystemOptions::leaf_color`].
+    ///
+    /// Leaves are scaled like segments and shrink as the branches get deeper.
+    Leaf(f32),
+    /// Draws the branch in a color from here on, written `color #6ac974`.
+    Color(Color),
+    /// Draws the branch in the next color of [`SystemOptions::palette`],
+    /// wrapping around at the end, bound to `'` by default.
+    ///
+    /// The first step goes to the first color of the palette.
+    NextColor,
+    /// Draws the branch in the previous color of the palette, see
+    /// [`Instruction::NextColor`], bound to `,` by default.
+    ///
+    /// The first step goes to the last color of the palette.
+    PrevColor,
+    Push,
+    Pop,
+    /// Starts tracing a filled polygon, like a leaf, from where the turtle is.
+    ///
+    /// Until [`Instruction::EndPolygon`] moving forward adds a corner instead
+    /// of drawing a segment, branches started inside the polygon add theirs
+    /// to it too. Beginning another polygon discards the open one.
+    ///
+    /// Also read as `polybegin`.
+    BeginPolygon,
+    /// Fills the polygon traced since [`Instruction::BeginPolygon`] with
+    /// [`SystemOptions::leaf_color`].
+    ///
+    /// Also read as `polyend`.
+    EndPolygon,
+    /// Runs a callback registered with [`Instructions::insert_custom`].
+    Custom(CustomId),
+}
+
+impl Instruction {
+    /// Parses `forward 1`, or `forward x * 2` whose argument comes from the
+    /// module the instruction is applied to, see [`Instructions::apply`].
+    fn parse<'a>(
+        mut parts: impl Iterator<Item = &'a str>,
+    ) -> Result<(Self, Option<Argument>), String> {
+        let name = parts.next().ok_or("expected an instruction after `=`")?;
+
+        let mut argument = None;
+        let mut number = || -> Result<f32, String> {
+            let number = parts
+                .next()
+                .ok_or_else(|| format!("`{}` expects a number", name))?;
+
+            if let Ok(number) = number.parse() {
+                return Ok(number);
+            }
+
+            let text = [number]
+                .into_iter()
+                .chain(parts.by_ref())
+                .collect::<Vec<_>>();
+            let text = text.join(" ");
+
+            let expr = Expr::parse(&text).map_err(|_| format!("`{}` is not a number", text))?;
+
+            // expressions of numbers alone are worked out right away
+            match expr.eval(&|_| None) {
+                Ok(number) => Ok(number),
+                Err(_) => {
+                    argument = Some(Argument::new(expr));
+                    Ok(0.0)
+                }
+            }
+        };
+
+        let instruction = match name {
+            "forward" => Self::Forward(number()?),
+            "move" => Self::Move(number()?),
+            "turn" => Self::Turn(number()?),
+            "heading" => Self::SetAngle(number()?),
+            "turnaround" => Self::TurnAround,
+            "yaw" => Self::Yaw(number()?),
+            "pitch" => Self::Pitch(number()?),
+            "roll" => Self::Roll(number()?),
+            "scale" => Self::Scale(number()?),
+            "width" => Self::Width(number()?),
+            "taper" => Self::Taper(number()?),
+            "leaf" => Self::Leaf(number()?),
+            "color" => {
+                let color = parts.next().unwrap_or_default();
+                Self::Color(parse_color(color).ok_or_else(|| {
+                    format!("`color` expects a color like #6ac974, not `{}`", color)
+                })?)
+            }
+            "next-color" => Self::NextColor,
+            "prev-color" => Self::PrevColor,
+            "push" => Self::Push,
+            "pop" => Self::Pop,
+            "begin-polygon" | "polybegin" => Self::BeginPolygon,
+            "end-polygon" | "polyend" => Self::EndPolygon,
+            _ => return Err(format!("unknown instruction `{}`", name)),
+        };
+
+        Ok((instruction, argument))
+    }
+
+    /// The name the instruction is written with.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Forward(_) => "forward",
+            Self::Move(_) => "move",
+            Self::Turn(_) => "turn",
+            Self::SetAngle(_) => "heading",
+            Self::TurnAround => "turnaround",
+            Self::Yaw(_) => "yaw",
+            Self::Pitch(_) => "pitch",
+            Self::Roll(_) => "roll",
+            Self::Scale(_) => "scale",
+            Self::Width(_) => "width",
+            Self::Taper(_) => "taper",
+            Self::Leaf(_) => "leaf",
+            Self::Color(_) => "color",
+            Self::NextColor => "next-color",
+            Self::PrevColor => "prev-color",
+            Self::Push => "push",
+            Self::Pop => "pop",
+            Self::BeginPolygon => "begin-polygon",
+            Self::EndPolygon => "end-polygon",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    /// The number the instruction takes, if any.
+    pub fn argument(&self) -> Option<f32> {
+        match *self {
+            Self::Forward(argument)
+            | Self::Move(argument)
+            | Self::Turn(argument)
+            | Self::SetAngle(argument)
+            | Self::Yaw(argument)
+            | Self::Pitch(argument)
+            | Self::Roll(argument)
+            | Self::Scale(argument)
+            | Self::Width(argument)
+            | Self::Taper(argument)
+            | Self::Leaf(argument) => Some(argument),
+            Self::TurnAround
+            | Self::Color(_)
+            | Self::NextColor
+            | Self::PrevColor
+            | Self::Push
+            | Self::Pop
+            | Self::BeginPolygon
+            | Self::EndPolygon
+            | Self::Custom(_) => None,
+        }
+    }
+
+    /// Replaces the number the instruction takes, instructions without one
+    /// are left alone.
+    pub fn set_argument(&mut self, value: f32) {
+        match self {
+            Self::Forward(argument)
+            | Self::Move(argument)
+            | Self::Turn(argument)
+            | Self::SetAngle(argument)
+            | Self::Yaw(argument)
+            | Self::Pitch(argument)
+            | Self::Roll(argument)
+            | Self::Scale(argument)
+            | Self::Width(argument)
+            | Self::Taper(argument)
+            | Self::Leaf(argument) => *argument = value,
+            Self::TurnAround
+            | Self::Color(_)
+            | Self::NextColor
+            | Self::PrevColor
+            | Self::Push
+            | Self::Pop
+            | Self::BeginPolygon
+            | Self::EndPolygon
+            | Self::Custom(_) => {}
+        }
+    }
+
+    /// Turns the other way, mirroring the drawing.
+    ///
+    /// Pitching is the same in the mirror, the heading tips out of the screen
+    /// either way.
+    pub fn negate_angle(&mut self) {
+        // every variant is listed, so a new one has to decide what to do here
+        match self {
+            Self::Turn(angle) | Self::SetAngle(angle) | Self::Yaw(angle) | Self::Roll(angle) => {
+                *angle = -*angle
+            }
+            Self::Forward(_)
+            | Self::Move(_)
+            | Self::TurnAround
+            | Self::Pitch(_)
+            | Self::Scale(_)
+            | Self::Width(_)
+            | Self::Taper(_)
+            | Self::Leaf(_)
+            | Self::Color(_)
+            | Self::NextColor
+            | Self::PrevColor
+            | Self::Push
+            | Self::Pop
+            | Self::BeginPolygon
+            | Self::EndPolygon
+            | Self::Custom(_) => {}
+        }
+    }
+
+    /// Multiplies the distance moved by `factor`.
+    ///
+    /// [`Instruction::Scale`] is relative, so it's left alone.
+    pub fn scale_length(&mut self, factor: f32) {
+        match self {
+            Self::Forward(length) | Self::Move(length) | Self::Leaf(length) => *length *= factor,
+            Self::Turn(_)
+            | Self::SetAngle(_)
+            | Self::TurnAround
+            | Self::Yaw(_)
+            | Self::Pitch(_)
+            | Self::Roll(_)
+            | Self::Scale(_)
+            | Self::Width(_)
+            | Self::Taper(_)
+            | Self::Color(_)
+            | Self::NextColor
+            | Self::PrevColor
+            | Self::Push
+            | Self::Pop
+            | Self::BeginPolygon
+            | Self::EndPolygon
+            | Self::Custom(_) => {}
+        }
+    }
+}
+
+/// Writes the instruction the way it's parsed, custom instructions can't be
+/// parsed and are written as `custom` and their id.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Forward(length) => write!(f, "forward {}", length),
+            Self::Move(length) => write!(f, "move {}", length),
+            Self::Turn(angle) => write!(f, "turn {}", angle),
+            Self::SetAngle(angle) => write!(f, "heading {}", angle),
+            Self::TurnAround => f.write_str("turnaround"),
+            Self::Yaw(angle) => write!(f, "yaw {}", angle),
+            Self::Pitch(angle) => write!(f, "pitch {}", angle),
+            Self::Roll(angle) => write!(f, "roll {}", angle),
+            Self::Scale(scale) => write!(f, "scale {}", scale),
+            Self::Width(width) => write!(f, "width {}", width),
+            Self::Taper(factor) => write!(f, "taper {}", factor),
+            Self::Leaf(length) => write!(f, "leaf {}", length),
+            Self::Color(color) => write!(f, "color {}", format_color(*color)),
+            Self::NextColor => f.write_str("next-color"),
+            Self::PrevColor => f.write_str("prev-color"),
+            Self::Push => f.write_str("push"),
+            Self::Pop => f.write_str("pop"),
+            Self::BeginPolygon => f.write_str("begin-polygon"),
+            Self::EndPolygon => f.write_str("end-polygon"),
+            Self::Custom(id) => write!(f, "custom {}", id.0),
+        }
+    }
+}
+
+/// The argument of an instruction like `F = forward x * 2`, worked out for
+/// every module it's applied to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Argument {
+    pub expr: Expr,
+    /// The parameters of the expression, bound to the arguments of the
+    /// module in this order.
+    pub parameters: Vec<String>,
+}
+
+impl Argument {
+    fn new(expr: Expr) -> Self {
+        let parameters = expr.parameters().into_iter().map(String::from).collect();
+        Self { expr, parameters }
+    }
+
+    /// The value for a module with `arguments`, `None` when it has too few.
+    pub fn eval(&self, arguments: &[f32]) -> Option<f32> {
+        let parameter = |name: &str| {
+            let index = self.parameters.iter().position(|other| other == name)?;
+            arguments.get(index).copied()
+        };
+
+        self.expr.eval(&parameter).ok()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Instructions {
+    instructions: HashMap<char, Instruction>,
+    /// The instructions whose argument comes from the module, see
+    /// [`Instructions::apply`].
+    arguments: HashMap<char, Argument>,
+    customs: CustomRegistry,
+}
+
+/// A parsed instruction line, its symbol, instruction and the argument of a
+/// parametric instruction.
+type InstructionLine = (char, Instruction, Option<Argument>);
+
+impl Instructions {
+    /// Parses a line like `F = forward 1`, blank lines and comments are
+    /// `Ok(None)`.
+    fn parse_instruction(input: &str) -> Result<Option<InstructionLine>, String> {
+        let code = unescape(strip_comment(input));
+        let mut parts = code.split_whitespace();
+
+        let Some(key) = parts.next().and_then(|key| key.chars().next()) else {
+            return Ok(None);
+        };
+
+        if parts.next() != Some("=") {
+            return Err(String::from("expected `=` after the symbol"));
+        }
+
+        let (instruction, argument) = Instruction::parse(parts)?;
+
+        Ok(Some((key, instruction, argument)))
+    }
+
+    pub fn parse(input: &str) -> Self {
+        Self::parse_checked(input).0
+    }
+
+    /// Parses every instruction it can, returning an error for each line it can't.
+    pub fn parse_checked(input: &str) -> (Self, Vec<LilyError>) {
+        let (instructions, errors, _) = Self::parse_bindings(input);
+        (instructions, errors)
+    }
+
+    /// Like [`Instructions::parse_checked`], also returning the 1-based line
+    /// and symbol of every instruction in order, later ones replace earlier
+    /// ones for the same symbol.
+    pub fn parse_bindings(input: &str) -> (Self, Vec<LilyError>, Vec<(usize, char)>) {
+        let mut instructions = Self::new();
+        let mut errors = Vec::new();
+        let mut bindings = Vec::new();
+
+        for (i, line) in input.lines().enumerate() {
+            match Self::parse_instruction(line) {
+                Ok(Some((key, instruction, argument))) => {
+                    instructions.insert(key, instruction);
+                    bindings.push((i + 1, key));
+
+                    if let Some(argument) = argument {
+                        instructions.arguments.insert(key, argument);
+                    }
+                }
+                Ok(None) => {}
+                Err(reason) => errors.push(LilyError::Instruction {
+                    line: i + 1,
+                    text: String::from(line.trim()),
+                    reason,
+                }),
+            }
+        }
+
+        (instructions, errors, bindings)
+    }
+
+    /// Rewrites every instruction in `text` with `transform`, keeping the
+    /// lines that don't parse as they are.
+    ///
+    /// Transforms only scale arguments, so the arguments of parametric
+    /// instructions are multiplied by what `transform` does to 1. Comments
+    /// are kept.
+    pub fn transform_text(text: &str, mut transform: impl FnMut(&mut Instruction)) -> String {
+        let lines: Vec<_> = (text.lines())
+            .map(|line| match Self::parse_instruction(line) {
+                Ok(Some((key, mut instruction, None))) => {
+                    transform(&mut instruction);
+                    with_comment(format!("{} = {}", key, instruction), line)
+                }
+                Ok(Some((key, mut instruction, Some(argument)))) => {
+                    instruction.set_argument(1.0);
+                    transform(&mut instruction);
+
+                    match instruction.argument() {
+                        Some(factor) if factor != 1.0 => {
+                            let factor = Box::new(Expr::Number(factor));
+                            let expr =
+                                Expr::Binary(Operator::Multiply, factor, Box::new(argument.expr));
+
+                            let text = format!("{} = {} {}", key, instruction.name(), expr);
+                            with_comment(text, line)
+                        }
+                        _ => String::from(line),
+                    }
+                }
+                _ => String::from(line),
+            })
+            .collect();
+
+        lines.join("\n")
+    }
+
+    /// Flips the handedness of the system, see [`Instruction::negate_angle`].
+    ///
+    /// Custom instructions run callbacks of their own, which aren't changed.
+    pub fn negate_angles(&mut self) {
+        self.transform(Instruction::negate_angle);
+    }
+
+    /// Rescales the system, see [`Instruction::scale_length`].
+    pub fn scale_lengths(&mut self, factor: f32) {
+        self.transform(|instruction| instruction.scale_length(factor));
+    }
+
+    /// Applies `transform` to every instruction, scaling the arguments of
+    /// parametric ones the way [`Instructions::transform_text`] does.
+    fn transform(&mut self, mut transform: impl FnMut(&mut Instruction)) {
+        for (key, instruction) in self.instructions.iter_mut() {
+            let Some(argument) = self.arguments.get_mut(key) else {
+                transform(instruction);
+                continue;
+            };
+
+            let mut probe = instruction.clone();
+            probe.set_argument(1.0);
+            transform(&mut probe);
+
+            if let Some(factor) = probe.argument().filter(|&factor| factor != 1.0) {
+                let expr = mem::replace(&mut argument.expr, Expr::Number(0.0));
+                argument.expr = Expr::Binary(
+                    Operator::Multiply,
+                    Box::new(Expr::Number(factor)),
+                    Box::new(expr),
+                );
+            }
+        }
+    }
+
+    /// The instruction bound to `c`, if any.
+    pub fn get(&self, c: char) -> Option<&Instruction> {
+        self.instructions.get(&c)
+    }
+
+    /// The argument of the instruction bound to `c`, if it's parametric.
+    pub fn argument(&self, c: char) -> Option<&Argument> {
+        self.arguments.get(&c)
+    }
+
+    /// The bound symbols that never appear in `expanded`, in order.
+    ///
+    /// These are often left over from an earlier version of the rules, though
+    /// they may still be used at other iteration counts.
+    pub fn unused_keys(&self, expanded: &str) -> Vec<char> {
+        let mut unused: HashSet<char> = self.instructions.keys().copied().collect();
+
+        for c in module::symbols(expanded) {
+            // most systems use every key early on
+            if unused.is_empty() {
+                break;
+            }
+
+            unused.remove(&c);
+        }
+
+        let mut unused: Vec<_> = unused.into_iter().collect();
+        unused.sort_unstable();
+        unused
+    }
+
+    /// Every bound symbol and its instruction, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (char, &Instruction)> {
+        self.instructions
+            .iter()
+            .map(|(&c, instruction)| (c, instruction))
+    }
+
+    pub fn new() -> Self {
+        Self {
+            instructions: HashMap::new(),
+            arguments: HashMap::new(),
+            customs: CustomRegistry::new(),
+        }
+    }
+
+    pub fn insert(&mut self, c: char, instruction: Instruction) {
+        self.instructions.insert(c, instruction);
+        self.arguments.remove(&c);
+    }
+
+    /// Checks that every push in `input` is popped and every pop follows a
+    /// push, returning the first symbol that isn't.
+    pub fn validate_brackets(&self, input: &str) -> Result<(), BracketError> {
+        let mut open = Vec::new();
+
+        for (index, symbol) in input.char_indices() {
+            match self.get(symbol) {
+                Some(Instruction::Push) => open.push((index, symbol)),
+                Some(Instruction::Pop) => {
+                    open.pop()
+                        .ok_or(BracketError::UnmatchedPop { index, symbol })?;
+                }
+                _ => {}
+            }
+        }
+
+        match open.first() {
+            Some(&(index, symbol)) => Err(BracketError::UnclosedPush { index, symbol }),
+            None => Ok(()),
+        }
+    }
+
+    /// Binds `[` and `]` to push and pop, which every system expects, and
+    /// the other symbols published systems use the same way.
+    ///
+    /// Only brackets and braces replace what they were bound to, the other
+    /// conventions give way to the instructions written for them.
+    pub fn insert_defaults(&mut self) {
+        self.insert('[', Instruction::Push);
+        self.insert(']', Instruction::Pop);
+        self.insert('{', Instruction::BeginPolygon);
+        self.insert('}', Instruction::EndPolygon);
+
+        let conventions = [
+            ('|', Instruction::TurnAround),
+            ('\'', Instruction::NextColor),
+            (',', Instruction::PrevColor),
+        ];
+
+        for (c, instruction) in conventions {
+            self.instructions.entry(c).or_insert(instruction);
+        }
+    }
+
+    /// Binds `c` to a custom callback, see [`Instruction::Custom`].
+    pub fn insert_custom(
+        &mut self,
+        c: char,
+        callback: impl Fn(&mut TurtleState, &mut dyn TurtleBackend) + Send + Sync + 'static,
+    ) -> CustomId {
+        let id = self.customs.register(callback);
+        self.insert(c, Instruction::Custom(id));
+        id
+    }
+
+    /// The callbacks of the [`Instruction::Custom`]s produced by [`Instructions::apply`].
+    pub fn customs(&self) -> &CustomRegistry {
+        &self.customs
+    }
+
+    /// Turns an expansion into instructions for the turtle.
+    ///
+    /// Parametric instructions like `F = forward x` take their argument from
+    /// the module, the parameters of the expression are bound to the module's
+    /// arguments in the order they first appear. Modules with too few
+    /// arguments for it are skipped.
+    ///
+    /// Digits after a module repeat it, `F12` is twelve `F`s, unless the
+    /// digit has an instruction of its own. Counts go up to [`MAX_REPEAT`].
+    pub fn apply(&self, input: &str) -> Vec<Instruction> {
+        self.apply_iter(module::modules(input))
+    }
+
+    /// Like [`Instructions::apply`] on modules.
+    pub fn apply_modules(&self, input: &[Module]) -> Vec<Instruction> {
+        self.apply_iter(input.iter().cloned())
+    }
+
+    fn apply_iter(&self, modules: impl Iterator<Item = Module>) -> Vec<Instruction> {
+        let mut modules = modules.peekable();
+        let mut instructions = Vec::new();
+
+        while let Some(module) = modules.next() {
+            let mut count = None;
+
+            while let Some(digit) = modules.peek().and_then(|next| self.repeat_digit(next)) {
+                count = Some(usize::min(count.unwrap_or(0) * 10 + digit, MAX_REPEAT));
+                modules.next();
+            }
+
+            // unknown symbols are skipped along with their count
+            if let Some(instruction) = self.instruction(&module) {
+                let count = count.unwrap_or(1);
+                instructions.extend(std::iter::repeat_n(instruction, count));
+            }
+        }
+
+        instructions
+    }
+
+    /// The value of `module` as a digit of a repeat count, `None` for digits
+    /// that have an instruction.
+    fn repeat_digit(&self, module: &Module) -> Option<usize> {
+        if !module.arguments.is_empty() || self.instructions.contains_key(&module.symbol) {
+            return None;
+        }
+
+        module.symbol.to_digit(10).map(|digit| digit as usize)
+    }
+
+    /// The instruction for `module`, with the argument worked out if it's
+    /// parametric.
+    fn instruction(&self, module: &Module) -> Option<Instruction> {
+        let mut instruction = self.instructions.get(&module.symbol)?.clone();
+
+        if let Some(argument) = self.arguments.get(&module.symbol) {
+            instruction.set_argument(argument.eval(&module.arguments)?);
+        }
+
+        Some(instruction)
+    }
+}
+
+/// The most times digits after a module repeat it, see
+/// [`Instructions::apply`].
+pub const MAX_REPEAT: usize = 10_000;
+
+/// Parses a color written as `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(input: &str) -> Option<Color> {
+    let digits = input.trim().strip_prefix('#')?;
+
+    if !digits.is_ascii() || !matches!(digits.len(), 6 | 8) {
+        return None;
+    }
+
+    let channel = |i: usize| -> Option<f32> {
+        let byte = u8::from_str_radix(digits.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        Some(byte as f32 / 255.0)
+    };
+
+    let alpha = match digits.len() {
+        8 => channel(3)?,
+        _ => 1.0,
+    };
+
+    Some(Color::rgba(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
+
+/// Formats a color as `#rrggbb`, or `#rrggbbaa` when it isn't opaque.
+pub fn format_color(color: Color) -> String {
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    let rgb = format!(
+        "#{:02x}{:02x}{:02x}",
+        byte(color.r),
+        byte(color.g),
+        byte(color.b)
+    );
+
+    match byte(color.a) {
+        255 => rgb,
+        a => format!("{}{:02x}", rgb, a),
+    }
+}
+
+/// Serializes a [`Color`] as a hex string, see [`parse_color`] and [`format_color`].
+pub(crate) mod hex_color {
+    use ori::prelude::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_color(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        super::parse_color(&input)
+            .ok_or_else(|| D::Error::custom(format!("invalid color `{}`", input)))
+    }
+}
+
+pub(crate) mod hex_colors {
+    use ori::prelude::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colors: &[Color], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(colors.iter().map(|&color| super::format_color(color)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Color>, D::Error> {
+        let input = Vec::<String>::deserialize(deserializer)?;
+
+        (input.iter())
+            .map(|color| {
+                super::parse_color(color)
+                    .ok_or_else(|| D::Error::custom(format!("invalid color `{}`", color)))
+            })
+            .collect()
+    }
+}
+
+/// Seeds are written as `i64`, which is as far as TOML integers go, seeds
+/// past `i64::MAX` wrap around to negative numbers. Seeds written as `u64`
+/// are still read.
+pub(crate) mod seed_i64 {
+    use std::fmt;
+
+    use serde::{
+        de::{Error, Visitor},
+        Deserializer, Serializer,
+    };
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(*seed as i64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_i64(SeedVisitor)
+    }
+
+    struct SeedVisitor;
+
+    impl Visitor<'_> for SeedVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an integer seed")
+        }
+
+        fn visit_i64<E: Error>(self, seed: i64) -> Result<u64, E> {
+            Ok(seed as u64)
+        }
+
+        fn visit_u64<E: Error>(self, seed: u64) -> Result<u64, E> {
+            Ok(seed)
+        }
+    }
+}
+
+/// Which way positive turn angles turn on screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TurnDirection {
+    #[default]
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Which way along the y axis the turtle starts out, y points down the screen.
+///
+/// Systems growing toward `+y` hang from the top of the view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Growth {
+    #[default]
+    NegativeY,
+    PositiveY,
+}
+
+/// Where a mesh sits in the rectangle it's fit into, see [`mesh_fit`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitMode {
+    /// Centered across, resting on the bottom edge, or hanging from the top
+    /// edge when growing toward `+y`.
+    #[default]
+    BottomCenter,
+    /// Centered both ways.
+    Center,
+}
+
+/// How the strips of consecutive segments meet at a turn, and how the ends
+/// of branches look.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JoinStyle {
+    /// The corner is cut off straight, ends are square.
+    Bevel,
+    /// The corner and the ends are rounded.
+    Round,
+    /// The edges go on until they meet, up to a limit for sharp turns, ends
+    /// are square.
+    #[default]
+    Miter,
+}
+
+/// Whether the turtle stays in the plane of the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dimensions {
+    /// Pitching and rolling are ignored, branches are flat strips.
+    #[default]
+    Two,
+    /// The turtle turns in space, branches are tubes drawn back to front, see
+    /// [`generate_mesh3d`].
+    Three,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SystemOptions {
+    #[serde(with = "hex_color")]
+    pub branch_color: Color,
+    pub branch_width: f32,
+    /// What the width is multiplied by for every branch deeper, 1 keeps
+    /// every branch as wide as the trunk. Kept between 0 and 1, so deep
+    /// branches never grow wider.
+    pub width_falloff: f32,
+    /// What the length of leaves is multiplied by for every branch deeper,
+    /// kept between 0 and 1 like [`width_falloff`](Self::width_falloff).
+    pub leaf_falloff: f32,
+    /// The narrowest segments are drawn, so deep twigs don't thin out until
+    /// they can't be seen.
+    pub min_width: f32,
+    /// Every stroke narrows the branch by this factor from its start to its
+    /// end, like an [`Instruction::Taper`] after each one, 1 keeps the
+    /// width.
+    pub taper: f32,
+    /// The color of the trunk when branches shade toward
+    /// [`tip_color`](Self::tip_color) the deeper they are, both the same
+    /// draws every branch in [`branch_color`](Self::branch_color).
+    #[serde(with = "hex_color")]
+    pub trunk_color: Color,
+    #[serde(with = "hex_color")]
+    pub tip_color: Color,
+    /// How many branches deep the tips are, deeper ones are tip colored too.
+    pub max_depth: usize,
+    /// The colors [`Instruction::NextColor`] and [`Instruction::PrevColor`]
+    /// step through.
+    #[serde(with = "hex_colors")]
+    pub palette: Vec<Color>,
+    /// The color polygons are filled with, see [`Instruction::BeginPolygon`].
+    #[serde(with = "hex_color")]
+    pub leaf_color: Color,
+    /// Turns are off by up to this many degrees either way, picked at random
+    /// for every turn, so systems look less perfect.
+    pub turn_jitter: f32,
+    /// Seeds the turn jitter, the same seed draws the same system.
+    #[serde(with = "seed_i64")]
+    pub jitter_seed: u64,
+    /// The direction branches bend toward, like gravity or the light, `y`
+    /// points down the screen and `z` into it.
+    pub tropism: [f32; 3],
+    /// How many degrees a branch bends toward [`tropism`](Self::tropism) for
+    /// every unit it's long, when it's heading straight across it.
+    pub tropism_strength: f32,
+    pub turn_direction: TurnDirection,
+    pub growth: Growth,
+    pub dimensions: Dimensions,
+    /// Only used by [`MeshBackend`], tubes in space are always round.
+    pub join_style: JoinStyle,
+}
+
+impl Default for SystemOptions {
+    fn default() -> Self {
+        Self {
+            branch_color: hex("#6ac974"),
+            branch_width: 3.0,
+            width_falloff: 0.9,
+            leaf_falloff: 0.9,
+            min_width: 0.0,
+            taper: 1.0,
+            trunk_color: hex("#6ac974"),
+            tip_color: hex("#6ac974"),
+            max_depth: 8,
+            palette: ["#6ac974", "#a3d977", "#8a5a3c", "#dd6c81"]
+                .map(hex)
+                .to_vec(),
+            leaf_color: hex("#a3d977"),
+            turn_jitter: 0.0,
+            jitter_seed: 0,
+            tropism: [0.0, 1.0, 0.0],
+            tropism_strength: 0.0,
+            turn_direction: TurnDirection::default(),
+            growth: Growth::default(),
+            dimensions: Dimensions::default(),
+            join_style: JoinStyle::default(),
+        }
+    }
+}
+
+impl SystemOptions {
+    /// The signs the turtle's x and y are multiplied by, counter-clockwise
+    /// turns mirror the drawing and growing toward `+y` flips it.
+    pub fn axes(&self) -> Vec2 {
+        let x = match self.turn_direction {
+            TurnDirection::Clockwise => 1.0,
+            TurnDirection::CounterClockwise => -1.0,
+        };
+
+        let y = match self.growth {
+            Growth::NegativeY => 1.0,
+            Growth::PositiveY => -1.0,
+        };
+
+        Vec2::new(x, y)
+    }
+}
+
+/// A stroke drawn by the turtle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub from: Point,
+    pub to: Point,
+    /// How far into the screen `from` and `to` are, always 0 in
+    /// [`Dimensions::Two`].
+    pub depth: [f32; 2],
+    /// Unit vector pointing to the turtle's left.
+    pub left: Vector,
+    pub width: f32,
+    /// The width at `to`, narrower than [`width`](Self::width) by
+    /// [`SystemOptions::taper`].
+    pub end_width: f32,
+    pub color: Color,
+}
+
+/// A polygon filled by the turtle, see [`Instruction::BeginPolygon`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygon {
+    pub points: Vec<Point>,
+    /// How far into the screen each point is, always 0 in
+    /// [`Dimensions::Two`].
+    pub depth: Vec<f32>,
+    pub color: Color,
+}
+
+impl Polygon {
+    /// Splits the polygon into triangles, as indices into
+    /// [`points`](Self::points), by clipping ears off it so concave outlines
+    /// are filled too.
+    ///
+    /// Outlines that cross themselves have no ears left at some point, the
+    /// rest of those is filled as a fan.
+    pub fn triangulate(&self) -> Vec<u32> {
+        let points = &self.points;
+
+        // twice the signed area of the triangle `a`, `b`, `c`
+        let cross =
+            |a: Point, b: Point, c: Point| (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+
+        // the sign of the area, which way round the outline goes
+        let winding: f32 = (0..points.len())
+            .map(|i| cross(Point::ZERO, points[i], points[(i + 1) % points.len()]))
+            .sum();
+
+        let mut remaining: Vec<u32> = (0..points.len() as u32).collect();
+        let mut indices = Vec::new();
+
+        while remaining.len() > 3 {
+            let count = remaining.len();
+
+            let ear = (0..count).find(|&i| {
+                let [a, b, c] = [
+                    remaining[(i + count - 1) % count],
+                    remaining[i],
+                    remaining[(i + 1) % count],
+                ];
+                let [pa, pb, pc] = [a, b, c].map(|index| points[index as usize]);
+
+                // a reflex corner isn't an ear
+                if cross(pa, pb, pc) * winding <= 0.0 {
+                    return false;
+                }
+
+                // neither is one with another corner inside it
+                !remaining.iter().any(|&other| {
+                    let p = points[other as usize];
+
+                    ![a, b, c].contains(&other)
+                        && cross(pa, pb, p) * winding >= 0.0
+                        && cross(pb, pc, p) * winding >= 0.0
+                        && cross(pc, pa, p) * winding >= 0.0
+                })
+            });
+
+            let Some(i) = ear else {
+                break;
+            };
+
+            let previous = remaining[(i + count - 1) % count];
+            let next = remaining[(i + 1) % count];
+            indices.extend([previous, remaining[i], next]);
+            remaining.remove(i);
+        }
+
+        for i in 1..remaining.len().saturating_sub(1) {
+            indices.extend([remaining[0], remaining[i], remaining[i + 1]]);
+        }
+
+        indices
+    }
+}
+
+/// Receives the geometry produced by [`interpret`].
+///
+/// This lets every output format share the same turtle semantics.
+pub trait TurtleBackend {
+    /// Draws a segment continuing the current branch.
+    fn segment(&mut self, segment: &Segment);
+
+    /// Moves the turtle along `segment` without drawing it, the branch goes
+    /// on from its end.
+    fn jump(&mut self, _segment: &Segment) {}
+
+    /// Starts a branch, called on [`Instruction::Push`].
+    fn begin_branch(&mut self) {}
+
+    /// Ends the current branch, returning to where it was started.
+    fn end_branch(&mut self) {}
+
+    /// Fills a polygon, backends that only draw lines leave it out.
+    fn polygon(&mut self, _polygon: &Polygon) {}
+}
+
+/// How many times longer than half the width a mitred joint may reach, so
+/// sharp turns don't spike out.
+const MITER_LIMIT: f32 = 4.0;
+
+/// The most a round joint or cap turns between the corners of its fan, in
+/// degrees.
+const ROUND_STEP: f32 = 30.0;
+
+/// The offset to the left of a joint between segments going left `before`
+/// and `after`, for a half width of 1, it's as wide as both segments.
+fn miter(before: Vector, after: Vector) -> Vector {
+    let bisector = before + after;
+    let length = bisector.length();
+
+    // turning all the way back has no joint to speak of
+    if length == 0.0 {
+        return after;
+    }
+
+    let bisector = bisector / length;
+    let cos = bisector.x * after.x + bisector.y * after.y;
+
+    bisector * f32::min(1.0 / cos, MITER_LIMIT)
+}
+
+/// `vector` turned by `angle` radians, from `x` toward `y`.
+fn rotate(vector: Vector, angle: f32) -> Vector {
+    let (sin, cos) = angle.sin_cos();
+    Vector::new(
+        vector.x * cos - vector.y * sin,
+        vector.x * sin + vector.y * cos,
+    )
+}
+
+/// The end of a branch the next segment continues from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Strip {
+    /// The indices of the last two vertices.
+    indices: [u32; 2],
+    /// The last segment, which is drawn up to its end once the strip goes
+    /// on or the branch ends, `None` where the strip starts.
+    last: Option<Segment>,
+}
+
+/// Builds a triangle [`Mesh`], one quad per [`Segment`].
+pub struct MeshBackend {
+    mesh: Mesh,
+    join_style: JoinStyle,
+    // the strip of each open branch
+    strips: Vec<Strip>,
+}
+
+impl MeshBackend {
+    pub fn new(options: &SystemOptions) -> Self {
+        let mut mesh = Mesh::new();
+
+        // the left of the turtle, which mirrors with the turn direction
+        let x = match options.turn_direction {
+            TurnDirection::Clockwise => options.branch_width / 2.0,
+            TurnDirection::CounterClockwise => -options.branch_width / 2.0,
+        };
+        let color = TurtleState::new(options).color_at(1);
+
+        mesh.vertices.push(Vertex {
+            position: Point::new(-x, 0.0),
+            tex_coords: Point::ZERO,
+            color,
+        });
+        mesh.vertices.push(Vertex {
+            position: Point::new(x, 0.0),
+            tex_coords: Point::ZERO,
+            color,
+        });
+
+        Self {
+            mesh,
+            join_style: options.join_style,
+            strips: vec![Strip {
+                indices: [0, 1],
+                last: None,
+            }],
+        }
+    }
+
+    pub fn into_mesh(self) -> Mesh {
+        self.mesh
+    }
+
+    /// Adds the vertices `offset` to either side of `center`, returning the
+    /// index of the first.
+    fn push_pair(&mut self, center: Point, offset: Vector, color: Color) -> u32 {
+        let index = self.mesh.vertices.len() as u32;
+
+        for position in [center + offset, center - offset] {
+            self.mesh.vertices.push(Vertex {
+                position,
+                tex_coords: Point::ZERO,
+                color,
+            });
+        }
+
+        index
+    }
+
+    /// Joins the pair of vertices at `index` to the pair before it.
+    fn bridge(&mut self, [a, b]: [u32; 2], index: u32) {
+        self.mesh.indices.extend([a, b, index]);
+        self.mesh.indices.extend([b, index, index + 1]);
+    }
+
+    /// Fills the circle sector around `center` from `from` as far as `angle`
+    /// radians, as a fan.
+    fn fan(&mut self, center: Point, from: Vector, angle: f32, color: Color) {
+        let steps = (angle.abs() / ROUND_STEP.to_radians()).ceil() as u32;
+        let first = self.mesh.vertices.len() as u32;
+
+        let positions = (0..=steps).map(|step| {
+            let angle = angle * step as f32 / steps.max(1) as f32;
+            center + rotate(from, angle)
+        });
+
+        for position in std::iter::once(center).chain(positions) {
+            self.mesh.vertices.push(Vertex {
+                position,
+                tex_coords: Point::ZERO,
+                color,
+            });
+        }
+
+        for step in 0..steps {
+            let corner = first + 1 + step;
+            self.mesh.indices.extend([first, corner, corner + 1]);
+        }
+    }
+
+    /// Draws the last segment of `strip` up to its end, rounded for
+    /// [`JoinStyle::Round`].
+    fn close(&mut self, strip: &Strip) {
+        let Some(last) = strip.last else {
+            return;
+        };
+
+        // the end is as narrow as the taper left it
+        let left = last.left * last.end_width / 2.0;
+        let index = self.push_pair(last.to, left, last.color);
+        self.bridge(strip.indices, index);
+
+        if self.join_style == JoinStyle::Round {
+            // half a circle, through the side the segment is heading to
+            let heading = last.to - last.from;
+            let ahead = rotate(left, std::f32::consts::FRAC_PI_2);
+            let sign = match ahead.x * heading.x + ahead.y * heading.y < 0.0 {
+                true => -1.0,
+                false => 1.0,
+            };
+
+            self.fan(last.to, left, sign * std::f32::consts::PI, last.color);
+        }
+    }
+
+    /// Draws the ends of the branches that are still open, the mesh is
+    /// complete after this.
+    pub fn finish(&mut self) {
+        for strip in self.strips.clone() {
+            self.close(&strip);
+        }
+    }
+}
+
+impl TurtleBackend for MeshBackend {
+    fn segment(&mut self, segment: &Segment) {
+        let Some(&strip) = self.strips.last() else {
+            return;
+        };
+
+        let half = segment.width / 2.0;
+        let mut indices = strip.indices;
+
+        // the joint with the segment before is shared by both, straight on
+        // it's square to this one
+        let left = match strip.last {
+            Some(before) if before.left != segment.left => match self.join_style {
+                JoinStyle::Miter => miter(before.left, segment.left),
+                JoinStyle::Bevel | JoinStyle::Round => {
+                    // the segment before ends square to itself, this one
+                    // starts square to itself and the corner between them is
+                    // filled in
+                    let end = self.push_pair(segment.from, before.left * half, segment.color);
+                    self.bridge(indices, end);
+                    indices = [end, end + 1];
+
+                    if self.join_style == JoinStyle::Round {
+                        let cross = before.left.x * segment.left.y - before.left.y * segment.left.x;
+                        let dot = before.left.x * segment.left.x + before.left.y * segment.left.y;
+                        let angle = cross.atan2(dot);
+
+                        for side in [1.0, -1.0] {
+                            self.fan(
+                                segment.from,
+                                before.left * half * side,
+                                angle,
+                                segment.color,
+                            );
+                        }
+                    }
+
+                    segment.left
+                }
+            },
+            _ => segment.left,
+        };
+
+        let index = self.push_pair(segment.from, left * half, segment.color);
+        self.bridge(indices, index);
+
+        if let Some(strip) = self.strips.last_mut() {
+            *strip = Strip {
+                indices: [index, index + 1],
+                last: Some(*segment),
+            };
+        }
+    }
+
+    fn jump(&mut self, segment: &Segment) {
+        // the strip is drawn up to where the turtle was, segments close the
+        // strip at their start
+        self.segment(segment);
+
+        // the strip starts over, so it doesn't bridge the gap
+        let left = segment.left * segment.width / 2.0;
+        let index = self.push_pair(segment.to, left, segment.color);
+
+        if let Some(strip) = self.strips.last_mut() {
+            *strip = Strip {
+                indices: [index, index + 1],
+                last: None,
+            };
+        }
+    }
+
+    fn begin_branch(&mut self) {
+        if let Some(&strip) = self.strips.last() {
+            self.strips.push(strip);
+        }
+    }
+
+    fn end_branch(&mut self) {
+        if let Some(strip) = self.strips.pop() {
+            self.close(&strip);
+        }
+    }
+
+    fn polygon(&mut self, polygon: &Polygon) {
+        let mesh = &mut self.mesh;
+        let first = mesh.vertices.len() as u32;
+
+        for &position in polygon.points.iter() {
+            mesh.vertices.push(Vertex {
+                position,
+                tex_coords: Point::ZERO,
+                color: polygon.color,
+            });
+        }
+
+        let triangles = polygon.triangulate();
+        mesh.indices
+            .extend(triangles.iter().map(|index| first + index));
+    }
+}
+
+/// The state of the turtle on one branch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TurtleState {
+    pub position: Vec3,
+    pub orientation: Orientation,
+    pub scale: Scalar,
+    /// The width before the depth falloff is applied.
+    pub width: f32,
+    /// Whether the width was set by [`Instruction::Width`], which turns the
+    /// depth falloff off.
+    pub width_set: bool,
+    /// See [`SystemOptions::width_falloff`].
+    pub width_falloff: f32,
+    /// See [`SystemOptions::leaf_falloff`].
+    pub leaf_falloff: f32,
+    /// See [`SystemOptions::min_width`].
+    pub min_width: f32,
+    /// See [`SystemOptions::taper`].
+    pub taper: f32,
+    pub color: Color,
+    /// The trunk and tip colors branches shade between, `None` once
+    /// [`Instruction::Color`] sets the color, see
+    /// [`SystemOptions::trunk_color`].
+    pub gradient: Option<(Color, Color)>,
+    /// See [`SystemOptions::max_depth`].
+    pub max_depth: usize,
+    /// See [`SystemOptions::palette`], shared by every branch.
+    pub palette: Arc<[Color]>,
+    /// The color of the palette the branch is drawn in, `None` until
+    /// [`Instruction::NextColor`] or [`Instruction::PrevColor`] picks one.
+    pub color_index: Option<usize>,
+    pub leaf_color: Color,
+    /// See [`SystemOptions::turn_jitter`].
+    pub turn_jitter: f32,
+    /// The unit direction branches bend toward in the turtle's own axes, see
+    /// [`SystemOptions::tropism`].
+    pub tropism: Vec3,
+    pub tropism_strength: f32,
+    /// The corners of the polygon being traced, see
+    /// [`Instruction::BeginPolygon`].
+    pub polygon: Option<Vec<Vec3>>,
+    /// The signs of the turtle's axes, see [`SystemOptions::axes`].
+    pub axes: Vec2,
+    pub dimensions: Dimensions,
+}
+
+impl TurtleState {
+    pub fn new(options: &SystemOptions) -> Self {
+        Self {
+            position: Vec3::ZERO,
+            orientation: Orientation::IDENTITY,
+            scale: 1.0,
+            width: options.branch_width,
+            width_set: false,
+            width_falloff: options.width_falloff.clamp(0.0, 1.0),
+            leaf_falloff: options.leaf_falloff.clamp(0.0, 1.0),
+            min_width: options.min_width,
+            taper: options.taper,
+            color: options.branch_color,
+            gradient: (options.trunk_color != options.tip_color)
+                .then_some((options.trunk_color, options.tip_color)),
+            max_depth: options.max_depth,
+            palette: Arc::from(options.palette.as_slice()),
+            color_index: None,
+            leaf_color: options.leaf_color,
+            turn_jitter: options.turn_jitter,
+            tropism: Self::tropism(options),
+            tropism_strength: options.tropism_strength,
+            polygon: None,
+            axes: options.axes(),
+            dimensions: options.dimensions,
+        }
+    }
+
+    /// The tropism of `options` before the turtle's axes are flipped, which
+    /// flipping takes back to the screen, in the plane of the screen for a
+    /// flat turtle.
+    fn tropism(options: &SystemOptions) -> Vec3 {
+        let [x, y, z] = options.tropism.map(|c| c as Scalar);
+        let axes = options.axes();
+
+        let z = match options.dimensions {
+            Dimensions::Two => 0.0,
+            Dimensions::Three => z,
+        };
+
+        let tropism = Vec3::new(x * axes.x, y * axes.y, z);
+
+        match tropism.length() > 0.0 {
+            true => tropism * (1.0 / tropism.length()),
+            false => Vec3::ZERO,
+        }
+    }
+
+    /// Turns the heading toward the tropism before moving `length`, as in
+    /// The Algorithmic Beauty of Plants, never past it.
+    fn bend(&mut self, length: Scalar) {
+        if self.tropism_strength == 0.0 {
+            return;
+        }
+
+        let heading = self.orientation * Vec3::NEG_Y;
+        let axis = heading.cross(self.tropism);
+        let across = axis.length();
+
+        if across == 0.0 {
+            return;
+        }
+
+        let between = across.atan2(heading.dot(self.tropism)).to_degrees();
+        let angle = self.tropism_strength as Scalar * length.abs() * across;
+
+        let bend = Orientation::around(axis * (1.0 / across), angle.min(between));
+        self.orientation = bend * self.orientation;
+    }
+
+    fn orient(&self, direction: Vec3) -> Vec3 {
+        let direction = self.orientation * direction;
+        Vec3::new(
+            direction.x * self.axes.x,
+            direction.y * self.axes.y,
+            direction.z,
+        )
+    }
+
+    /// The direction the turtle is facing.
+    pub fn heading(&self) -> Vec3 {
+        self.orient(Vec3::NEG_Y)
+    }
+
+    /// The direction to the left of the turtle.
+    pub fn left(&self) -> Vec3 {
+        self.orient(Vec3::NEG_X)
+    }
+
+    /// The width segments are drawn with, `depth` branches deep.
+    pub fn width_at(&self, depth: usize) -> f32 {
+        self.scaled_width(self.width, depth)
+    }
+
+    /// The width segments drawn now end with, `depth` branches deep, the
+    /// next one starts that wide.
+    pub fn end_width_at(&self, depth: usize) -> f32 {
+        self.scaled_width(self.width * self.taper, depth)
+    }
+
+    fn scaled_width(&self, width: f32, depth: usize) -> f32 {
+        let width = match self.width_set {
+            true => width,
+            false => width * f32::powi(self.width_falloff, depth as i32),
+        };
+
+        f32::max(width, self.min_width)
+    }
+
+    /// The color segments are drawn with, `depth` branches deep.
+    pub fn color_at(&self, depth: usize) -> Color {
+        let Some((trunk, tip)) = self.gradient else {
+            return self.color;
+        };
+
+        // the trunk is 1 deep
+        let t = depth.saturating_sub(1) as f32 / self.max_depth.max(1) as f32;
+        lerp_color(trunk, tip, t.min(1.0))
+    }
+}
+
+/// The number of corners around a leaf.
+const LEAF_CORNERS: usize = 12;
+
+/// How wide a leaf is, as a part of its length.
+const LEAF_WIDTH: Scalar = 0.3;
+
+/// A leaf `length` long, from the turtle along its heading, see
+/// [`Instruction::Leaf`].
+fn leaf(branch: &TurtleState, length: Scalar) -> Polygon {
+    let (heading, left) = (branch.heading(), branch.left());
+
+    // an ellipse starting at the turtle, which is its first corner
+    let corners: Vec<_> = (0..LEAF_CORNERS)
+        .map(|i| {
+            let angle = (i as Scalar / LEAF_CORNERS as Scalar * 360.0).to_radians();
+            let along = (1.0 - angle.cos()) / 2.0 * length;
+            let across = angle.sin() * LEAF_WIDTH / 2.0 * length;
+
+            branch.position + heading * along + left * across
+        })
+        .collect();
+
+    Polygon {
+        points: corners.iter().map(|corner| corner.to_point()).collect(),
+        depth: corners.iter().map(|corner| corner.depth()).collect(),
+        color: branch.leaf_color,
+    }
+}
+
+/// Runs `instruction`, the turn jitter is picked with `rng`.
+fn apply_instruction(
+    backend: &mut dyn TurtleBackend,
+    stack: &mut Vec<TurtleState>,
+    rng: &mut Pcg32,
+    customs: &CustomRegistry,
+    instruction: &Instruction,
+) {
+    let depth = stack.len();
+
+    let Some(branch) = stack.last_mut() else {
+        return;
+    };
+
+    match *instruction {
+        Instruction::Forward(length) | Instruction::Move(length) => {
+            // apply the scale
+            let length = length as Scalar * branch.scale;
+
+            branch.bend(length);
+
+            let width = branch.width_at(depth);
+
+            let forward = branch.heading() * length;
+
+            let to = branch.position + forward;
+
+            // the outline of a polygon isn't drawn
+            if let Some(polygon) = &mut branch.polygon {
+                polygon.push(to);
+                branch.position = to;
+                return;
+            }
+
+            let segment = Segment {
+                from: branch.position.to_point(),
+                to: to.to_point(),
+                depth: [branch.position.depth(), to.depth()],
+                left: branch.left().to_vector(),
+                width,
+                end_width: branch.end_width_at(depth),
+                color: branch.color_at(depth),
+            };
+
+            match instruction {
+                Instruction::Move(_) => backend.jump(&segment),
+                _ => {
+                    backend.segment(&segment);
+
+                    // the stroke ends where the next one starts, narrower
+                    branch.width *= branch.taper;
+                }
+            }
+
+            branch.position = to;
+        }
+        Instruction::Turn(angle) | Instruction::Yaw(angle) => {
+            let jitter = branch.turn_jitter;
+            let angle = match jitter > 0.0 {
+                true => angle + rng.range(-jitter, jitter),
+                false => angle,
+            };
+
+            branch.orientation = branch.orientation * Orientation::yaw(angle as Scalar);
+        }
+        Instruction::SetAngle(angle) => {
+            branch.orientation = Orientation::yaw(angle as Scalar);
+        }
+        Instruction::TurnAround => {
+            branch.orientation = branch.orientation * Orientation::yaw(180.0);
+        }
+        Instruction::Pitch(angle) if branch.dimensions == Dimensions::Three => {
+            branch.orientation = branch.orientation * Orientation::pitch(angle as Scalar);
+        }
+        Instruction::Roll(angle) if branch.dimensions == Dimensions::Three => {
+            branch.orientation = branch.orientation * Orientation::roll(angle as Scalar);
+        }
+        // a flat turtle stays in the plane of the screen
+        Instruction::Pitch(_) | Instruction::Roll(_) => {}
+        Instruction::Scale(scale) => {
+            branch.scale *= scale as Scalar;
+        }
+        Instruction::Width(width) => {
+            branch.width = width;
+            branch.width_set = true;
+        }
+        Instruction::Taper(factor) => {
+            branch.width *= factor;
+        }
+        Instruction::Leaf(length) => {
+            let falloff = Scalar::powi(branch.leaf_falloff as Scalar, depth as i32);
+            let length = length as Scalar * branch.scale * falloff;
+            backend.polygon(&leaf(branch, length));
+        }
+        Instruction::Color(color) => {
+            branch.color = color;
+            branch.gradient = None;
+        }
+        Instruction::NextColor | Instruction::PrevColor => {
+            let count = branch.palette.len();
+            if count == 0 {
+                return;
+            }
+
+            let index = match (instruction, branch.color_index) {
+                (Instruction::NextColor, Some(index)) => (index + 1) % count,
+                (Instruction::NextColor, None) => 0,
+                (_, Some(index)) => (index + count - 1) % count,
+                (_, None) => count - 1,
+            };
+
+            branch.color_index = Some(index);
+            branch.color = branch.palette[index];
+            branch.gradient = None;
+        }
+        Instruction::Push => {
+            let branch = branch.clone();
+            stack.push(branch);
+            backend.begin_branch();
+        }
+        // the trunk is never popped, an extra pop is ignored
+        Instruction::Pop if depth > 1 => {
+            let popped = stack.pop();
+
+            // a polygon traced across branches keeps the corners of each
+            if let (Some(parent), Some(popped)) = (stack.last_mut(), popped) {
+                if parent.polygon.is_some() && popped.polygon.is_some() {
+                    parent.polygon = popped.polygon;
+                }
+            }
+
+            backend.end_branch();
+        }
+        Instruction::Pop => {}
+        Instruction::BeginPolygon => {
+            branch.polygon = Some(vec![branch.position]);
+        }
+        Instruction::EndPolygon => {
+            let Some(mut corners) = branch.polygon.take() else {
+                return;
+            };
+
+            // an outline traced back to where it started closes there, the
+            // corner it ends on is the first one again
+            if let [first, .., before, last] = corners[..] {
+                if (last - first).length() < (last - before).length() * 1e-3 {
+                    corners.pop();
+                }
+            }
+
+            // fewer than three corners don't enclose anything
+            if corners.len() >= 3 {
+                backend.polygon(&Polygon {
+                    points: corners.iter().map(|corner| corner.to_point()).collect(),
+                    depth: corners.iter().map(|corner| corner.depth()).collect(),
+                    color: branch.leaf_color,
+                });
+            }
+        }
+        Instruction::Custom(id) => {
+            if let Some(callback) = customs.get(id) {
+                callback(branch, backend);
+            }
+        }
+    }
+}
+
+/// Runs the turtle over `instructions`, emitting geometry into `backend`.
+///
+/// [`Instruction::Custom`]s are looked up in `customs`, usually
+/// [`Instructions::customs`], unknown ones are ignored.
+pub fn interpret(
+    options: &SystemOptions,
+    instructions: &[Instruction],
+    customs: &CustomRegistry,
+    backend: &mut dyn TurtleBackend,
+) {
+    let mut stack = vec![TurtleState::new(options)];
+    let mut rng = Pcg32::new(options.jitter_seed);
+
+    for instruction in instructions {
+        apply_instruction(backend, &mut stack, &mut rng, customs, instruction);
+    }
+}
+
+/// The smallest rectangle around every vertex of `mesh`, an empty mesh has
+/// [`Rect::ZERO`].
+pub fn mesh_bounds(mesh: &Mesh) -> Rect {
+    let Some(first) = mesh.vertices.first() else {
+        return Rect::ZERO;
+    };
+
+    let mut bounds = Rect {
+        min: first.position,
+        max: first.position,
+    };
+
+    for vertex in mesh.vertices.iter() {
+        bounds.min = Point::min(bounds.min, vertex.position);
+        bounds.max = Point::max(bounds.max, vertex.position);
+    }
+
+    bounds
+}
+
+/// The scale and offset [`fit_mesh`] moves `mesh` into `rect` with, a point
+/// ends up at `point * scale + offset`.
+pub fn mesh_fit(mesh: &Mesh, rect: Rect, growth: Growth, mode: FitMode) -> (f32, Vector) {
+    let bounds = mesh_bounds(mesh);
+
+    // scale and center the mesh, a straight line is only scaled along its
+    // length and a single point not at all
+    let size = bounds.size();
+    let scale = match (size.width > 0.0, size.height > 0.0) {
+        (true, true) => f32::min(rect.width() / size.width, rect.height() / size.height),
+        (true, false) => rect.width() / size.width,
+        (false, true) => rect.height() / size.height,
+        (false, false) => 1.0,
+    };
+
+    // lines up the middles across, and the edge or the middles down
+    let x = (rect.min.x + rect.max.x - (bounds.min.x + bounds.max.x) * scale) / 2.0;
+    let y = match (mode, growth) {
+        (FitMode::BottomCenter, Growth::NegativeY) => rect.max.y - bounds.max.y * scale,
+        (FitMode::BottomCenter, Growth::PositiveY) => rect.min.y - bounds.min.y * scale,
+        (FitMode::Center, _) => {
+            (rect.min.y + rect.max.y - (bounds.min.y + bounds.max.y) * scale) / 2.0
+        }
+    };
+
+    (scale, Vector::new(x, y))
+}
+
+/// Scales `mesh` uniformly to fit inside `rect`, placed as `mode` says.
+pub fn fit_mesh(mesh: &mut Mesh, rect: Rect, growth: Growth, mode: FitMode) {
+    let (scale, offset) = mesh_fit(mesh, rect, growth, mode);
+
+    for vertex in mesh.vertices.iter_mut() {
+        vertex.position *= scale;
+        vertex.position += offset;
+    }
+}
+
+pub fn generate_mesh(options: &SystemOptions, instructions: &[Instruction]) -> Mesh {
+    generate_mesh_with(options, instructions, &CustomRegistry::new())
+}
+
+/// Like [`generate_mesh`], running [`Instruction::Custom`]s from `customs`.
+pub fn generate_mesh_with(
+    options: &SystemOptions,
+    instructions: &[Instruction],
+    customs: &CustomRegistry,
+) -> Mesh {
+    if options.dimensions == Dimensions::Three {
+        return generate_mesh3d(options, instructions, customs).project();
+    }
+
+    let mut backend = MeshBackend::new(options);
+    interpret(options, instructions, customs, &mut backend);
+    backend.finish();
+    backend.into_mesh()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::PRESETS;
+
+    /// FNV-1a over the bits of every vertex and index, which unlike the
+    /// standard hasher is the same in every Rust version.
+    pub(super) fn fingerprint(mesh: &Mesh) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        let mut write = |word: u32| {
+            for byte in word.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+
+        for vertex in mesh.vertices.iter() {
+            let Color { r, g, b, a } = vertex.color;
+            let position = vertex.position;
+
+            for value in [position.x, position.y, r, g, b, a] {
+                write(value.to_bits());
+            }
+        }
+
+        for &index in mesh.indices.iter() {
+            write(index);
+        }
+
+        hash
+    }
+
+    fn expand(rules: &str, input: &str) -> String {
+        Rules::parse(rules).apply(input, &mut Pcg32::new(0))
+    }
+
+    #[test]
+    fn multi_byte_rules() {
+        // a two symbol rule of multi-byte symbols, next to ascii ones
+        assert_eq!(expand("λ→ -> ab", "xλ→yλ→λ→z"), "xabyababz");
+
+        // a partial match is copied as it is
+        assert_eq!(expand("λ→ -> ab", "λλ→→"), "λab→");
+    }
+
+    #[test]
+    fn longest_rule_wins() {
+        // in either order
+        assert_eq!(expand("F -> FF\nFA -> X", "FAF"), "XFF");
+        assert_eq!(expand("FA -> X\nF -> FF", "FAF"), "XFF");
+
+        // only where the longer rule matches
+        assert_eq!(expand("F -> G\nFAB -> X\nFA -> Y", "FAFABFF"), "YXGG");
+        assert_eq!(expand("FA -> Y\nFAB -> X\nF -> G", "FAFABFF"), "YXGG");
+
+        // multi-character rules overlapping each other
+        assert_eq!(expand("AB -> 1\nABC -> 2\nBC -> 3", "ABCBC"), "23");
+        assert_eq!(expand("AB -> 1\nBC -> 3", "ABC"), "1C");
+    }
+
+    #[test]
+    fn equal_rules_go_in_order() {
+        // both replace one symbol, the one declared first wins
+        assert_eq!(expand("B < A -> X\nA > C -> Y", "BAC"), "BXC");
+        assert_eq!(expand("A > C -> Y\nB < A -> X", "BAC"), "BYC");
+    }
+
+    #[test]
+    fn modules_match_the_longest_rule_too() {
+        let rules = Rules::parse("F -> FF\nFA -> X");
+        let modules = module::modules("FAF").collect::<Vec<_>>();
+
+        let expanded = rules.apply_modules(&modules, &mut Pcg32::new(0));
+        let text: String = expanded.iter().map(Module::to_string).collect();
+        assert_eq!(text, "XFF");
+    }
+
+    #[test]
+    fn multi_byte_replacements() {
+        assert_eq!(expand("F -> λ→F", "FF"), "λ→Fλ→F");
+        assert_eq!(expand("β -> λβ", "ββ"), "λβλβ");
+    }
+
+    #[test]
+    fn greek_rule_keys() {
+        let rules = Rules::parse("αβ -> β\nα -> αβ\nβ -> γ");
+        let mut rng = Pcg32::new(0);
+
+        // the longest rule is matched first, then single symbols
+        let mut tree = String::from("α");
+        for expected in ["αβ", "β", "γ", "γ"] {
+            tree = rules.apply(&tree, &mut rng);
+            assert_eq!(tree, expected);
+        }
+
+        assert_eq!(rules.apply("ααββ", &mut rng), "αββγ");
+    }
+
+    #[test]
+    fn non_ascii_instructions() {
+        let (instructions, errors) = Instructions::parse_checked("λ = forward 1\nβ = turn 90");
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            instructions.apply("λβ→λ"),
+            [
+                Instruction::Forward(1.0),
+                Instruction::Turn(90.0),
+                Instruction::Forward(1.0)
+            ]
+        );
+    }
+
+    // the geometry is computed in `Scalar`, f64 meshes have bits of their own
+    #[cfg(not(feature = "f64"))]
+    #[test]
+    fn plant_fingerprint() {
+        let preset = PRESETS
+            .iter()
+            .find(|preset| preset.name == "Plant")
+            .unwrap();
+
+        let mut definition = preset.definition();
+        definition.iterations = 4;
+        definition.options.turn_jitter = 5.0;
+        definition.options.jitter_seed = 1234;
+
+        // colors are given as numbers, so the fingerprint doesn't depend on
+        // how hex colors are parsed
+        let options = &mut definition.options;
+        options.branch_color = Color::rgb(0.4, 0.8, 0.45);
+        options.trunk_color = options.branch_color;
+        options.tip_color = options.branch_color;
+        options.leaf_color = Color::rgb(0.7, 0.9, 0.5);
+
+        let instructions = definition.instructions();
+        let stream = instructions.apply(&definition.expand());
+        let mesh = generate_mesh_with(&definition.options, &stream, instructions.customs());
+
+        assert_eq!(fingerprint(&mesh), 0x2e7d_3766_779a_82ed);
+    }
+
+    /// Draws branches the way [`generate_mesh`] did before it went through
+    /// [`TurtleBackend`], a strip with a pair of corners at the start of
+    /// every segment.
+    struct Strips {
+        mesh: Mesh,
+        ends: Vec<[u32; 2]>,
+    }
+
+    impl Strips {
+        fn new(options: &SystemOptions) -> Self {
+            let mut mesh = Mesh::new();
+            let x = options.branch_width / 2.0;
+
+            for x in [-x, x] {
+                mesh.vertices.push(Vertex {
+                    position: Point::new(x, 0.0),
+                    tex_coords: Point::ZERO,
+                    color: options.branch_color,
+                });
+            }
+
+            Self {
+                mesh,
+                ends: vec![[0, 1]],
+            }
+        }
+    }
+
+    impl TurtleBackend for Strips {
+        fn segment(&mut self, segment: &Segment) {
+            let left = segment.left * segment.width / 2.0;
+            let index = self.mesh.vertices.len() as u32;
+
+            for position in [segment.from + left, segment.from - left] {
+                self.mesh.vertices.push(Vertex {
+                    position,
+                    tex_coords: Point::ZERO,
+                    color: segment.color,
+                });
+            }
+
+            let end = self.ends.last_mut().unwrap();
+            let [a, b] = *end;
+            (self.mesh.indices).extend([a, b, index, b, index, index + 1]);
+            *end = [index, index + 1];
+        }
+
+        fn begin_branch(&mut self) {
+            self.ends.push(*self.ends.last().unwrap());
+        }
+
+        fn end_branch(&mut self) {
+            self.ends.pop();
+        }
+    }
+
+    // the fingerprint is of the mesh `generate_mesh` made with the default
+    // options before the turtle was split from the mesh, the turtle still
+    // walks the same way
+    #[cfg(not(feature = "f64"))]
+    #[test]
+    fn turtle_walks_as_before_backends() {
+        let rules = Rules::parse("A -> F[-A]F[-A]+FA\nF -> FF");
+        let tree = (0..4).fold(String::from("A"), |tree, _| {
+            rules.apply(&tree, &mut Pcg32::new(0))
+        });
+
+        let instructions =
+            Instructions::parse("F = forward 10\n+ = turn 25\n- = turn -25\n[ = push\n] = pop");
+        let options = SystemOptions {
+            branch_color: Color::rgb(0.4, 0.8, 0.45),
+            ..SystemOptions::default()
+        };
+
+        let mut strips = Strips::new(&options);
+        let stream = instructions.apply(&tree);
+        interpret(&options, &stream, &CustomRegistry::new(), &mut strips);
+
+        assert_eq!(fingerprint(&strips.mesh), 0x0cd8_ab90_a1f2_8f69);
+    }
+
+    #[test]
+    fn branch_ends_are_tapered() {
+        let options = SystemOptions {
+            branch_width: 8.0,
+            width_falloff: 1.0,
+            taper: 0.5,
+            join_style: JoinStyle::Miter,
+            ..SystemOptions::default()
+        };
+
+        let mesh = generate_mesh(&options, &[Instruction::Forward(10.0)]);
+
+        // the first pair of vertices is the start, the last the end cap
+        let across = |pair: &[Vertex]| (pair[0].position - pair[1].position).length();
+        let start = across(&mesh.vertices[..2]);
+        let end = across(&mesh.vertices[mesh.vertices.len() - 2..]);
+
+        assert!((start - 8.0).abs() < 1e-4);
+        assert!((end - 4.0).abs() < 1e-4);
+    }
+
+    /// Keeps the polygons the turtle fills.
+    #[derive(Default)]
+    struct Polygons(Vec<Polygon>);
+
+    impl TurtleBackend for Polygons {
+        fn segment(&mut self, _segment: &Segment) {}
+
+        fn polygon(&mut self, polygon: &Polygon) {
+            self.0.push(polygon.clone());
+        }
+    }
+
+    /// The lengths of leaves drawn on the trunk and one and two branches up.
+    fn leaf_lengths(options: &SystemOptions) -> Vec<f32> {
+        use Instruction::*;
+
+        let instructions = [Leaf(10.0), Push, Leaf(10.0), Push, Leaf(10.0), Pop, Pop];
+        let mut polygons = Polygons::default();
+        interpret(
+            options,
+            &instructions,
+            &CustomRegistry::new(),
+            &mut polygons,
+        );
+
+        // the leaf is an ellipse from the turtle, its far end is halfway
+        let lengths = polygons.0.iter().map(|polygon| {
+            let half = polygon.points.len() / 2;
+            (polygon.points[half] - polygon.points[0]).length()
+        });
+
+        lengths.collect()
+    }
+
+    #[test]
+    fn leaf_falloff() {
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3);
+
+        let options = SystemOptions::default();
+        assert!(close(&leaf_lengths(&options), &[9.0, 8.1, 7.29]));
+
+        let options = SystemOptions {
+            leaf_falloff: 1.0,
+            ..SystemOptions::default()
+        };
+        assert!(close(&leaf_lengths(&options), &[10.0, 10.0, 10.0]));
+
+        // leaves never grow deeper in
+        let options = SystemOptions {
+            leaf_falloff: 1.5,
+            ..SystemOptions::default()
+        };
+        assert!(close(&leaf_lengths(&options), &[10.0, 10.0, 10.0]));
+    }
+
+    /// One of every instruction, with arguments that change when negated or
+    /// scaled.
+    fn every_instruction() -> Vec<Instruction> {
+        vec![
+            Instruction::Forward(2.0),
+            Instruction::Move(2.0),
+            Instruction::Turn(2.0),
+            Instruction::SetAngle(2.0),
+            Instruction::TurnAround,
+            Instruction::Yaw(2.0),
+            Instruction::Pitch(2.0),
+            Instruction::Roll(2.0),
+            Instruction::Scale(2.0),
+            Instruction::Width(2.0),
+            Instruction::Taper(2.0),
+            Instruction::Leaf(2.0),
+            Instruction::Color(Color::rgb(0.2, 0.4, 0.6)),
+            Instruction::NextColor,
+            Instruction::PrevColor,
+            Instruction::Push,
+            Instruction::Pop,
+            Instruction::BeginPolygon,
+            Instruction::EndPolygon,
+            Instruction::Custom(CustomId(3)),
+        ]
+    }
+
+    #[test]
+    fn transforms_cover_every_instruction() {
+        for instruction in every_instruction() {
+            let mut negated = instruction.clone();
+            negated.negate_angle();
+
+            let mut scaled = instruction.clone();
+            scaled.scale_length(3.0);
+
+            // a new instruction doesn't compile here until it's listed, add
+            // it to `every_instruction` too
+            let (expected_negated, expected_scaled) = match instruction {
+                Instruction::Turn(_)
+                | Instruction::SetAngle(_)
+                | Instruction::Yaw(_)
+                | Instruction::Roll(_) => (Some(-2.0), None),
+                Instruction::Forward(_) | Instruction::Move(_) | Instruction::Leaf(_) => {
+                    (None, Some(6.0))
+                }
+                Instruction::TurnAround
+                | Instruction::Pitch(_)
+                | Instruction::Scale(_)
+                | Instruction::Width(_)
+                | Instruction::Taper(_)
+                | Instruction::Color(_)
+                | Instruction::NextColor
+                | Instruction::PrevColor
+                | Instruction::Push
+                | Instruction::Pop
+                | Instruction::BeginPolygon
+                | Instruction::EndPolygon
+                | Instruction::Custom(_) => (None, None),
+            };
+
+            let changed = |transformed: Instruction, expected: Option<f32>| match expected {
+                Some(argument) => {
+                    let mut expected = instruction.clone();
+                    expected.set_argument(argument);
+                    assert_eq!(transformed, expected);
+                }
+                None => assert_eq!(transformed, instruction),
+            };
+
+            changed(negated, expected_negated);
+            changed(scaled, expected_scaled);
+        }
+    }
+
+    #[test]
+    fn transforms_reach_defaults_and_parameters() {
+        let text = "F = forward 2 # trunk\nG(x) = forward x * 2\n+ = turn 25\n- = turn -25";
+        let mut instructions = Instructions::parse(text);
+        instructions.insert_defaults();
+        instructions.negate_angles();
+        instructions.scale_lengths(3.0);
+
+        assert_eq!(
+            instructions.apply("F+G(1)-|["),
+            [
+                Instruction::Forward(6.0),
+                Instruction::Turn(-25.0),
+                Instruction::Forward(6.0),
+                Instruction::Turn(25.0),
+                Instruction::TurnAround,
+                Instruction::Push,
+            ]
+        );
+
+        // the text is rewritten the same way, keeping its comments
+        let text = Instructions::transform_text(text, |instruction| {
+            instruction.negate_angle();
+            instruction.scale_length(3.0);
+        });
+        assert!(text.starts_with("F = forward 6 # trunk\n"));
+
+        let mut parsed = Instructions::parse(&text);
+        parsed.insert_defaults();
+        assert_eq!(parsed.apply("F+G(1)-|["), instructions.apply("F+G(1)-|["));
+    }
+
+    /// The Plant preset without jitter, so mirroring it is exact.
+    fn plant_mesh(options: impl FnOnce(&mut SystemOptions)) -> Mesh {
+        let preset = PRESETS
+            .iter()
+            .find(|preset| preset.name == "Plant")
+            .unwrap();
+
+        let mut definition = preset.definition();
+        definition.iterations = 4;
+        options(&mut definition.options);
+
+        let instructions = definition.instructions();
+        let stream = instructions.apply(&definition.expand());
+        generate_mesh_with(&definition.options, &stream, instructions.customs())
+    }
+
+    /// The fingerprint of `mesh` with its axes multiplied by `x` and `y`.
+    fn flipped(mesh: &Mesh, x: f32, y: f32) -> u64 {
+        let mut mesh = mesh.clone();
+
+        // adding zero makes `-0.0` zero, the mirror of a zero is either
+        for vertex in mesh.vertices.iter_mut() {
+            vertex.position.x = vertex.position.x * x + 0.0;
+            vertex.position.y = vertex.position.y * y + 0.0;
+        }
+
+        fingerprint(&mesh)
+    }
+
+    #[test]
+    fn conventions_mirror_the_mesh() {
+        let reference = plant_mesh(|_| {});
+
+        let counter_clockwise = plant_mesh(|options| {
+            options.turn_direction = TurnDirection::CounterClockwise;
+        });
+        assert_eq!(
+            flipped(&counter_clockwise, 1.0, 1.0),
+            flipped(&reference, -1.0, 1.0)
+        );
+
+        let upward = plant_mesh(|options| options.growth = Growth::PositiveY);
+        assert_eq!(flipped(&upward, 1.0, 1.0), flipped(&reference, 1.0, -1.0));
+
+        let both = plant_mesh(|options| {
+            options.turn_direction = TurnDirection::CounterClockwise;
+            options.growth = Growth::PositiveY;
+        });
+        assert_eq!(flipped(&both, 1.0, 1.0), flipped(&reference, -1.0, -1.0));
+    }
+
+    #[test]
+    fn fitting_follows_the_growth() {
+        let rect = Rect::min_size(Point::ZERO, Size::new(100.0, 100.0));
+
+        let mut standing = plant_mesh(|_| {});
+        fit_mesh(
+            &mut standing,
+            rect,
+            Growth::NegativeY,
+            FitMode::BottomCenter,
+        );
+        assert!((mesh_bounds(&standing).max.y - 100.0).abs() < 1e-3);
+
+        let mut hanging = plant_mesh(|options| options.growth = Growth::PositiveY);
+        fit_mesh(&mut hanging, rect, Growth::PositiveY, FitMode::BottomCenter);
+        assert!(mesh_bounds(&hanging).min.y.abs() < 1e-3);
+    }
+
+    fn points_mesh(points: &[Point]) -> Mesh {
+        let mut mesh = Mesh::new();
+
+        for &position in points {
+            mesh.vertices.push(Vertex {
+                position,
+                tex_coords: Point::ZERO,
+                color: Color::rgb(0.0, 0.0, 0.0),
+            });
+        }
+
+        mesh
+    }
+
+    #[test]
+    fn bounds_leave_out_the_origin() {
+        let mesh = points_mesh(&[Point::new(120.0, 100.0), Point::new(100.0, 140.0)]);
+        let bounds = mesh_bounds(&mesh);
+        assert_eq!(bounds.min, Point::new(100.0, 100.0));
+        assert_eq!(bounds.max, Point::new(120.0, 140.0));
+
+        // the mesh fills the rect as high as it is, not shoved into a corner
+        let rect = Rect::min_size(Point::ZERO, Size::new(100.0, 100.0));
+        let mut fitted = mesh.clone();
+        fit_mesh(&mut fitted, rect, Growth::NegativeY, FitMode::Center);
+        let fitted = mesh_bounds(&fitted);
+        assert!((fitted.min.x - 25.0).abs() < 1e-3 && (fitted.max.x - 75.0).abs() < 1e-3);
+        assert!(fitted.min.y.abs() < 1e-3 && (fitted.max.y - 100.0).abs() < 1e-3);
+
+        assert_eq!(mesh_bounds(&Mesh::new()), Rect::ZERO);
+    }
+
+    #[test]
+    fn straight_lines_fit_along_their_length() {
+        let rect = Rect::min_size(Point::ZERO, Size::new(100.0, 50.0));
+
+        let vertical = points_mesh(&[Point::new(5.0, 0.0), Point::new(5.0, 10.0)]);
+        let (scale, offset) = mesh_fit(&vertical, rect, Growth::NegativeY, FitMode::Center);
+        assert_eq!(scale, 5.0);
+        assert_eq!(offset, Vector::new(25.0, 0.0));
+
+        let horizontal = points_mesh(&[Point::new(0.0, 5.0), Point::new(20.0, 5.0)]);
+        let (scale, offset) = mesh_fit(&horizontal, rect, Growth::NegativeY, FitMode::Center);
+        assert_eq!(scale, 5.0);
+        assert_eq!(offset, Vector::new(0.0, 0.0));
+
+        // a single point is moved to the middle without scaling
+        let point = points_mesh(&[Point::new(10.0, 10.0)]);
+        let (scale, offset) = mesh_fit(&point, rect, Growth::NegativeY, FitMode::Center);
+        assert_eq!(scale, 1.0);
+        assert_eq!(offset, Vector::new(40.0, 15.0));
+    }
+
+    #[test]
+    fn polygons_are_filled() {
+        let leaf_color = Color::rgb(0.7, 0.9, 0.5);
+        let options = SystemOptions {
+            leaf_color,
+            ..SystemOptions::default()
+        };
+
+        // `{` and `}` are bound like `[` and `]`
+        let mut instructions = Instructions::parse("F = forward 10\n+ = turn 120");
+        instructions.insert_defaults();
+        let stream = instructions.apply("{F+F+F}");
+
+        let mut polygons = Polygons::default();
+        interpret(&options, &stream, &CustomRegistry::new(), &mut polygons);
+        assert_eq!(polygons.0.len(), 1);
+        assert_eq!(polygons.0[0].points.len(), 3);
+        assert_eq!(polygons.0[0].color, leaf_color);
+
+        // a triangle is three vertices and one triangle, after the two the
+        // mesh starts with
+        let mesh = generate_mesh(&options, &stream);
+        assert_eq!(mesh.vertices.len(), 2 + 3);
+        assert_eq!(mesh.indices.len(), 3);
+        assert!(mesh.vertices[2..]
+            .iter()
+            .all(|vertex| vertex.color == leaf_color));
+    }
+
+    #[test]
+    fn branches_add_corners_to_polygons() {
+        let mut instructions = Instructions::parse("F = forward 10\n+ = turn 90\n- = turn -90");
+        instructions.insert_defaults();
+
+        let mut polygons = Polygons::default();
+        // a square with a stalk out of its second corner
+        let stream = instructions.apply("{F+F[-F]+F+F}");
+        interpret(
+            &SystemOptions::default(),
+            &stream,
+            &CustomRegistry::new(),
+            &mut polygons,
+        );
+
+        // the outline is closed where it started, the branch adds its end
+        let points = &polygons.0[0].points;
+        assert_eq!(points.len(), 5);
+        assert_eq!(polygons.0[0].triangulate().len(), 3 * (points.len() - 2));
+    }
+
+    #[derive(Default)]
+    struct Segments(Vec<Segment>);
+
+    impl TurtleBackend for Segments {
+        fn segment(&mut self, segment: &Segment) {
+            self.0.push(*segment);
+        }
+    }
+
+    /// The segments the turtle draws for `text`, `[` and `]` are push and pop
+    /// unless `instructions` binds them.
+    fn segments(options: &SystemOptions, instructions: &str, text: &str) -> Vec<Segment> {
+        let mut instructions = Instructions::parse(instructions);
+        instructions.insert_defaults();
+
+        let mut segments = Segments::default();
+        let stream = instructions.apply(text);
+        interpret(options, &stream, &CustomRegistry::new(), &mut segments);
+        segments.0
+    }
+
+    /// The ends of the segments the turtle draws for `text`.
+    fn segment_ends(instructions: &str, text: &str, dimensions: Dimensions) -> Vec<Point> {
+        let options = SystemOptions {
+            dimensions,
+            ..SystemOptions::default()
+        };
+
+        let segments = segments(&options, instructions, text);
+        segments.iter().map(|segment| segment.to).collect()
+    }
+
+    fn same_points(a: &[Point], b: &[Point]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (*a - *b).length() < 1e-3)
+    }
+
+    #[test]
+    fn heading_overwrites_the_turns() {
+        let instructions = "F = forward 10\n+ = turn 30\n- = turn -45\nH = heading 90";
+
+        // 90 degrees from the start, however the turtle got turned before
+        for before in ["", "+", "-", "+++", "-+-"] {
+            let heading = segment_ends(instructions, &format!("F{}HF", before), Dimensions::Two);
+            let turns = segment_ends(instructions, "F+++F", Dimensions::Two);
+            assert!(same_points(&heading, &turns), "after `{}`", before);
+        }
+
+        // pitches and rolls too
+        let instructions = format!("{}\n^ = pitch 40\n/ = roll 25", instructions);
+        let heading = segment_ends(&instructions, "F^/+HF", Dimensions::Three);
+        let turns = segment_ends(&instructions, "F+++F", Dimensions::Three);
+        assert!(same_points(&heading, &turns));
+    }
+
+    #[test]
+    fn moves_leave_a_gap() {
+        let instructions = "F = forward 10\ng = move 10\n@ = scale 0.5\n+ = turn 90";
+
+        // moves are scaled and turned like forwards, without a segment
+        let ends = segment_ends(instructions, "Fg@F+gF", Dimensions::Two);
+        let expected = [
+            Point::new(0.0, -10.0),
+            Point::new(0.0, -25.0),
+            Point::new(10.0, -25.0),
+        ];
+        assert!(same_points(&ends, &expected), "{:?}", ends);
+
+        // the next forward starts a strip of its own, no triangle crosses
+        // the gap between -10 and -20
+        let stream = Instructions::parse(instructions).apply("FgF");
+        let mesh = generate_mesh(&SystemOptions::default(), &stream);
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let y = |index: &u32| mesh.vertices[*index as usize].position.y;
+            let below = triangle.iter().any(|index| y(index) > -10.5);
+            let above = triangle.iter().any(|index| y(index) < -19.5);
+            assert!(!(below && above), "{:?} crosses the gap", triangle);
+        }
+    }
+
+    #[test]
+    fn colors_follow_the_branches() {
+        let instructions = "F = forward 1\ng = color #3a7d44\nr = color #ff0000";
+        let options = SystemOptions {
+            branch_color: Color::rgb(0.5, 0.5, 0.5),
+            ..SystemOptions::default()
+        };
+
+        // a branch starts in the color of its parent and gives it back
+        let segments = segments(&options, instructions, "FgF[FrF]F");
+        let colors: Vec<_> = segments.iter().map(|segment| segment.color).collect();
+
+        let green = parse_color("#3a7d44").unwrap();
+        let red = Color::rgb(1.0, 0.0, 0.0);
+        let depth = |color: Color, depth: usize| {
+            let mut state = TurtleState::new(&options);
+            state.color = color;
+            state.color_at(depth)
+        };
+
+        assert_eq!(
+            colors,
+            [
+                depth(options.branch_color, 1),
+                depth(green, 1),
+                depth(green, 2),
+                depth(red, 2),
+                depth(green, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn color_instructions_round_trip() {
+        let (instructions, errors) = Instructions::parse_checked("g = color #3a7d44");
+        assert!(errors.is_empty());
+
+        let color = instructions.get('g').unwrap();
+        assert_eq!(*color, Instruction::Color(parse_color("#3a7d44").unwrap()));
+        assert_eq!(color.to_string(), "color #3a7d44");
+
+        // a color that doesn't parse is an error, not left out quietly
+        let (_, errors) = Instructions::parse_checked("F = forward 1\ng = color #3a7d4");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], LilyError::Instruction { line: 2, .. }));
+    }
+
+    #[test]
+    fn parse_errors_have_lines_and_reasons() {
+        let lines = |errors: &[LilyError]| -> Vec<String> {
+            errors.iter().map(LilyError::to_string).collect()
+        };
+
+        // the rules that parse are kept, comments and blank lines are skipped
+        let (rules, errors) = Rules::parse_checked("F -> FF\n\nF => FF\n# a comment\n -> F");
+        assert_eq!(rules.len(), 1);
+        assert_eq!(
+            lines(&errors),
+            [
+                "line 3: invalid rule `F => FF`: expected `->`",
+                "line 5: invalid rule `-> F`: nothing to replace before `->`",
+            ]
+        );
+
+        let (instructions, errors) =
+            Instructions::parse_checked("F = forward 1\nG = fly\n+ = turn");
+        assert_eq!(instructions.get('F'), Some(&Instruction::Forward(1.0)));
+        assert_eq!(
+            lines(&errors),
+            [
+                "line 2: invalid instruction `G = fly`: unknown instruction `fly`",
+                "line 3: invalid instruction `+ = turn`: `turn` expects a number",
+            ]
+        );
+    }
+
+    /// How far down the screen each segment of a branch heads, the branch
+    /// starts out across the tropism and goes on for `count` segments.
+    fn downward(options: &SystemOptions, count: usize) -> Vec<f32> {
+        let text = format!("+{}", "F".repeat(count));
+        let segments = segments(options, "F = forward 1\n+ = turn 90", &text);
+        let ends: Vec<_> = segments.iter().map(|segment| segment.to).collect();
+
+        let starts = std::iter::once(Point::ZERO).chain(ends.iter().copied());
+        (starts.zip(&ends))
+            .map(|(from, to)| {
+                let direction = *to - from;
+                direction.y / direction.length()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn branches_bend_toward_the_tropism() {
+        for dimensions in [Dimensions::Two, Dimensions::Three] {
+            for growth in [Growth::NegativeY, Growth::PositiveY] {
+                let options = SystemOptions {
+                    tropism_strength: 2.0,
+                    dimensions,
+                    growth,
+                    ..SystemOptions::default()
+                };
+
+                // down the screen whichever way y grows, and never past it
+                let down = downward(&options, 200);
+                assert!(down.windows(2).all(|pair| pair[1] >= pair[0] - 1e-6));
+                assert!(down[0] < 0.1, "{:?} {:?}", dimensions, growth);
+                assert!(down[199] > 0.999, "{:?} {:?}", dimensions, growth);
+            }
+        }
+    }
+
+    #[test]
+    fn no_strength_no_bend() {
+        let options = SystemOptions {
+            tropism: [1.0, 0.5, 0.0],
+            ..SystemOptions::default()
+        };
+
+        // exactly the same segments as without a tropism
+        let instructions = "F = forward 1\n+ = turn 30";
+        let bent = segments(&options, instructions, "F+F+FFF");
+        let straight = segments(&SystemOptions::default(), instructions, "F+F+FFF");
+        assert_eq!(bent, straight);
+    }
+
+    #[test]
+    fn branches_shade_from_trunk_to_tip() {
+        let trunk = Color::rgb(1.0, 0.0, 0.0);
+        let tip = Color::rgb(0.0, 0.0, 1.0);
+        let options = SystemOptions {
+            trunk_color: trunk,
+            tip_color: tip,
+            max_depth: 2,
+            ..SystemOptions::default()
+        };
+
+        // branches deeper than the tips keep the tip color, the colors are
+        // mixed in oklab so even the ends go through lerp_color
+        let shade = |t| lerp_color(trunk, tip, t);
+        let colors = |instructions, text| -> Vec<_> {
+            let segments = segments(&options, instructions, text);
+            segments.iter().map(|segment| segment.color).collect()
+        };
+
+        let shaded = colors("F = forward 1", "F[F[F[F]]]");
+        assert_eq!(shaded, [shade(0.0), shade(0.5), shade(1.0), shade(1.0)]);
+
+        // a color instruction takes over from the gradient
+        let green = Color::rgb(0.0, 1.0, 0.0);
+        let colored = colors("F = forward 1\ng = color #00ff00", "F[gF[F]]");
+        assert_eq!(colored, [shade(0.0), green, green]);
+    }
+
+    #[test]
+    fn same_trunk_and_tip_use_the_branch_color() {
+        let branch = Color::rgb(0.5, 0.5, 0.5);
+        let options = SystemOptions {
+            branch_color: branch,
+            trunk_color: Color::rgb(0.2, 0.6, 0.3),
+            tip_color: Color::rgb(0.2, 0.6, 0.3),
+            ..SystemOptions::default()
+        };
+
+        let segments = segments(&options, "F = forward 1", "F[F[F]]");
+        let colors: Vec<_> = segments.iter().map(|segment| segment.color).collect();
+        assert_eq!(colors, [branch; 3]);
+    }
+
+    #[test]
+    fn widths_never_drop_below_the_minimum() {
+        let widths = |options: &SystemOptions, instructions, text: &str| -> Vec<_> {
+            let segments = segments(options, instructions, text);
+            segments.iter().map(|segment| segment.width).collect()
+        };
+
+        // a segment 100 branches deep
+        let text = format!("{}F{}", "F[".repeat(100), "]".repeat(100));
+
+        // the falloff narrows every branch by 0.9 unless told otherwise
+        let falloff = widths(&SystemOptions::default(), "F = forward 1", &text);
+        assert_eq!(falloff.len(), 101);
+        for pair in falloff.windows(2) {
+            assert!((pair[1] - pair[0] * 0.9).abs() < 1e-6);
+        }
+
+        let options = SystemOptions {
+            min_width: 0.5,
+            ..SystemOptions::default()
+        };
+        let clamped = widths(&options, "F = forward 1", &text);
+
+        for (clamped, falloff) in clamped.iter().zip(&falloff) {
+            assert_eq!(*clamped, falloff.max(0.5));
+        }
+        assert_eq!(clamped.last(), Some(&0.5));
+
+        // set widths are clamped too
+        let set = widths(&options, "F = forward 1\nw = width 0.1", "wF[F]");
+        assert_eq!(set, [0.5, 0.5]);
+    }
 }
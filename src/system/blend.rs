@@ -0,0 +1,261 @@
+//! Interpolating between two versions of a system that only differ in numbers.
+
+use ori::prelude::*;
+
+use super::{with_comment, Instruction, Instructions, SystemOptions};
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn to_linear(c: f32) -> f32 {
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn to_srgb(c: f32) -> f32 {
+    match c <= 0.0031308 {
+        true => c * 12.92,
+        false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+/// Converts to Oklab, where distances roughly match how different colors look.
+fn to_oklab(color: Color) -> [f32; 3] {
+    let [r, g, b] = [color.r, color.g, color.b].map(to_linear);
+
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn from_oklab([l, a, b]: [f32; 3], alpha: f32) -> Color {
+    let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+    let rgb = [
+        4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_,
+        -1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_,
+        -0.004_196_086 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_,
+    ];
+
+    let [r, g, b] = rgb.map(|c| to_srgb(c).clamp(0.0, 1.0));
+    Color::rgba(r, g, b, alpha)
+}
+
+/// Blends two colors in Oklab, which keeps the midpoints from going muddy.
+pub fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let (from, to) = (to_oklab(a), to_oklab(b));
+    let mixed = [0, 1, 2].map(|i| lerp(from[i], to[i], t));
+
+    from_oklab(mixed, lerp(a.a, b.a, t))
+}
+
+impl Instruction {
+    /// Blends the arguments of two instructions of the same kind, `None` when
+    /// they're different kinds.
+    pub fn lerp(&self, other: &Self, t: f32) -> Option<Self> {
+        match (self, other) {
+            (Self::Forward(a), Self::Forward(b)) => Some(Self::Forward(lerp(*a, *b, t))),
+            (Self::Move(a), Self::Move(b)) => Some(Self::Move(lerp(*a, *b, t))),
+            (Self::Turn(a), Self::Turn(b)) => Some(Self::Turn(lerp(*a, *b, t))),
+            (Self::SetAngle(a), Self::SetAngle(b)) => Some(Self::SetAngle(lerp(*a, *b, t))),
+            (Self::TurnAround, Self::TurnAround) => Some(Self::TurnAround),
+            (Self::Yaw(a), Self::Yaw(b)) => Some(Self::Yaw(lerp(*a, *b, t))),
+            (Self::Pitch(a), Self::Pitch(b)) => Some(Self::Pitch(lerp(*a, *b, t))),
+            (Self::Roll(a), Self::Roll(b)) => Some(Self::Roll(lerp(*a, *b, t))),
+            (Self::Scale(a), Self::Scale(b)) => Some(Self::Scale(lerp(*a, *b, t))),
+            (Self::Width(a), Self::Width(b)) => Some(Self::Width(lerp(*a, *b, t))),
+            (Self::Taper(a), Self::Taper(b)) => Some(Self::Taper(lerp(*a, *b, t))),
+            (Self::Leaf(a), Self::Leaf(b)) => Some(Self::Leaf(lerp(*a, *b, t))),
+            (Self::Color(a), Self::Color(b)) => Some(Self::Color(lerp_color(*a, *b, t))),
+            (Self::NextColor, Self::NextColor) => Some(Self::NextColor),
+            (Self::PrevColor, Self::PrevColor) => Some(Self::PrevColor),
+            (Self::Push, Self::Push) => Some(Self::Push),
+            (Self::Pop, Self::Pop) => Some(Self::Pop),
+            (Self::BeginPolygon, Self::BeginPolygon) => Some(Self::BeginPolygon),
+            (Self::EndPolygon, Self::EndPolygon) => Some(Self::EndPolygon),
+            (Self::Custom(a), Self::Custom(b)) if a == b => Some(Self::Custom(*a)),
+            _ => None,
+        }
+    }
+}
+
+impl Instructions {
+    /// Blends two instruction texts line by line, the lines have to bind the
+    /// same symbols to the same kinds of instructions.
+    ///
+    /// Lines that aren't instructions, or are parametric, have to be identical.
+    pub fn blend_text(a: &str, b: &str, t: f32) -> Result<String, String> {
+        let (a_lines, b_lines): (Vec<_>, Vec<_>) = (a.lines().collect(), b.lines().collect());
+
+        if a_lines.len() != b_lines.len() {
+            return Err(String::from(
+                "the instructions have a different number of lines",
+            ));
+        }
+
+        let mut lines = Vec::with_capacity(a_lines.len());
+
+        for (i, (a, b)) in a_lines.into_iter().zip(b_lines).enumerate() {
+            let different = || format!("instruction line {} is different", i + 1);
+
+            match (Self::parse_instruction(a), Self::parse_instruction(b)) {
+                // parametric arguments can't be blended, so they have to match
+                (Ok(Some((key, x, None))), Ok(Some((other, y, None)))) if key == other => {
+                    let blended = x.lerp(&y, t).ok_or_else(different)?;
+                    lines.push(with_comment(format!("{} = {}", key, blended), a));
+                }
+                _ if a.trim() == b.trim() => lines.push(String::from(a)),
+                _ => return Err(different()),
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+}
+
+impl SystemOptions {
+    /// Blends the numeric options, colors are blended with [`lerp_color`].
+    ///
+    /// The coordinate conventions can't be blended, those of `self` are kept.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            branch_color: lerp_color(self.branch_color, other.branch_color, t),
+            branch_width: lerp(self.branch_width, other.branch_width, t),
+            width_falloff: lerp(self.width_falloff, other.width_falloff, t),
+            leaf_falloff: lerp(self.leaf_falloff, other.leaf_falloff, t),
+            min_width: lerp(self.min_width, other.min_width, t),
+            taper: lerp(self.taper, other.taper, t),
+            trunk_color: lerp_color(self.trunk_color, other.trunk_color, t),
+            tip_color: lerp_color(self.tip_color, other.tip_color, t),
+            max_depth: lerp(self.max_depth as f32, other.max_depth as f32, t).round() as usize,
+            // palettes of different lengths have no colors to pair up
+            palette: match self.palette.len() == other.palette.len() {
+                true => (self.palette.iter().zip(other.palette.iter()))
+                    .map(|(&a, &b)| lerp_color(a, b, t))
+                    .collect(),
+                false => self.palette.clone(),
+            },
+            leaf_color: lerp_color(self.leaf_color, other.leaf_color, t),
+            turn_jitter: lerp(self.turn_jitter, other.turn_jitter, t),
+            tropism: [0, 1, 2].map(|i| lerp(self.tropism[i], other.tropism[i], t)),
+            tropism_strength: lerp(self.tropism_strength, other.tropism_strength, t),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{CustomId, TurnDirection};
+
+    fn close(a: Color, b: Color) -> bool {
+        let channels = |c: Color| [c.r, c.g, c.b, c.a];
+        let (a, b) = (channels(a), channels(b));
+        (0..4).all(|i| (a[i] - b[i]).abs() < 1e-3)
+    }
+
+    #[test]
+    fn colors_blend_in_oklab() {
+        let green = Color::rgb(0.42, 0.79, 0.45);
+        let pink = Color::rgba(0.87, 0.42, 0.5, 0.5);
+
+        assert!(close(lerp_color(green, pink, 0.0), green));
+        assert!(close(lerp_color(green, pink, 1.0), pink));
+
+        // halfway in lightness is darker than halfway in sRGB
+        let gray = lerp_color(Color::BLACK, Color::WHITE, 0.5);
+        assert!(close(gray, Color::rgb(0.389, 0.389, 0.389)));
+
+        assert_eq!(lerp_color(green, pink, 0.5).a, 0.75);
+    }
+
+    #[test]
+    fn instructions_blend_within_a_kind() {
+        let blended = Instruction::Turn(20.0).lerp(&Instruction::Turn(40.0), 0.25);
+        assert_eq!(blended, Some(Instruction::Turn(25.0)));
+
+        let blended = Instruction::Push.lerp(&Instruction::Push, 0.5);
+        assert_eq!(blended, Some(Instruction::Push));
+
+        assert_eq!(
+            Instruction::Turn(20.0).lerp(&Instruction::Yaw(20.0), 0.5),
+            None
+        );
+
+        let (a, b) = (
+            Instruction::Custom(CustomId(0)),
+            Instruction::Custom(CustomId(1)),
+        );
+        assert_eq!(a.lerp(&b, 0.5), None);
+    }
+
+    #[test]
+    fn instruction_texts_blend_line_by_line() {
+        let a = "F = forward 2 # trunk\n+ = turn 20\nG = forward x";
+        let b = "F = forward 4\n+ = turn 30\nG = forward x";
+
+        let blended = Instructions::blend_text(a, b, 0.5).unwrap();
+        assert_eq!(blended, "F = forward 3 # trunk\n+ = turn 25\nG = forward x");
+
+        let err = Instructions::blend_text(a, "F = forward 4", 0.5).unwrap_err();
+        assert!(err.contains("number of lines"));
+
+        // different kinds, keys and parametric arguments can't be blended
+        for b in [
+            "F = move 4\n+ = turn 30\nG = forward x",
+            "F = forward 4\n- = turn 30\nG = forward x",
+            "F = forward 4\n+ = turn 30\nG = forward x * 2",
+        ] {
+            assert!(Instructions::blend_text(a, b, 0.5).is_err(), "{}", b);
+        }
+    }
+
+    #[test]
+    fn options_blend_their_numbers() {
+        let a = SystemOptions {
+            branch_width: 2.0,
+            max_depth: 4,
+            branch_color: Color::rgb(0.42, 0.79, 0.45),
+            palette: vec![Color::WHITE],
+            tropism: [0.0, 1.0, 0.0],
+            ..SystemOptions::default()
+        };
+
+        let b = SystemOptions {
+            branch_width: 4.0,
+            max_depth: 7,
+            branch_color: Color::rgb(0.87, 0.42, 0.5),
+            palette: vec![Color::BLACK, Color::WHITE],
+            tropism: [1.0, 0.0, 0.0],
+            turn_direction: TurnDirection::CounterClockwise,
+            ..SystemOptions::default()
+        };
+
+        let blended = a.lerp(&b, 0.5);
+        assert_eq!(blended.branch_width, 3.0);
+        assert_eq!(blended.max_depth, 6);
+        assert_eq!(blended.tropism, [0.5, 0.5, 0.0]);
+        let color = lerp_color(a.branch_color, b.branch_color, 0.5);
+        assert_eq!(blended.branch_color, color);
+
+        // palettes of different lengths and conventions are kept from `a`
+        assert_eq!(blended.palette, a.palette);
+        assert_eq!(blended.turn_direction, TurnDirection::Clockwise);
+
+        let end = a.lerp(&b, 1.0);
+        assert_eq!(end.branch_width, b.branch_width);
+        assert!(close(end.branch_color, b.branch_color));
+    }
+}
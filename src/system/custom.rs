@@ -0,0 +1,66 @@
+//! Instructions defined by library users.
+
+use std::{fmt, sync::Arc};
+
+use super::{TurtleBackend, TurtleState};
+
+/// Identifies a callback in a [`CustomRegistry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CustomId(pub usize);
+
+/// The behavior of an [`Instruction::Custom`](super::Instruction::Custom).
+///
+/// It may change the turtle state and emit geometry into the backend.
+pub type CustomCallback = Arc<dyn Fn(&mut TurtleState, &mut dyn TurtleBackend) + Send + Sync>;
+
+/// Maps [`CustomId`]s to their callbacks.
+#[derive(Clone, Default)]
+pub struct CustomRegistry {
+    callbacks: Vec<CustomCallback>,
+}
+
+impl CustomRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self,
+        callback: impl Fn(&mut TurtleState, &mut dyn TurtleBackend) + Send + Sync + 'static,
+    ) -> CustomId {
+        self.callbacks.push(Arc::new(callback));
+        CustomId(self.callbacks.len() - 1)
+    }
+
+    pub fn get(&self, id: CustomId) -> Option<&CustomCallback> {
+        self.callbacks.get(id.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.callbacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+}
+
+impl fmt::Debug for CustomRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomRegistry")
+            .field("callbacks", &self.callbacks.len())
+            .finish()
+    }
+}
+
+// callbacks can't be compared, so registries are equal when they share them
+impl PartialEq for CustomRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.callbacks.len() == other.callbacks.len()
+            && self
+                .callbacks
+                .iter()
+                .zip(&other.callbacks)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
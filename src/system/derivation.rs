@@ -0,0 +1,130 @@
+use crate::{error::Result, LilyError};
+
+use super::{Pcg32, Rules};
+
+/// Every expansion of an axiom up to some iteration, kept so asking for more
+/// iterations continues from the last one instead of starting over.
+///
+/// The expansions are the same as [`Rules::expansions`] with the same seed.
+#[derive(Clone, Debug)]
+pub struct Derivation {
+    rules: Rules,
+    axiom: String,
+    seed: u64,
+    trees: Vec<String>,
+    /// The generator after the last expansion, so stochastic rules pick the
+    /// same way they would have without stopping.
+    rng: Pcg32,
+}
+
+impl Derivation {
+    pub fn new(rules: Rules, axiom: &str, seed: u64) -> Self {
+        Self {
+            rules,
+            axiom: String::from(axiom),
+            seed,
+            trees: vec![String::from(axiom)],
+            rng: Pcg32::new(seed),
+        }
+    }
+
+    /// Whether this is the derivation of `axiom` with `rules` and `seed`.
+    pub fn is_for(&self, rules: &Rules, axiom: &str, seed: u64) -> bool {
+        self.rules == *rules && self.axiom == axiom && self.seed == seed
+    }
+
+    /// The expansions so far, starting with the axiom.
+    pub fn trees(&self) -> &[String] {
+        &self.trees
+    }
+
+    /// The expansion after `iterations`, expanding as far as needed.
+    ///
+    /// Stops with [`LilyError::ExpansionBudget`] once an expansion grows past
+    /// `limit` symbols, the expansions before it are kept.
+    pub fn expand(&mut self, iterations: usize, limit: usize) -> Result<&str> {
+        while self.trees.len() <= iterations {
+            let last = self.trees.last().expect("there's always the axiom");
+
+            // the generator only moves on with the expansions that are kept
+            let mut rng = self.rng.clone();
+
+            let Ok(tree) = (self.rules).apply_within(last, limit, &mut rng) else {
+                return Err(LilyError::ExpansionBudget {
+                    limit,
+                    iteration: self.trees.len(),
+                    partial: last.clone(),
+                });
+            };
+
+            self.trees.push(tree);
+            self.rng = rng;
+        }
+
+        // the limit may have been lowered since the expansion was kept
+        if iterations > 0 && self.trees[iterations].chars().count() > limit {
+            return Err(LilyError::ExpansionBudget {
+                limit,
+                iteration: iterations,
+                partial: self.trees[iterations - 1].clone(),
+            });
+        }
+
+        Ok(&self.trees[iterations])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_expansions() {
+        let rules = Rules::parse("A -> F[-A]+A\nF -> FF");
+        let expected: Vec<_> = rules.expansions("A").take(6).map(Result::unwrap).collect();
+
+        let mut derivation = Derivation::new(rules, "A", 0);
+
+        // asking for fewer iterations later only looks them up
+        derivation.expand(5, usize::MAX).unwrap();
+        derivation.expand(2, usize::MAX).unwrap();
+
+        assert_eq!(derivation.trees(), expected);
+    }
+
+    #[test]
+    fn continues_without_redoing() {
+        let mut derivation = Derivation::new(Rules::parse("F -> FF"), "F", 0);
+        derivation.expand(4, usize::MAX).unwrap();
+
+        let kept = derivation.trees()[4].as_ptr();
+        derivation.expand(8, usize::MAX).unwrap();
+
+        assert_eq!(derivation.trees()[4].as_ptr(), kept);
+        assert_eq!(derivation.trees()[8].len(), 256);
+    }
+
+    #[test]
+    fn is_for_its_definition_only() {
+        let rules = Rules::parse("F -> FF");
+        let derivation = Derivation::new(rules.clone(), "F", 1);
+
+        assert!(derivation.is_for(&rules, "F", 1));
+        assert!(derivation.is_for(&Rules::parse("F -> FF  # same rule"), "F", 1));
+        assert!(!derivation.is_for(&Rules::parse("F -> F+F"), "F", 1));
+        assert!(!derivation.is_for(&rules, "FF", 1));
+        assert!(!derivation.is_for(&rules, "F", 2));
+    }
+
+    #[test]
+    fn keeps_what_fit_the_limit() {
+        let mut derivation = Derivation::new(Rules::parse("F -> FF"), "F", 0);
+
+        let err = derivation.expand(6, 10).unwrap_err();
+        assert!(matches!(
+            err,
+            LilyError::ExpansionBudget { iteration: 4, .. }
+        ));
+        assert_eq!(derivation.trees().len(), 4);
+    }
+}
@@ -0,0 +1,225 @@
+//! Arithmetic in the parameters of parametric rules and instructions.
+//!
+//! Expressions have numbers, named parameters, `+ - * / ^`, negation and
+//! parentheses, with the usual precedence.
+
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+impl Operator {
+    fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Self::Add => a + b,
+            Self::Subtract => a - b,
+            Self::Multiply => a * b,
+            Self::Divide => a / b,
+            Self::Power => a.powf(b),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Add => f.write_str("+"),
+            Self::Subtract => f.write_str("-"),
+            Self::Multiply => f.write_str("*"),
+            Self::Divide => f.write_str("/"),
+            Self::Power => f.write_str("^"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Parameter(String),
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    /// Consumes characters while `f` holds, returning them.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.chars.peek().map_or(self.input.len(), |&(i, _)| i);
+
+        while self.chars.next_if(|&(_, c)| f(c)).is_some() {}
+
+        let end = self.chars.peek().map_or(self.input.len(), |&(i, _)| i);
+        &self.input[start..end]
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[(char, Operator)],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut expr = operand(self)?;
+
+        while let Some(&(_, operator)) =
+            (self.peek()).and_then(|c| operators.iter().find(|(symbol, _)| *symbol == c))
+        {
+            self.chars.next();
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(operand(self)?));
+        }
+
+        Ok(expr)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let operators = [('+', Operator::Add), ('-', Operator::Subtract)];
+        self.binary(&operators, Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let operators = [('*', Operator::Multiply), ('/', Operator::Divide)];
+        self.binary(&operators, Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(Expr::Negate(Box::new(self.unary()?)))
+            }
+            _ => self.power(),
+        }
+    }
+
+    /// Powers are right associative and bind tighter than negation on their
+    /// left, `-2^2` is -4.
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+
+        match self.peek() {
+            Some('^') => {
+                self.chars.next();
+                let exponent = self.unary()?;
+                Ok(Expr::Binary(
+                    Operator::Power,
+                    Box::new(base),
+                    Box::new(exponent),
+                ))
+            }
+            _ => Ok(base),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let expr = self.sum()?;
+
+                match self.peek() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(expr)
+                    }
+                    _ => Err(String::from("expected `)`")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+
+                (number.parse())
+                    .map(Expr::Number)
+                    .map_err(|_| format!("`{}` is not a number", number))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                Ok(Expr::Parameter(String::from(name)))
+            }
+            Some(c) => Err(format!("unexpected `{}`", c)),
+            None => Err(String::from("expected a number or a parameter")),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            input,
+            chars: input.char_indices().peekable(),
+        };
+
+        let expr = parser.sum()?;
+
+        match parser.peek() {
+            Some(c) => Err(format!("unexpected `{}`", c)),
+            None => Ok(expr),
+        }
+    }
+
+    /// Evaluates the expression, looking up parameters with `parameter`.
+    pub fn eval(&self, parameter: &impl Fn(&str) -> Option<f32>) -> Result<f32, String> {
+        match self {
+            Self::Number(number) => Ok(*number),
+            Self::Parameter(name) => {
+                parameter(name).ok_or_else(|| format!("unknown parameter `{}`", name))
+            }
+            Self::Negate(expr) => Ok(-expr.eval(parameter)?),
+            Self::Binary(operator, a, b) => {
+                Ok(operator.apply(a.eval(parameter)?, b.eval(parameter)?))
+            }
+        }
+    }
+
+    /// The names of the parameters in the expression, in the order they first
+    /// appear.
+    pub fn parameters(&self) -> Vec<&str> {
+        fn collect<'a>(expr: &'a Expr, names: &mut Vec<&'a str>) {
+            match expr {
+                Expr::Number(_) => {}
+                Expr::Parameter(name) => {
+                    if !names.contains(&name.as_str()) {
+                        names.push(name);
+                    }
+                }
+                Expr::Negate(expr) => collect(expr, names),
+                Expr::Binary(_, a, b) => {
+                    collect(a, names);
+                    collect(b, names);
+                }
+            }
+        }
+
+        let mut names = Vec::new();
+        collect(self, &mut names);
+        names
+    }
+}
+
+/// Writes the expression fully parenthesized, so it parses back the same.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Parameter(name) => f.write_str(name),
+            Self::Negate(expr) => write!(f, "-{}", expr),
+            Self::Binary(operator, a, b) => write!(f, "({} {} {})", a, operator, b),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Importing Fractint `.l` files.
+//!
+//! A file holds any number of named blocks:
+//!
+//! ```text
+//! Koch1 {            ; comments start with a semicolon
+//!   Angle 6          ; turns are a sixth of a circle
+//!   Axiom F--F--F
+//!   F=F+F--F+F
+//! }
+//! ```
+//!
+//! Symbols are case-insensitive, so axioms and rules are uppercased.
+
+use super::{SystemOptions, TurnDirection};
+use crate::{error::Result, project::Definition, LilyError};
+
+/// The iteration count given to imported systems, the format doesn't store one.
+pub const FRACTINT_ITERATIONS: usize = 3;
+
+/// A system imported from a file holding several.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NamedSystem {
+    pub name: String,
+    pub definition: Definition,
+}
+
+/// The part of a block that has been read so far.
+struct Block {
+    name: String,
+    line: usize,
+    angle: Option<f32>,
+    axiom: Option<String>,
+    rules: Vec<String>,
+}
+
+impl Block {
+    fn parse_command(&mut self, line: usize, command: &str) -> Result<()> {
+        let error = |reason: String| LilyError::Fractint { line, reason };
+
+        if let Some((symbol, replace)) = command.split_once('=') {
+            let mut symbol = symbol.trim().chars();
+
+            let (Some(symbol), None) = (symbol.next(), symbol.next()) else {
+                return Err(error(format!("invalid rule `{}`", command)));
+            };
+
+            let rule = format!("{} -> {}", symbol, normalize(replace));
+            self.rules.push(rule.to_uppercase());
+
+            return Ok(());
+        }
+
+        let mut parts = command.splitn(2, char::is_whitespace);
+        let keyword = parts.next().unwrap_or_default();
+        let argument = parts.next().unwrap_or_default().trim();
+
+        match keyword.to_ascii_lowercase().as_str() {
+            "angle" => match argument.parse::<f32>() {
+                Ok(divisions) if divisions > 0.0 => self.angle = Some(divisions),
+                _ => return Err(error(format!("invalid angle `{}`", argument))),
+            },
+            "axiom" => self.axiom = Some(normalize(argument).to_uppercase()),
+            _ => return Err(error(format!("unknown command `{}`", keyword))),
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<NamedSystem> {
+        let error = |reason: &str| LilyError::Fractint {
+            line: self.line,
+            reason: format!("`{}` {}", self.name, reason),
+        };
+
+        let axiom = self.axiom.clone().ok_or_else(|| error("has no axiom"))?;
+        let divisions = self.angle.ok_or_else(|| error("has no angle"))?;
+
+        Ok(NamedSystem {
+            name: self.name,
+            definition: Definition {
+                axiom,
+                rules: self.rules.join("\n"),
+                instructions: instructions(360.0 / divisions),
+                iterations: FRACTINT_ITERATIONS,
+                options: fractint_options(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Removes the whitespace Fractint allows inside axioms and productions.
+fn normalize(input: &str) -> String {
+    input.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// The options matching Fractint's coordinates.
+///
+/// Fractint turns counter-clockwise for `+` with y pointing up, so the plant
+/// still grows up the screen, which is toward lily's `-y`.
+fn fractint_options() -> SystemOptions {
+    SystemOptions {
+        turn_direction: TurnDirection::CounterClockwise,
+        ..Default::default()
+    }
+}
+
+/// The instructions for Fractint's drawing symbols, with Fractint's signs,
+/// see [`fractint_options`].
+fn instructions(angle: f32) -> String {
+    let lines = [
+        String::from("F = forward 1"),
+        String::from("D = forward 1"),
+        String::from("G = move 1"),
+        String::from("M = move 1"),
+        format!("+ = turn {}", angle),
+        format!("- = turn {}", -angle),
+        String::from("| = turn 180"),
+    ];
+
+    lines.join("\n")
+}
+
+/// Imports every system in a Fractint `.l` file.
+pub fn import_fractint(text: &str) -> Result<Vec<NamedSystem>> {
+    let mut systems = Vec::new();
+    let mut block: Option<Block> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+
+        // strip comments
+        let mut line = line.split(';').next().unwrap_or_default().trim();
+
+        if block.is_none() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((name, rest)) = line.split_once('{') else {
+                return Err(LilyError::Fractint {
+                    line: line_number,
+                    reason: format!("expected `name {{`, found `{}`", line),
+                });
+            };
+
+            block = Some(Block {
+                name: name.trim().to_string(),
+                line: line_number,
+                angle: None,
+                axiom: None,
+                rules: Vec::new(),
+            });
+
+            line = rest.trim();
+        }
+
+        let (command, closed) = match line.split_once('}') {
+            Some((command, _)) => (command.trim(), true),
+            None => (line, false),
+        };
+
+        if let Some(ref mut current) = block {
+            if !command.is_empty() {
+                current.parse_command(line_number, command)?;
+            }
+        }
+
+        if closed {
+            if let Some(finished) = block.take() {
+                systems.push(finished.finish()?);
+            }
+        }
+    }
+
+    if let Some(unclosed) = block {
+        return Err(LilyError::Fractint {
+            line: unclosed.line,
+            reason: format!("`{}` is missing a closing `}}`", unclosed.name),
+        });
+    }
+
+    Ok(systems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::Growth;
+
+    /// Two entries of Fractint's `fractint.l`, as they're distributed.
+    const FRACTINT_L: &str = "\
+Koch1 {   ; Adrian Mariano
+          ; from The Fractal Geometry of Nature by Mandelbrot
+  Angle 6
+  Axiom F--F--F
+  F=F+F--F+F
+  }
+
+Dragon {  ; Adrian Mariano
+  Angle 8
+  Axiom FX
+  F=
+  y=+FX--FY+
+  x=-FX++FY-
+  }
+";
+
+    fn derive(system: &NamedSystem, iterations: usize) -> String {
+        let definition = Definition {
+            iterations,
+            ..system.definition.clone()
+        };
+
+        definition.expand()
+    }
+
+    #[test]
+    fn imports_fractint_l() {
+        let systems = import_fractint(FRACTINT_L).unwrap();
+
+        let names: Vec<_> = systems.iter().map(|system| system.name.as_str()).collect();
+        assert_eq!(names, ["Koch1", "Dragon"]);
+
+        assert_eq!(derive(&systems[0], 1), "F+F--F+F--F+F--F+F--F+F--F+F");
+        assert_eq!(derive(&systems[1], 2), "--FX++FY-+++FX--FY+-");
+
+        // a sixth and an eighth of a circle
+        assert!(systems[0].definition.instructions.contains("+ = turn 60"));
+        assert!(systems[1].definition.instructions.contains("- = turn -45"));
+
+        // with Fractint's signs, turning the way Fractint does
+        for system in &systems {
+            let options = &system.definition.options;
+            assert_eq!(options.turn_direction, TurnDirection::CounterClockwise);
+            assert_eq!(options.growth, Growth::NegativeY);
+        }
+    }
+
+    #[test]
+    fn reports_the_line() {
+        let err = import_fractint("Bad {\n  Angle 0\n}").unwrap_err();
+        assert!(matches!(err, LilyError::Fractint { line: 2, .. }));
+
+        let err = import_fractint("Open {\n  Angle 4\n  Axiom F\n").unwrap_err();
+        assert!(matches!(err, LilyError::Fractint { line: 1, .. }));
+    }
+}
@@ -0,0 +1,315 @@
+use ori::prelude::*;
+
+use super::{
+    apply_instruction, generate_mesh3d, CustomRegistry, Dimensions, Instruction, MeshBackend,
+    Pcg32, Strip, SystemOptions, TurtleState,
+};
+
+/// How many instructions apart the turtle is checkpointed.
+const CHECKPOINT_INTERVAL: usize = 1024;
+
+/// Everything needed to continue generating from an instruction.
+#[derive(Clone, Debug)]
+struct Checkpoint {
+    index: usize,
+    stack: Vec<TurtleState>,
+    /// Picks the turn jitter of the instructions after the checkpoint.
+    rng: Pcg32,
+    strips: Vec<Strip>,
+    vertices: usize,
+    indices: usize,
+}
+
+/// The turtle partway through the instructions, see [`IncrementalMesh::probe`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TurtleProbe {
+    /// The number of instructions run.
+    pub index: usize,
+    /// The turtle on the current branch.
+    pub state: TurtleState,
+    /// The number of open branches, the trunk counts as one.
+    pub depth: usize,
+    /// The length of the start of the index buffer drawn so far.
+    pub indices: usize,
+}
+
+impl TurtleProbe {
+    /// The width the next segment would be drawn with.
+    pub fn width(&self) -> f32 {
+        self.state.width_at(self.depth)
+    }
+}
+
+/// A mesh that keeps what it can when its instructions change.
+///
+/// The turtle is checkpointed every [`CHECKPOINT_INTERVAL`] instructions, an
+/// update keeps the geometry before the first changed instruction and
+/// continues from the checkpoint before it. The result is the same as
+/// [`generate_mesh_with`](super::generate_mesh_with), as long as custom
+/// callbacks don't keep state of their own.
+#[derive(Default)]
+pub struct IncrementalMesh {
+    options: Option<SystemOptions>,
+    customs: CustomRegistry,
+    instructions: Vec<Instruction>,
+    backend: Option<MeshBackend>,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl IncrementalMesh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mesh of the last update.
+    pub fn mesh(&self) -> Option<&Mesh> {
+        self.backend.as_ref().map(|backend| &backend.mesh)
+    }
+
+    /// Regenerates the mesh for `instructions`, returning the number of
+    /// instructions whose geometry was kept.
+    pub fn update(
+        &mut self,
+        options: &SystemOptions,
+        instructions: &[Instruction],
+        customs: &CustomRegistry,
+    ) -> usize {
+        // tubes are sorted back to front, so they're generated all at once
+        // and there's nothing to probe
+        if options.dimensions == Dimensions::Three {
+            let mut backend = MeshBackend::new(options);
+            backend.mesh = generate_mesh3d(options, instructions, customs).project();
+
+            self.options = Some(options.clone());
+            self.customs = customs.clone();
+            self.instructions = instructions.to_vec();
+            self.backend = Some(backend);
+            self.checkpoints.clear();
+
+            return 0;
+        }
+
+        let reusable = self.options.as_ref() == Some(options) && self.customs == *customs;
+
+        let prefix = match reusable {
+            true => (self.instructions.iter())
+                .zip(instructions)
+                .take_while(|(old, new)| old == new)
+                .count(),
+            false => 0,
+        };
+
+        // a checkpoint at an instruction only covers the ones before it
+        let kept = self
+            .checkpoints
+            .iter()
+            .rposition(|checkpoint| checkpoint.index <= prefix);
+
+        let (mut backend, checkpoint) = match (reusable, self.backend.take(), kept) {
+            (true, Some(backend), Some(kept)) => {
+                self.checkpoints.truncate(kept + 1);
+                (backend, self.checkpoints[kept].clone())
+            }
+            _ => {
+                let backend = MeshBackend::new(options);
+                let checkpoint = Checkpoint {
+                    index: 0,
+                    stack: vec![TurtleState::new(options)],
+                    rng: Pcg32::new(options.jitter_seed),
+                    strips: backend.strips.clone(),
+                    vertices: backend.mesh.vertices.len(),
+                    indices: backend.mesh.indices.len(),
+                };
+
+                self.checkpoints = vec![checkpoint.clone()];
+                (backend, checkpoint)
+            }
+        };
+
+        backend.mesh.vertices.truncate(checkpoint.vertices);
+        backend.mesh.indices.truncate(checkpoint.indices);
+        backend.strips = checkpoint.strips;
+        let mut stack = checkpoint.stack;
+        let mut rng = checkpoint.rng;
+
+        for (i, instruction) in instructions.iter().enumerate().skip(checkpoint.index) {
+            if i > checkpoint.index && i % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoints.push(Checkpoint {
+                    index: i,
+                    stack: stack.clone(),
+                    rng: rng.clone(),
+                    strips: backend.strips.clone(),
+                    vertices: backend.mesh.vertices.len(),
+                    indices: backend.mesh.indices.len(),
+                });
+            }
+
+            apply_instruction(&mut backend, &mut stack, &mut rng, customs, instruction);
+        }
+
+        // the next update continues from a checkpoint, before the ends
+        backend.finish();
+
+        self.options = Some(options.clone());
+        self.customs = customs.clone();
+        self.instructions = instructions.to_vec();
+        self.backend = Some(backend);
+
+        checkpoint.index
+    }
+
+    /// The turtle after the first `index` instructions of the last update.
+    ///
+    /// This replays from the closest checkpoint, so it's quick at any index.
+    /// Meshes in [`Dimensions::Three`] have no checkpoints and can't be
+    /// probed.
+    pub fn probe(&self, index: usize) -> Option<TurtleProbe> {
+        let options = self.options.as_ref()?;
+        let index = usize::min(index, self.instructions.len());
+
+        let checkpoint = (self.checkpoints.iter())
+            .rev()
+            .find(|checkpoint| checkpoint.index <= index)?;
+
+        // only the number of indices added matters, the geometry is thrown away
+        let mut backend = MeshBackend::new(options);
+        backend.strips = checkpoint.strips.clone();
+
+        let mut stack = checkpoint.stack.clone();
+        let mut rng = checkpoint.rng.clone();
+
+        for instruction in &self.instructions[checkpoint.index..index] {
+            apply_instruction(
+                &mut backend,
+                &mut stack,
+                &mut rng,
+                &self.customs,
+                instruction,
+            );
+        }
+
+        Some(TurtleProbe {
+            index,
+            depth: stack.len(),
+            state: stack.pop()?,
+            indices: checkpoint.indices + backend.mesh.indices.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        presets::PRESETS,
+        system::{generate_mesh_with, tests::fingerprint},
+    };
+
+    fn plant() -> (SystemOptions, CustomRegistry, Vec<Instruction>) {
+        let preset = PRESETS
+            .iter()
+            .find(|preset| preset.name == "Plant")
+            .unwrap();
+
+        let mut definition = preset.definition();
+        definition.iterations = 5;
+        definition.options.turn_jitter = 5.0;
+
+        let instructions = definition.instructions();
+        let stream = instructions.apply(&definition.expand());
+        (definition.options, instructions.customs().clone(), stream)
+    }
+
+    /// Updates `mesh` and checks it against a full regeneration, returning
+    /// the number of instructions kept.
+    fn update(
+        mesh: &mut IncrementalMesh,
+        options: &SystemOptions,
+        customs: &CustomRegistry,
+        stream: &[Instruction],
+    ) -> usize {
+        let kept = mesh.update(options, stream, customs);
+        let full = generate_mesh_with(options, stream, customs);
+
+        assert_eq!(fingerprint(mesh.mesh().unwrap()), fingerprint(&full));
+        kept
+    }
+
+    #[test]
+    fn edits_match_full_regeneration() {
+        let (options, customs, stream) = plant();
+        assert!(stream.len() > 4 * CHECKPOINT_INTERVAL);
+
+        let mut mesh = IncrementalMesh::new();
+        assert_eq!(update(&mut mesh, &options, &customs, &stream), 0);
+
+        // nothing changed, everything up to the last checkpoint is kept
+        let kept = update(&mut mesh, &options, &customs, &stream);
+        assert_eq!(
+            kept,
+            stream.len() / CHECKPOINT_INTERVAL * CHECKPOINT_INTERVAL
+        );
+
+        // tweaking one angle near the end
+        let mut tweaked = stream.clone();
+        let turn = (tweaked.iter())
+            .rposition(|instruction| matches!(instruction, Instruction::Turn(_)))
+            .unwrap();
+        tweaked[turn] = Instruction::Turn(40.0);
+
+        let kept = update(&mut mesh, &options, &customs, &tweaked);
+        assert!(kept <= turn && turn - kept < CHECKPOINT_INTERVAL);
+
+        // appending to the stream, like a symbol added to the axiom
+        let mut appended = stream.clone();
+        appended.extend([Instruction::Turn(90.0), Instruction::Forward(20.0)]);
+
+        let kept = update(&mut mesh, &options, &customs, &appended);
+        assert!(kept > turn - CHECKPOINT_INTERVAL);
+
+        // cutting the stream short
+        let half = &stream[..stream.len() / 2];
+        let kept = update(&mut mesh, &options, &customs, half);
+        assert!(kept <= half.len() && half.len() - kept < CHECKPOINT_INTERVAL);
+
+        // and growing it back
+        let kept = update(&mut mesh, &options, &customs, &stream);
+        assert!(kept <= half.len() && half.len() - kept < CHECKPOINT_INTERVAL);
+
+        // an edit at the start falls back to a full regeneration
+        let mut first = stream.clone();
+        first[0] = Instruction::Forward(3.0);
+        assert_eq!(update(&mut mesh, &options, &customs, &first), 0);
+    }
+
+    #[test]
+    fn changed_options_regenerate_everything() {
+        let (options, customs, stream) = plant();
+
+        let mut mesh = IncrementalMesh::new();
+        update(&mut mesh, &options, &customs, &stream);
+
+        let wider = SystemOptions {
+            branch_width: 3.0,
+            ..options.clone()
+        };
+        assert_eq!(update(&mut mesh, &wider, &customs, &stream), 0);
+
+        let reseeded = SystemOptions {
+            jitter_seed: 7,
+            ..options.clone()
+        };
+        assert_eq!(update(&mut mesh, &reseeded, &customs, &stream), 0);
+
+        // tubes are always generated whole
+        let tubes = SystemOptions {
+            dimensions: Dimensions::Three,
+            ..options.clone()
+        };
+        assert_eq!(update(&mut mesh, &tubes, &customs, &stream), 0);
+        assert_eq!(update(&mut mesh, &tubes, &customs, &stream), 0);
+
+        // and leave nothing to continue from
+        assert_eq!(update(&mut mesh, &options, &customs, &stream), 0);
+    }
+}
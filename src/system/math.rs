@@ -0,0 +1,270 @@
+//! The turtle's math, in [`Scalar`] precision.
+//!
+//! Deep systems accumulate thousands of small moves and turns, which drifts
+//! visibly in `f32`. Enabling the `f64` feature tracks the turtle in `f64`,
+//! converting to `f32` only when geometry is emitted.
+
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+use ori::prelude::{Point, Vector};
+
+/// The precision the turtle is tracked in.
+#[cfg(not(feature = "f64"))]
+pub type Scalar = f32;
+
+/// The precision the turtle is tracked in.
+#[cfg(feature = "f64")]
+pub type Scalar = f64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: Scalar,
+    pub y: Scalar,
+}
+
+impl Vec2 {
+    pub const ZERO: Self = Self::new(0.0, 0.0);
+    pub const NEG_X: Self = Self::new(-1.0, 0.0);
+    pub const NEG_Y: Self = Self::new(0.0, -1.0);
+
+    pub const fn new(x: Scalar, y: Scalar) -> Self {
+        Self { x, y }
+    }
+
+    // `Scalar` is `f32` unless the `f64` feature is enabled
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_point(self) -> Point {
+        Point::new(self.x as f32, self.y as f32)
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_vector(self) -> Vector {
+        Vector::new(self.x as f32, self.y as f32)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+impl Mul<Scalar> for Vec2 {
+    type Output = Self;
+
+    fn mul(self, rhs: Scalar) -> Self {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+/// A point or direction in space, `z` points into the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: Scalar,
+    pub y: Scalar,
+    pub z: Scalar,
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self::new(0.0, 0.0, 0.0);
+    pub const X: Self = Self::new(1.0, 0.0, 0.0);
+    pub const Y: Self = Self::new(0.0, 1.0, 0.0);
+    pub const Z: Self = Self::new(0.0, 0.0, 1.0);
+    pub const NEG_X: Self = Self::new(-1.0, 0.0, 0.0);
+    pub const NEG_Y: Self = Self::new(0.0, -1.0, 0.0);
+
+    pub const fn new(x: Scalar, y: Scalar, z: Scalar) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Where the point is on the screen, leaving out its depth.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_point(self) -> Point {
+        Point::new(self.x as f32, self.y as f32)
+    }
+
+    /// The direction on the screen, leaving out its depth.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_vector(self) -> Vector {
+        Vector::new(self.x as f32, self.y as f32)
+    }
+
+    /// How far into the screen the point is.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn depth(self) -> f32 {
+        self.z as f32
+    }
+
+    pub fn dot(self, rhs: Self) -> Scalar {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn cross(self, rhs: Self) -> Self {
+        Self::new(
+            self.y * rhs.z - self.z * rhs.y,
+            self.z * rhs.x - self.x * rhs.z,
+            self.x * rhs.y - self.y * rhs.x,
+        )
+    }
+
+    pub fn length(self) -> Scalar {
+        self.dot(self).sqrt()
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Mul<Scalar> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Scalar) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+/// A rotation in space, the matrix taking the turtle's own axes to the
+/// world's, stored as its columns.
+///
+/// The turtle's heading is its `-y` axis and its left is its `-x` axis, so a
+/// turtle that only yaws stays in the plane of the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orientation {
+    pub x: Vec3,
+    pub y: Vec3,
+    pub z: Vec3,
+}
+
+impl Orientation {
+    pub const IDENTITY: Self = Self {
+        x: Vec3::X,
+        y: Vec3::Y,
+        z: Vec3::Z,
+    };
+
+    /// Turns about the turtle's `z` axis, in the plane of the screen when the
+    /// turtle hasn't pitched or rolled.
+    pub fn yaw(angle: Scalar) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+
+        Self {
+            x: Vec3::new(cos, sin, 0.0),
+            y: Vec3::new(-sin, cos, 0.0),
+            z: Vec3::Z,
+        }
+    }
+
+    /// Turns about the turtle's `x` axis, positive angles tip the heading out
+    /// of the screen.
+    pub fn pitch(angle: Scalar) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+
+        Self {
+            x: Vec3::X,
+            y: Vec3::new(0.0, cos, sin),
+            z: Vec3::new(0.0, -sin, cos),
+        }
+    }
+
+    /// Turns about the turtle's `y` axis, the one it's heading along.
+    pub fn roll(angle: Scalar) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+
+        Self {
+            x: Vec3::new(cos, 0.0, -sin),
+            y: Vec3::Y,
+            z: Vec3::new(sin, 0.0, cos),
+        }
+    }
+
+    /// Turns about `axis`, which must be a unit vector, counter-clockwise
+    /// looking down it.
+    pub fn around(axis: Vec3, angle: Scalar) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let rotate = |v: Vec3| v * cos + axis.cross(v) * sin + axis * (axis.dot(v) * (1.0 - cos));
+
+        Self {
+            x: rotate(Vec3::X),
+            y: rotate(Vec3::Y),
+            z: rotate(Vec3::Z),
+        }
+    }
+
+    /// The angle the heading is turned by on the screen, between -180 and
+    /// 180 degrees, see [`Orientation::yaw`].
+    pub fn heading_degrees(self) -> Scalar {
+        let heading = self * Vec3::NEG_Y;
+        heading.x.atan2(-heading.y).to_degrees()
+    }
+}
+
+impl Mul for Orientation {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            x: self * rhs.x,
+            y: self * rhs.y,
+            z: self * rhs.z,
+        }
+    }
+}
+
+impl Mul<Vec3> for Orientation {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+}
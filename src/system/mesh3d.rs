@@ -0,0 +1,188 @@
+//! Tubes in space, for systems that pitch and roll out of the screen.
+//!
+//! The painter only draws flat triangles, so the tubes are projected straight
+//! onto the screen, drawn back to front and shaded by how much they face the
+//! light.
+
+use ori::prelude::*;
+
+use super::{
+    interpret, CustomRegistry, Instruction, Polygon, Segment, SystemOptions, TurtleBackend,
+};
+
+/// The number of sides of the tube around a segment.
+const SIDES: usize = 6;
+
+/// The direction the light comes from, the top left, toward the viewer.
+const LIGHT: [f32; 3] = [-0.42, -0.52, -0.74];
+
+/// How bright the side facing away from the light still is.
+const AMBIENT: f32 = 0.35;
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt();
+    a.map(|c| c / length)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex3d {
+    /// Where the vertex is, `z` points into the screen.
+    pub position: [f32; 3],
+    /// The direction the tube faces at the vertex.
+    pub normal: [f32; 3],
+    pub color: Color,
+}
+
+/// A triangle mesh in space, one tube per [`Segment`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh3d {
+    pub vertices: Vec<Vertex3d>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh3d {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Projects the mesh onto the screen, leaving out the depth.
+    ///
+    /// The triangles furthest into the screen come first, so the painter
+    /// draws the nearer ones over them.
+    pub fn project(&self) -> Mesh {
+        let mut mesh = Mesh::new();
+
+        for vertex in self.vertices.iter() {
+            let light = f32::max(dot(vertex.normal, LIGHT.map(|c| -c)), 0.0);
+            let shade = AMBIENT + (1.0 - AMBIENT) * light;
+
+            let color = vertex.color;
+            let [x, y, _] = vertex.position;
+
+            mesh.vertices.push(Vertex {
+                position: Point::new(x, y),
+                tex_coords: Point::ZERO,
+                color: Color::rgba(color.r * shade, color.g * shade, color.b * shade, color.a),
+            });
+        }
+
+        let depth = |triangle: &[u32]| -> f32 {
+            (triangle.iter())
+                .map(|&index| self.vertices[index as usize].position[2])
+                .sum()
+        };
+
+        let mut triangles: Vec<_> = self.indices.chunks_exact(3).collect();
+        triangles.sort_by(|a, b| depth(b).total_cmp(&depth(a)));
+        mesh.indices = triangles.concat();
+
+        mesh
+    }
+}
+
+impl TurtleBackend for Mesh3d {
+    fn segment(&mut self, segment: &Segment) {
+        let from = [segment.from.x, segment.from.y, segment.depth[0]];
+        let to = [segment.to.x, segment.to.y, segment.depth[1]];
+
+        let direction = sub(to, from);
+        if dot(direction, direction) == 0.0 {
+            return;
+        }
+
+        // any two directions across the segment make a ring around it
+        let direction = normalize(direction);
+        let other = match direction[2].abs() < 0.9 {
+            true => [0.0, 0.0, 1.0],
+            false => [1.0, 0.0, 0.0],
+        };
+
+        let u = normalize(cross(direction, other));
+        let v = cross(direction, u);
+
+        let radius = segment.width / 2.0;
+        let first = self.vertices.len() as u32;
+
+        for side in 0..SIDES {
+            let angle = side as f32 / SIDES as f32 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+
+            let normal = [0, 1, 2].map(|i| u[i] * cos + v[i] * sin);
+
+            for end in [from, to] {
+                self.vertices.push(Vertex3d {
+                    position: [0, 1, 2].map(|i| end[i] + normal[i] * radius),
+                    normal,
+                    color: segment.color,
+                });
+            }
+        }
+
+        for side in 0..SIDES as u32 {
+            let next = (side + 1) % SIDES as u32;
+            let (a, b) = (first + side * 2, first + next * 2);
+
+            self.indices.extend([a, a + 1, b]);
+            self.indices.extend([a + 1, b + 1, b]);
+        }
+    }
+
+    fn polygon(&mut self, polygon: &Polygon) {
+        let corners: Vec<_> = (polygon.points.iter().zip(polygon.depth.iter()))
+            .map(|(point, &depth)| [point.x, point.y, depth])
+            .collect();
+
+        let [a, b, c] = [corners[0], corners[1], corners[2]];
+        let normal = cross(sub(b, a), sub(c, a));
+
+        // a leaf is lit from whichever side faces the light
+        let normal = match dot(normal, normal) > 0.0 {
+            true if dot(normal, LIGHT) > 0.0 => normalize(normal.map(|c| -c)),
+            true => normalize(normal),
+            false => LIGHT.map(|c| -c),
+        };
+
+        let first = self.vertices.len() as u32;
+
+        for position in corners {
+            self.vertices.push(Vertex3d {
+                position,
+                normal,
+                color: polygon.color,
+            });
+        }
+
+        // triangulated as seen on the screen, which is how it's drawn
+        let triangles = polygon.triangulate();
+        self.indices
+            .extend(triangles.iter().map(|index| first + index));
+    }
+}
+
+/// Like [`generate_mesh_with`](super::generate_mesh_with), with the branches
+/// as tubes in space.
+pub fn generate_mesh3d(
+    options: &SystemOptions,
+    instructions: &[Instruction],
+    customs: &CustomRegistry,
+) -> Mesh3d {
+    let mut mesh = Mesh3d::new();
+    interpret(options, instructions, customs, &mut mesh);
+    mesh
+}
@@ -0,0 +1,260 @@
+//! Parametric modules, symbols with arguments like `F(1.5)` or `A(2,0.5)`.
+//!
+//! Expansions can stay strings, with a module's arguments written right after
+//! its symbol, or be kept as a list of [`Module`]s. Parentheses that don't
+//! hold a parameter list are symbols like any other, so systems without
+//! parameters expand the same as before.
+
+use std::fmt;
+
+use super::expr::Expr;
+
+/// A symbol and its arguments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Module {
+    pub symbol: char,
+    pub arguments: Vec<f32>,
+}
+
+impl Module {
+    pub fn new(symbol: char) -> Self {
+        Self {
+            symbol,
+            arguments: Vec::new(),
+        }
+    }
+}
+
+/// Writes the module the way [`parse_modules`] reads it, `F(1.5)`.
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol)?;
+
+        if !self.arguments.is_empty() {
+            let arguments: Vec<_> = self.arguments.iter().map(f32::to_string).collect();
+            write!(f, "({})", arguments.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a `(…)` group with balanced parentheses off the start of `text`,
+/// returning what's inside and the length of the whole group.
+fn group(text: &str) -> Option<(&str, usize)> {
+    if !text.starts_with('(') {
+        return None;
+    }
+
+    let mut depth = 0usize;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some((&text[1..i], i + 1)),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Splits the inside of a group at the commas outside nested parentheses.
+fn split_arguments(inside: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+
+    for (i, c) in inside.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                arguments.push(&inside[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    arguments.push(&inside[start..]);
+    arguments
+}
+
+/// The arguments at the start of `text`, right after a symbol, and the length
+/// they take up.
+///
+/// Only lists of numbers are arguments, `(A)` is just symbols.
+pub fn arguments(text: &str) -> Option<(Vec<f32>, usize)> {
+    let (inside, len) = group(text)?;
+
+    let arguments = (split_arguments(inside).into_iter())
+        .map(|argument| argument.trim().parse().ok())
+        .collect::<Option<_>>()?;
+
+    Some((arguments, len))
+}
+
+/// The length of the module at the start of `text`, its symbol and arguments.
+pub fn module_len(text: &str) -> usize {
+    let Some(symbol) = text.chars().next() else {
+        return 0;
+    };
+
+    let rest = &text[symbol.len_utf8()..];
+    symbol.len_utf8() + arguments(rest).map_or(0, |(_, len)| len)
+}
+
+/// The modules of an expansion, each symbol with its arguments.
+pub fn modules(text: &str) -> impl Iterator<Item = Module> + '_ {
+    let mut rest = text;
+
+    std::iter::from_fn(move || {
+        let symbol = rest.chars().next()?;
+        rest = &rest[symbol.len_utf8()..];
+
+        match arguments(rest) {
+            Some((arguments, len)) => {
+                rest = &rest[len..];
+                Some(Module { symbol, arguments })
+            }
+            None => Some(Module::new(symbol)),
+        }
+    })
+}
+
+/// Reads an expansion into modules, see [`modules`].
+pub fn parse_modules(text: &str) -> Vec<Module> {
+    modules(text).collect()
+}
+
+/// Writes modules back out as an expansion.
+pub fn format_modules(modules: &[Module]) -> String {
+    modules.iter().map(Module::to_string).collect()
+}
+
+/// The symbols of `text`, leaving out every group after a symbol.
+///
+/// Unlike [`modules`] the groups don't have to be numbers, so this also reads
+/// replacements like `F(x*2)`.
+pub fn symbols(text: &str) -> impl Iterator<Item = char> + '_ {
+    let mut rest = text;
+
+    std::iter::from_fn(move || {
+        let symbol = rest.chars().next()?;
+        rest = &rest[symbol.len_utf8()..];
+
+        if let Some((_, len)) = group(rest) {
+            rest = &rest[len..];
+        }
+
+        Some(symbol)
+    })
+}
+
+/// Evaluates the groups after the symbols of `replace`, looking up parameters
+/// with `parameter`, so `F(x*2)` becomes `F(3)` when `x` is 1.5.
+///
+/// Groups that aren't expressions, or use unknown parameters, are kept as
+/// they're written.
+pub fn substitute(replace: &str, parameter: &impl Fn(&str) -> Option<f32>) -> String {
+    let mut output = String::with_capacity(replace.len());
+    let mut rest = replace;
+
+    while let Some(symbol) = rest.chars().next() {
+        output.push(symbol);
+        rest = &rest[symbol.len_utf8()..];
+
+        let Some((inside, len)) = group(rest) else {
+            continue;
+        };
+
+        let values: Option<Vec<_>> = (split_arguments(inside).into_iter())
+            .map(|argument| Expr::parse(argument).ok()?.eval(parameter).ok())
+            .collect();
+
+        match values {
+            Some(values) => {
+                let values: Vec<_> = values.iter().map(f32::to_string).collect();
+                output.push('(');
+                output.push_str(&values.join(","));
+                output.push(')');
+            }
+            None => output.push_str(&rest[..len]),
+        }
+
+        rest = &rest[len..];
+    }
+
+    output
+}
+
+enum Part {
+    Module(Module),
+    /// A symbol with a group of expressions, and the modules to write if they
+    /// can't be evaluated.
+    Parametric(char, Vec<Expr>, Vec<Module>),
+}
+
+/// A replacement read once, so the module path doesn't parse its expressions
+/// every time a rule matches.
+///
+/// Writes the same modules as reading back [`substitute`].
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+impl Replacement {
+    pub fn new(replace: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut rest = replace;
+
+        while let Some(symbol) = rest.chars().next() {
+            let start = rest;
+            rest = &rest[symbol.len_utf8()..];
+
+            let Some((inside, len)) = group(rest) else {
+                parts.push(Part::Module(Module::new(symbol)));
+                continue;
+            };
+
+            let exprs: Option<Vec<_>> = (split_arguments(inside).into_iter())
+                .map(|argument| Expr::parse(argument).ok())
+                .collect();
+
+            let literal = || parse_modules(&start[..symbol.len_utf8() + len]);
+
+            match exprs {
+                Some(exprs) => parts.push(Part::Parametric(symbol, exprs, literal())),
+                None => parts.extend(literal().into_iter().map(Part::Module)),
+            }
+
+            rest = &rest[len..];
+        }
+
+        Self { parts }
+    }
+
+    /// Writes the replacement to `output`, looking up parameters with
+    /// `parameter`.
+    pub fn write(&self, parameter: &impl Fn(&str) -> Option<f32>, output: &mut Vec<Module>) {
+        for part in &self.parts {
+            match part {
+                Part::Module(module) => output.push(module.clone()),
+                Part::Parametric(symbol, exprs, literal) => {
+                    let arguments: Option<Vec<_>> = (exprs.iter())
+                        .map(|expr| expr.eval(parameter).ok())
+                        .collect();
+
+                    match arguments {
+                        Some(arguments) => output.push(Module {
+                            symbol: *symbol,
+                            arguments,
+                        }),
+                        None => output.extend(literal.iter().cloned()),
+                    }
+                }
+            }
+        }
+    }
+}
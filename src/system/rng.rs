@@ -0,0 +1,119 @@
+//! The random number generator behind every stochastic feature.
+//!
+//! The algorithm is part of the project file format: a seed saved in a
+//! project must render the same system on every machine and in every
+//! version of lily, so the output sequence of [`Pcg32`] must never change.
+
+/// The PCG32 generator (PCG-XSH-RR with 64 bits of state), see
+/// <https://www.pcg-random.org>.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+    const DEFAULT_STREAM: u64 = 0xda3e39cb94b95bdb;
+
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, Self::DEFAULT_STREAM)
+    }
+
+    /// Creates a generator on one of 2^63 independent streams.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        let rotation = (state >> 59) as u32;
+
+        xorshifted.rotate_right(rotation)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let high = self.next_u32() as u64;
+        let low = self.next_u32() as u64;
+
+        (high << 32) | low
+    }
+
+    /// Returns a float in `[0, 1)`, using the top 24 bits of one output.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Returns a float in `[min, max)`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_stream() {
+        // the sequence from the reference implementation's demo, seeded with
+        // 42 on stream 54
+        let mut rng = Pcg32::with_stream(42, 54);
+        let outputs: Vec<_> = (0..6).map(|_| rng.next_u32()).collect();
+
+        assert_eq!(
+            outputs,
+            [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]
+        );
+    }
+
+    // seeds saved in projects use the default stream, these pin it so a
+    // change to the generator can't slip in unnoticed
+
+    #[test]
+    fn default_stream_u32() {
+        let mut rng = Pcg32::new(0);
+        let outputs: Vec<_> = (0..4).map(|_| rng.next_u32()).collect();
+
+        assert_eq!(outputs, [0x0a65ce7d, 0x97a1773e, 0xc03f123a, 0xf1654d25]);
+    }
+
+    #[test]
+    fn default_stream_u64() {
+        let mut rng = Pcg32::new(12345);
+        let outputs: Vec<_> = (0..4).map(|_| rng.next_u64()).collect();
+
+        assert_eq!(
+            outputs,
+            [
+                0x8630b53a16ac2a2c,
+                0xbade2d7fd2f5adf8,
+                0xb84deedd9836aa9e,
+                0x87ae6fbc04ce78fc
+            ]
+        );
+    }
+
+    #[test]
+    fn next_u64_is_two_u32s() {
+        let (mut a, mut b) = (Pcg32::new(7), Pcg32::new(7));
+        let high = b.next_u32() as u64;
+        let low = b.next_u32() as u64;
+
+        assert_eq!(a.next_u64(), (high << 32) | low);
+    }
+}
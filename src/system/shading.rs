@@ -0,0 +1,80 @@
+use ori::prelude::*;
+
+use super::mesh_bounds;
+
+/// Darkens the mesh where it's dense, a cheap stand-in for ambient occlusion
+/// inside canopies, see [`shade_density`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DensityShading {
+    /// How much the densest parts are darkened, 0 leaves the colors untouched
+    /// and 1 makes them black.
+    pub strength: f32,
+    /// The number of grid cells along the longest side of the mesh.
+    pub cells: usize,
+}
+
+impl Default for DensityShading {
+    fn default() -> Self {
+        Self {
+            strength: 0.0,
+            cells: 64,
+        }
+    }
+}
+
+/// Darkens vertex colors by how many vertices are around them.
+///
+/// Vertices are counted in a coarse grid, each cell is compared to the
+/// densest one, counting its neighbours too so the shading doesn't step at
+/// cell borders. This is linear in the number of vertices and cells.
+pub fn shade_density(mesh: &mut Mesh, shading: &DensityShading) {
+    if shading.strength == 0.0 || shading.cells == 0 || mesh.vertices.is_empty() {
+        return;
+    }
+
+    let bounds = mesh_bounds(mesh);
+    let extent = f32::max(bounds.width(), bounds.height()).max(f32::EPSILON);
+    let cell_size = extent / shading.cells as f32;
+
+    let columns = ((bounds.width() / cell_size).ceil() as usize).max(1);
+    let rows = ((bounds.height() / cell_size).ceil() as usize).max(1);
+
+    let cell = |position: Point| {
+        let x = ((position.x - bounds.min.x) / cell_size) as usize;
+        let y = ((position.y - bounds.min.y) / cell_size) as usize;
+        (x.min(columns - 1), y.min(rows - 1))
+    };
+
+    let mut counts = vec![0u32; columns * rows];
+    for vertex in mesh.vertices.iter() {
+        let (x, y) = cell(vertex.position);
+        counts[y * columns + x] += 1;
+    }
+
+    // the density of a cell includes its neighbours
+    let mut density = vec![0u32; columns * rows];
+    for y in 0..rows {
+        for x in 0..columns {
+            let mut sum = 0;
+
+            for ny in y.saturating_sub(1)..usize::min(y + 2, rows) {
+                for nx in x.saturating_sub(1)..usize::min(x + 2, columns) {
+                    sum += counts[ny * columns + nx];
+                }
+            }
+
+            density[y * columns + x] = sum;
+        }
+    }
+
+    let max = density.iter().copied().max().unwrap_or(0).max(1) as f32;
+
+    for vertex in mesh.vertices.iter_mut() {
+        let (x, y) = cell(vertex.position);
+        let shade = 1.0 - shading.strength * density[y * columns + x] as f32 / max;
+
+        vertex.color.r *= shade;
+        vertex.color.g *= shade;
+        vertex.color.b *= shade;
+    }
+}
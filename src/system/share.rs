@@ -0,0 +1,194 @@
+//! Share codes, a whole project as a single copy-pasteable token.
+//!
+//! A code is a version byte followed by the deflated, minified JSON of the
+//! project, encoded as unpadded base64url. Fields left at their defaults are
+//! left out of the JSON.
+
+use std::io::{Read, Write};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde_json::Value;
+
+use crate::{
+    error::Result,
+    project::{Project, ProjectFormat},
+    LilyError,
+};
+
+/// The version of the share code encoding, not of the project inside it.
+const SHARE_VERSION: u8 = 1;
+
+pub fn encode_share(project: &Project) -> String {
+    let serialize = |project| serde_json::to_value(project).expect("projects always serialize");
+
+    // every field has a default, so the ones left at it can be left out
+    let mut value = serialize(project);
+    strip_defaults(&mut value, &serialize(&Project::default()));
+    value["version"] = project.version.into();
+
+    let json = serde_json::to_vec(&value).expect("values always serialize");
+
+    let mut encoder = DeflateEncoder::new(vec![SHARE_VERSION], Compression::best());
+    encoder
+        .write_all(&json)
+        .expect("writing to a vec can't fail");
+    let bytes = encoder.finish().expect("writing to a vec can't fail");
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Removes the fields of `value` that are equal to the ones in `default`.
+fn strip_defaults(value: &mut Value, default: &Value) {
+    let (Value::Object(fields), Value::Object(defaults)) = (value, default) else {
+        return;
+    };
+
+    fields.retain(|key, field| match defaults.get(key) {
+        Some(default) if field == default => false,
+        Some(default) => {
+            strip_defaults(field, default);
+            true
+        }
+        None => true,
+    });
+}
+
+pub fn decode_share(code: &str) -> Result<Project> {
+    let invalid = |reason: &str| LilyError::ShareCode(String::from(reason));
+
+    let bytes = URL_SAFE_NO_PAD
+        .decode(code.trim())
+        .map_err(|_| invalid("not a share code"))?;
+
+    let Some((&version, compressed)) = bytes.split_first() else {
+        return Err(invalid("empty share code"));
+    };
+
+    if version != SHARE_VERSION {
+        return Err(LilyError::ShareCode(format!(
+            "unsupported share code version {}",
+            version
+        )));
+    }
+
+    let mut json = String::new();
+    DeflateDecoder::new(compressed)
+        .read_to_string(&mut json)
+        .map_err(|_| invalid("corrupted share code"))?;
+
+    Project::parse(&json, ProjectFormat::Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        presets::PRESETS,
+        project::{Definition, Snapshot, ViewOptions},
+        system::{
+            parse_color, Dimensions, FitMode, Growth, JoinStyle, SystemOptions, TurnDirection,
+        },
+    };
+
+    /// A project with every field off its default.
+    fn everything() -> Project {
+        let color = |hex| parse_color(hex).unwrap();
+
+        let options = SystemOptions {
+            branch_color: color("#8a5a3c"),
+            branch_width: 4.5,
+            width_falloff: 0.75,
+            leaf_falloff: 0.8,
+            min_width: 0.25,
+            taper: 0.9,
+            trunk_color: color("#3b2a1a"),
+            tip_color: color("#a3d97780"),
+            max_depth: 5,
+            palette: vec![color("#ff0000"), color("#00ff00")],
+            leaf_color: color("#dd6c81"),
+            turn_jitter: 2.5,
+            jitter_seed: u64::MAX,
+            tropism: [0.5, 1.0, -0.25],
+            tropism_strength: 0.1,
+            turn_direction: TurnDirection::CounterClockwise,
+            growth: Growth::PositiveY,
+            dimensions: Dimensions::Three,
+            join_style: JoinStyle::Round,
+        };
+
+        let definition = Definition {
+            axiom: String::from("X"),
+            rules: String::from("X -> F[+X]F[-X]+X # comment\nF -> FF"),
+            instructions: String::from("F = forward 1\n+ = turn 20\n- = turn -20"),
+            iterations: 6,
+            seed: 1 << 63,
+            options,
+        };
+
+        Project {
+            snapshots: vec![Snapshot {
+                name: String::from("ünïcode"),
+                definition: Definition {
+                    iterations: 2,
+                    ..definition.clone()
+                },
+            }],
+            view: ViewOptions {
+                zoom: 2.5,
+                pan: [-10.0, 4.5],
+                fit: FitMode::Center,
+                max_iterations: 20,
+            },
+            definition,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_every_field() {
+        let project = everything();
+        let code = encode_share(&project);
+
+        assert!(code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(decode_share(&code).unwrap(), project);
+
+        let project = Project::default();
+        assert_eq!(decode_share(&encode_share(&project)).unwrap(), project);
+
+        // pasting often brings whitespace along
+        let code = encode_share(&everything());
+        assert_eq!(
+            decode_share(&format!("  {}\n", code)).unwrap(),
+            everything()
+        );
+    }
+
+    #[test]
+    fn presets_make_short_codes() {
+        for preset in PRESETS {
+            let code = encode_share(&Project::new(preset.definition()));
+            assert!(code.len() < 300, "{} is {} long", preset.name, code.len());
+        }
+    }
+
+    #[test]
+    fn rejects_other_codes() {
+        assert!(matches!(
+            decode_share("not a code!"),
+            Err(LilyError::ShareCode(_))
+        ));
+        assert!(matches!(decode_share(""), Err(LilyError::ShareCode(_))));
+
+        // a code of a newer encoding
+        let mut bytes = URL_SAFE_NO_PAD
+            .decode(encode_share(&Project::default()))
+            .unwrap();
+        bytes[0] = SHARE_VERSION + 1;
+
+        let err = decode_share(&URL_SAFE_NO_PAD.encode(bytes)).unwrap_err();
+        assert!(err.to_string().contains("version 2"));
+    }
+}
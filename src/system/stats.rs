@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use ori::prelude::*;
+use serde::Serialize;
+
+use super::{interpret, CustomRegistry, Instruction, Segment, SystemOptions, TurtleBackend};
+
+/// Measurements of a generated system, see [`generation_stats`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct GenerationStats {
+    /// The length of the expanded string.
+    pub symbols: usize,
+    /// The number of instructions the string turned into.
+    pub instructions: usize,
+    pub segments: usize,
+    /// The number of branches pushed.
+    pub branches: usize,
+    /// The deepest the branch stack got.
+    pub max_depth: usize,
+    /// The total length of every segment.
+    pub length: f32,
+    /// The size of the drawing in turtle units, without stroke widths.
+    pub width: f32,
+    pub height: f32,
+    /// Only known when the mesh is generated.
+    pub vertices: Option<usize>,
+    pub triangles: Option<usize>,
+}
+
+impl GenerationStats {
+    /// Fills in the mesh counts.
+    pub fn with_mesh(mut self, mesh: &Mesh) -> Self {
+        self.vertices = Some(mesh.vertices.len());
+        self.triangles = Some(mesh.indices.len() / 3);
+        self
+    }
+}
+
+/// Counts what the turtle draws without building any geometry.
+#[derive(Default)]
+struct StatsBackend {
+    stats: GenerationStats,
+    depth: usize,
+    bounds: Option<Rect>,
+}
+
+impl TurtleBackend for StatsBackend {
+    fn segment(&mut self, segment: &Segment) {
+        self.stats.segments += 1;
+        self.stats.length += (segment.to - segment.from).length();
+
+        let bounds = self
+            .bounds
+            .get_or_insert(Rect::new(segment.from, segment.from));
+
+        for point in [segment.from, segment.to] {
+            bounds.min = Point::min(bounds.min, point);
+            bounds.max = Point::max(bounds.max, point);
+        }
+    }
+
+    fn begin_branch(&mut self) {
+        self.depth += 1;
+        self.stats.branches += 1;
+        self.stats.max_depth = usize::max(self.stats.max_depth, self.depth);
+    }
+
+    fn end_branch(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
+
+/// Measures the system `tree` expanded to, which `instructions` came from.
+///
+/// This runs the turtle but skips the mesh, which is the slow part, see
+/// [`GenerationStats::with_mesh`] to include it.
+pub fn generation_stats(
+    options: &SystemOptions,
+    tree: &str,
+    instructions: &[Instruction],
+    customs: &CustomRegistry,
+) -> GenerationStats {
+    let mut backend = StatsBackend::default();
+    interpret(options, instructions, customs, &mut backend);
+
+    let mut stats = backend.stats;
+    stats.symbols = tree.chars().count();
+    stats.instructions = instructions.len();
+
+    if let Some(bounds) = backend.bounds {
+        stats.width = bounds.width();
+        stats.height = bounds.height();
+    }
+
+    stats
+}
+
+/// How many times each symbol occurs in `tree`, not counting the arguments
+/// of modules.
+pub fn symbol_frequencies(tree: &str) -> BTreeMap<char, usize> {
+    let mut frequencies = BTreeMap::new();
+
+    for c in super::symbols(tree) {
+        *frequencies.entry(c).or_default() += 1;
+    }
+
+    frequencies
+}
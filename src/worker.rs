@@ -0,0 +1,567 @@
+//! Expanding the system and generating its mesh away from the UI thread.
+//!
+//! When a job is done the worker sends [`JobFinished`] through the proxy it
+//! was given, so the app picks the mesh up without waiting for input. On the
+//! web there are no threads, a job is run as soon as it's started.
+//!
+//! Contact sheets are rendered next to the jobs, see [`SheetJob`], edits
+//! don't cancel them, only [`Worker::cancel_sheet`] does.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use lily::{
+    error::Result,
+    export::{self, ContactSheetOptions},
+    project::Definition,
+    system::{self, Derivation, IncrementalMesh, Instruction, Instructions, SystemOptions},
+    LilyError,
+};
+
+use crate::Stage;
+
+/// Everything a generation needs, from the first dirty stage on.
+pub struct Job {
+    /// The first stage to run, [`Stage::Mesh`] at the latest.
+    pub stage: Stage,
+    /// The expansions so far, which the job continues.
+    pub derivation: Derivation,
+    pub iterations: usize,
+    pub symbol_limit: usize,
+    pub instructions: Instructions,
+    pub options: SystemOptions,
+    /// The expansion, when the job starts after [`Stage::Expand`].
+    pub tree: String,
+    /// The instructions, when the job starts at [`Stage::Mesh`].
+    pub stream: Vec<Instruction>,
+    /// Generates the mesh, the mesh of the job is left in it.
+    pub generator: IncrementalMesh,
+}
+
+/// The command sent through the worker's proxy when a job is done.
+pub struct JobFinished;
+
+/// A job that ran, with what it got to.
+pub struct Finished {
+    pub job: Job,
+    /// Why the job didn't get to a new mesh, the last mesh is kept.
+    ///
+    /// That's [`LilyError::ExpansionBudget`] or [`LilyError::Brackets`], or
+    /// [`LilyError::Cancelled`] when [`Worker::cancel`] stopped it.
+    pub problem: Option<LilyError>,
+    /// How long the job took, `None` on the web, which has no clock to time
+    /// it with.
+    pub elapsed: Option<Duration>,
+}
+
+/// A contact sheet of snapshots, rendered into a PNG.
+pub struct SheetJob {
+    /// The label and the definition of each cell, in order.
+    pub cells: Vec<(String, Definition)>,
+    pub options: ContactSheetOptions,
+    pub symbol_limit: usize,
+}
+
+/// The command sent through the worker's proxy whenever a cell of a contact
+/// sheet is done, and when the sheet is.
+pub struct SheetProgress;
+
+/// Renders the sheet of `job`, calling `progress` with the number of cells
+/// done after each one.
+///
+/// Stops with [`LilyError::Cancelled`] between cells once `cancelled` is set.
+fn render_sheet(
+    job: SheetJob,
+    cancelled: &AtomicBool,
+    progress: impl Fn(usize),
+) -> Result<Vec<u8>> {
+    let check = || match cancelled.load(Ordering::Relaxed) {
+        true => Err(LilyError::Cancelled),
+        false => Ok(()),
+    };
+
+    let mut cells = Vec::with_capacity(job.cells.len());
+
+    for (name, definition) in job.cells {
+        check()?;
+
+        let stream = definition.try_interpret(job.symbol_limit)?;
+        let mesh = system::generate_mesh(&definition.options, &stream);
+        cells.push((name, mesh, definition.options.growth));
+
+        progress(cells.len());
+    }
+
+    check()?;
+
+    let mut png = Vec::new();
+    export::contact_sheet_png(&cells, &job.options, &mut png)?;
+    Ok(png)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run(mut job: Job, cancelled: &AtomicBool) -> Finished {
+    let start = std::time::Instant::now();
+    let problem = run_stages(&mut job, cancelled).err();
+
+    Finished {
+        job,
+        problem,
+        elapsed: Some(start.elapsed()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn run(mut job: Job, cancelled: &AtomicBool) -> Finished {
+    let problem = run_stages(&mut job, cancelled).err();
+
+    Finished {
+        job,
+        problem,
+        elapsed: None,
+    }
+}
+
+fn run_stages(job: &mut Job, cancelled: &AtomicBool) -> Result<()> {
+    let check = || match cancelled.load(Ordering::Relaxed) {
+        true => Err(LilyError::Cancelled),
+        false => Ok(()),
+    };
+
+    if job.stage <= Stage::Expand {
+        // the iterations that were expanded before are only looked up
+        let expanded = job.derivation.trees().len();
+
+        for iteration in usize::min(expanded, job.iterations)..=job.iterations {
+            check()?;
+
+            job.derivation.expand(iteration, job.symbol_limit)?;
+        }
+
+        job.tree = job.derivation.trees()[job.iterations].clone();
+    }
+
+    if job.stage <= Stage::Interpret {
+        check()?;
+
+        // an unmatched bracket would draw something confusing
+        job.instructions.validate_brackets(&job.tree)?;
+        job.stream = job.instructions.apply(&job.tree);
+    }
+
+    check()?;
+    let customs = job.instructions.customs();
+    job.generator.update(&job.options, &job.stream, customs);
+
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc::{self, Receiver, TryRecvError},
+            Arc,
+        },
+        thread,
+    };
+
+    use lily::{error::Result, LilyError};
+    use ori::prelude::*;
+
+    use super::{Finished, Job, JobFinished, SheetJob, SheetProgress};
+
+    struct Running {
+        cancelled: Arc<AtomicBool>,
+        receiver: Receiver<Finished>,
+    }
+
+    struct RunningSheet {
+        cancelled: Arc<AtomicBool>,
+        done: Arc<AtomicUsize>,
+        total: usize,
+        receiver: Receiver<Result<Vec<u8>>>,
+    }
+
+    /// Runs one job at a time on a thread of its own, and a contact sheet
+    /// on another.
+    #[derive(Default)]
+    pub struct Worker {
+        running: Option<Running>,
+        sheet: Option<RunningSheet>,
+        /// Wakes the app when a job is done, see [`Worker::set_proxy`].
+        proxy: Option<CommandProxy>,
+    }
+
+    impl Worker {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Whether the worker has no way to wake the app yet.
+        pub fn needs_proxy(&self) -> bool {
+            self.proxy.is_none()
+        }
+
+        /// Sends [`JobFinished`] through `proxy` whenever a job is done, and
+        /// [`SheetProgress`] as a contact sheet gets along, what's already
+        /// running keeps going quietly.
+        pub fn set_proxy(&mut self, proxy: CommandProxy) {
+            self.proxy = Some(proxy);
+        }
+
+        /// Starts `job`, cancelling the one running, whose result is thrown
+        /// away.
+        pub fn start(&mut self, job: Job) {
+            if let Some(running) = self.running.take() {
+                running.cancelled.store(true, Ordering::Relaxed);
+            }
+
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let (sender, receiver) = mpsc::channel();
+
+            let flag = Arc::clone(&cancelled);
+            let proxy = self.proxy.clone();
+
+            thread::spawn(move || {
+                // the worker may have moved on, then nobody's listening
+                if sender.send(super::run(job, &flag)).is_ok() {
+                    if let Some(proxy) = proxy {
+                        proxy.cmd(JobFinished);
+                    }
+                }
+            });
+
+            self.running = Some(Running {
+                cancelled,
+                receiver,
+            });
+        }
+
+        pub fn is_busy(&self) -> bool {
+            self.running.is_some()
+        }
+
+        /// Asks the running job to stop, it still finishes, with
+        /// [`LilyError::Cancelled`](lily::LilyError::Cancelled), so what it
+        /// was given can be picked up again.
+        pub fn cancel(&self) {
+            if let Some(running) = self.running.as_ref() {
+                running.cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+
+        /// The job that's running, once it's finished, without waiting for
+        /// it.
+        pub fn finished(&mut self) -> Option<Finished> {
+            let running = self.running.as_ref()?;
+
+            match running.receiver.try_recv() {
+                Ok(finished) => {
+                    self.running = None;
+                    Some(finished)
+                }
+                Err(TryRecvError::Empty) => None,
+                // the job panicked
+                Err(TryRecvError::Disconnected) => {
+                    self.running = None;
+                    None
+                }
+            }
+        }
+
+        /// Starts rendering a contact sheet, unless one is being rendered
+        /// already, returning whether it was started.
+        pub fn start_sheet(&mut self, job: SheetJob) -> bool {
+            if self.sheet.is_some() {
+                return false;
+            }
+
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let done = Arc::new(AtomicUsize::new(0));
+            let total = job.cells.len();
+            let (sender, receiver) = mpsc::channel();
+
+            let flag = Arc::clone(&cancelled);
+            let counter = Arc::clone(&done);
+            let proxy = self.proxy.clone();
+
+            thread::spawn(move || {
+                let wake = || {
+                    if let Some(ref proxy) = proxy {
+                        proxy.cmd(SheetProgress);
+                    }
+                };
+
+                let png = super::render_sheet(job, &flag, |cells| {
+                    counter.store(cells, Ordering::Relaxed);
+                    wake();
+                });
+
+                if sender.send(png).is_ok() {
+                    wake();
+                }
+            });
+
+            self.sheet = Some(RunningSheet {
+                cancelled,
+                done,
+                total,
+                receiver,
+            });
+
+            true
+        }
+
+        /// Asks the contact sheet being rendered to stop, it finishes with
+        /// [`LilyError::Cancelled`] after the cell it's on.
+        pub fn cancel_sheet(&self) {
+            if let Some(sheet) = self.sheet.as_ref() {
+                sheet.cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+
+        /// How many cells of the contact sheet being rendered are done, out
+        /// of how many.
+        pub fn sheet_progress(&self) -> Option<(usize, usize)> {
+            let sheet = self.sheet.as_ref()?;
+            Some((sheet.done.load(Ordering::Relaxed), sheet.total))
+        }
+
+        /// The contact sheet being rendered, once it's done, without waiting
+        /// for it.
+        pub fn sheet_finished(&mut self) -> Option<Result<Vec<u8>>> {
+            let sheet = self.sheet.as_ref()?;
+
+            match sheet.receiver.try_recv() {
+                Ok(png) => {
+                    self.sheet = None;
+                    Some(png)
+                }
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    self.sheet = None;
+                    Some(Err(LilyError::Export(String::from(
+                        "rendering the contact sheet failed",
+                    ))))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod native {
+    use std::sync::atomic::AtomicBool;
+
+    use lily::error::Result;
+    use ori::prelude::*;
+
+    use super::{Finished, Job, SheetJob};
+
+    /// Runs jobs as they're started.
+    #[derive(Default)]
+    pub struct Worker {
+        finished: Option<Finished>,
+        sheet: Option<Result<Vec<u8>>>,
+    }
+
+    impl Worker {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Jobs are done by the time they're started, there's nothing to
+        /// wake the app for.
+        pub fn needs_proxy(&self) -> bool {
+            false
+        }
+
+        pub fn set_proxy(&mut self, _proxy: CommandProxy) {}
+
+        pub fn start(&mut self, job: Job) {
+            self.finished = Some(super::run(job, &AtomicBool::new(false)));
+        }
+
+        pub fn is_busy(&self) -> bool {
+            false
+        }
+
+        pub fn cancel(&self) {}
+
+        pub fn finished(&mut self) -> Option<Finished> {
+            self.finished.take()
+        }
+
+        pub fn start_sheet(&mut self, job: SheetJob) -> bool {
+            self.sheet = Some(super::render_sheet(job, &AtomicBool::new(false), |_| {}));
+            true
+        }
+
+        pub fn cancel_sheet(&self) {}
+
+        pub fn sheet_progress(&self) -> Option<(usize, usize)> {
+            None
+        }
+
+        pub fn sheet_finished(&mut self) -> Option<Result<Vec<u8>>> {
+            self.sheet.take()
+        }
+    }
+}
+
+pub use native::*;
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use lily::system::Rules;
+
+    use super::*;
+
+    fn job(derivation: Derivation, iterations: usize) -> Job {
+        Job {
+            stage: Stage::Expand,
+            derivation,
+            iterations,
+            symbol_limit: usize::MAX,
+            instructions: Instructions::parse("F = forward 1"),
+            options: SystemOptions::default(),
+            tree: String::new(),
+            stream: Vec::new(),
+            generator: IncrementalMesh::new(),
+        }
+    }
+
+    fn wait(worker: &mut Worker) -> Finished {
+        loop {
+            if let Some(finished) = worker.finished() {
+                return finished;
+            }
+
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn cancelled_jobs_hand_back_their_expansions() {
+        let mut derivation = Derivation::new(Rules::parse("F -> FF"), "F", 0);
+        derivation.expand(4, usize::MAX).unwrap();
+
+        let mut worker = Worker::new();
+        worker.start(job(derivation, 20));
+        worker.cancel();
+
+        let finished = wait(&mut worker);
+        assert!(matches!(
+            finished.problem,
+            Some(LilyError::Cancelled) | None
+        ));
+
+        // the expansions from before the job, and any it got to, are kept
+        assert!(finished.job.derivation.trees().len() >= 5);
+        assert_eq!(finished.job.derivation.trees()[4].len(), 16);
+    }
+
+    fn sheet(cells: usize) -> SheetJob {
+        let definition = Definition {
+            axiom: String::from("F"),
+            rules: String::from("F -> F+F"),
+            instructions: String::from("F = forward 1\n+ = turn 60"),
+            iterations: 3,
+            ..Default::default()
+        };
+
+        SheetJob {
+            cells: (0..cells)
+                .map(|i| (i.to_string(), definition.clone()))
+                .collect(),
+            options: ContactSheetOptions {
+                cell_size: 32.0,
+                ..Default::default()
+            },
+            symbol_limit: usize::MAX,
+        }
+    }
+
+    #[test]
+    fn sheets_render_next_to_jobs() {
+        let mut worker = Worker::new();
+        assert!(worker.start_sheet(sheet(3)));
+
+        // a second sheet waits for the first
+        assert!(!worker.start_sheet(sheet(1)));
+
+        let png = loop {
+            let (done, total) = worker.sheet_progress().unwrap();
+            assert!(done <= total && total == 3);
+
+            if let Some(png) = worker.sheet_finished() {
+                break png.unwrap();
+            }
+
+            std::thread::yield_now();
+        };
+
+        assert!(png.starts_with(b"\x89PNG"));
+        assert_eq!(worker.sheet_progress(), None);
+    }
+
+    #[test]
+    fn sheets_stop_at_the_symbol_limit() {
+        let mut worker = Worker::new();
+        worker.start_sheet(SheetJob {
+            symbol_limit: 4,
+            ..sheet(2)
+        });
+
+        let result = loop {
+            if let Some(result) = worker.sheet_finished() {
+                break result;
+            }
+
+            std::thread::yield_now();
+        };
+
+        assert!(matches!(result, Err(LilyError::ExpansionBudget { .. })));
+    }
+
+    #[test]
+    fn cancelled_sheets_stop_between_cells() {
+        let cancelled = AtomicBool::new(false);
+        let done = std::cell::Cell::new(0);
+
+        let result = render_sheet(sheet(5), &cancelled, |cells| {
+            done.set(cells);
+
+            if cells == 2 {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        });
+
+        assert!(matches!(result, Err(LilyError::Cancelled)));
+        assert_eq!(done.get(), 2);
+    }
+
+    #[test]
+    fn sheets_are_cancelled_through_the_worker() {
+        let mut worker = Worker::new();
+        worker.start_sheet(sheet(100));
+        worker.cancel_sheet();
+
+        let result = loop {
+            if let Some(result) = worker.sheet_finished() {
+                break result;
+            }
+
+            std::thread::yield_now();
+        };
+
+        assert!(matches!(result, Err(LilyError::Cancelled)));
+
+        // another sheet can be started right away
+        assert!(worker.start_sheet(sheet(1)));
+    }
+}
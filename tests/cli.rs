@@ -0,0 +1,88 @@
+//! Drives the `lily` binary, reading definitions from stdin and writing to
+//! stdout with `-`.
+
+use assert_cmd::Command;
+
+fn lily() -> Command {
+    Command::cargo_bin("lily").unwrap()
+}
+
+#[test]
+fn expands_plain_text_from_stdin() {
+    lily()
+        .args(["expand", "-n", "4", "-"])
+        .write_stdin("axiom: A\n\nA -> AB\nB -> A\n")
+        .assert()
+        .success()
+        .stdout("ABAABABA\n");
+}
+
+#[test]
+fn renders_projects_from_stdin_to_stdout() {
+    let project = r#"{
+        "version": 1,
+        "axiom": "F",
+        "rules": "F -> F-F++F-F",
+        "instructions": "F = forward 1\n+ = turn 60\n- = turn -60",
+        "iterations": 2
+    }"#;
+
+    let assert = lily()
+        .args(["render", "--format", "svg", "-", "-o", "-"])
+        .write_stdin(project)
+        .assert()
+        .success();
+
+    // a line for every `F` after two iterations
+    let svg = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert!(svg.starts_with("<svg"));
+    assert_eq!(svg.matches("<line").count(), 16);
+}
+
+#[cfg(unix)]
+#[test]
+fn refuses_binary_formats_on_a_terminal() {
+    use std::{
+        io::Write,
+        os::fd::{FromRawFd, OwnedFd},
+        process::Stdio,
+        ptr,
+    };
+
+    use assert_cmd::cargo::CommandCargoExt;
+
+    let (mut leader, mut follower) = (0, 0);
+    let opened = unsafe {
+        libc::openpty(
+            &mut leader,
+            &mut follower,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    assert_eq!(opened, 0);
+
+    // both ends are closed when they're dropped
+    let (_leader, follower) =
+        unsafe { (OwnedFd::from_raw_fd(leader), OwnedFd::from_raw_fd(follower)) };
+
+    let mut child = std::process::Command::cargo_bin("lily")
+        .unwrap()
+        .args(["render", "--format", "png", "-", "-o", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(follower))
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"axiom: F\n\nF = forward 1\n").unwrap();
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("refusing to write a binary format to a terminal"));
+}